@@ -0,0 +1,374 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Derive macros for [`kdlite`](https://docs.rs/kdlite)'s `FromNode` and
+//! `ToNode`, use them through the `derive` feature of `kdlite` instead of
+//! depending on this crate directly.
+//!
+//! Both work on structs with named fields. Every field needs a `#[kdl(...)]`
+//! attribute saying where it is in the node:
+//! - `arg`: the next argument, in field order
+//! - `arguments`: all arguments after the `arg` fields, as a collection
+//! - `property`: the property with the field name
+//! - `child`: the only child with the field name, as a node
+//! - `children`: the children as a collection, all of them unless the field
+//!   is renamed
+//! - `type_name`: the node's type annotation
+//! - `node_name`: the node's name
+//!
+//! Names can be changed with `rename = "name"`. `Option` fields can be
+//! missing or `#null`, and `None` arguments are written as `#null`. Other
+//! fields can be given a default for when they're missing with `default`
+//! (for [`Default`]) or `default = "path::to::function"`.
+//! Other arguments, properties, and children are ignored.
+//!
+//! Unlike the others, `children` doesn't look for the field name: without
+//! `rename` it takes every child whatever its name, and children written by
+//! `ToNode` keep their own names. With `rename = "route"` it only takes the
+//! `route` children, and writes every element as `route`.
+//!
+//! The struct itself can have `#[kdl(name = "name")]`, the node name used by
+//! `ToNode` when there's no `node_name` field (`-` otherwise).
+//!
+//! ```
+//! use kdlite::convert::{FromNode, ToNode};
+//! use kdlite::dom::Document;
+//!
+//! #[derive(FromNode, ToNode)]
+//! #[kdl(name = "server")]
+//! struct Server<'a> {
+//!   #[kdl(arg)]
+//!   host: &'a str,
+//!   #[kdl(property, default = "default_port")]
+//!   port: u16,
+//!   #[kdl(children, rename = "route")]
+//!   routes: Vec<Route>,
+//! }
+//! #[derive(FromNode, ToNode)]
+//! struct Route {
+//!   #[kdl(arg)]
+//!   path: String,
+//!   #[kdl(type_name)]
+//!   method: Option<String>,
+//! }
+//! fn default_port() -> u16 {
+//!   80
+//! }
+//!
+//! let document = Document::parse("server localhost { (get)route \"/\"; route \"/api\" }").unwrap();
+//! let server = Server::from_node(&document.nodes[0]).unwrap();
+//! assert_eq!((server.host, server.port, server.routes.len()), ("localhost", 80, 2));
+//! assert_eq!(server.routes[0].method.as_deref(), Some("get"));
+//! assert_eq!(
+//!   server.to_node().to_string(),
+//!   "server localhost port=80 {\n    (get)route \"/\"\n    route \"/api\"\n}"
+//! );
+//! ```
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, GenericParam, Ident, LitStr, Path, Type};
+
+/// Derive `kdlite::convert::FromNode`, see the [crate docs](crate)
+#[proc_macro_derive(FromNode, attributes(kdl))]
+pub fn derive_from_node(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let input = syn::parse_macro_input!(input as DeriveInput);
+  from_node(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derive `kdlite::convert::ToNode`, see the [crate docs](crate)
+#[proc_macro_derive(ToNode, attributes(kdl))]
+pub fn derive_to_node(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let input = syn::parse_macro_input!(input as DeriveInput);
+  to_node(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+  /// with its index
+  Arg(usize),
+  Arguments,
+  Property,
+  Child,
+  Children,
+  TypeName,
+  NodeName,
+}
+
+enum Default {
+  Trait,
+  Function(Path),
+}
+
+struct Field<'a> {
+  ident: &'a Ident,
+  ty: &'a Type,
+  kind: Kind,
+  rename: Option<String>,
+  default: Option<Default>,
+}
+
+impl Field<'_> {
+  fn key(&self) -> String {
+    match &self.rename {
+      Some(name) => name.clone(),
+      None => self.ident.to_string().trim_start_matches("r#").to_owned(),
+    }
+  }
+}
+
+/// The name from `#[kdl(name = "...")]` on the struct
+fn container_name(input: &DeriveInput) -> syn::Result<Option<String>> {
+  let mut name = None;
+  for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("kdl")) {
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("name") {
+        name = Some(meta.value()?.parse::<LitStr>()?.value());
+        Ok(())
+      } else {
+        Err(meta.error("unknown kdl attribute, expected `name`"))
+      }
+    })?;
+  }
+  Ok(name)
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<Field<'_>>> {
+  let fields = match &input.data {
+    Data::Struct(data) => match &data.fields {
+      Fields::Named(fields) => &fields.named,
+      _ => return Err(Error::new_spanned(&data.fields, "expected named fields")),
+    },
+    _ => return Err(Error::new(Span::call_site(), "expected a struct")),
+  };
+  let mut args = 0;
+  let mut parsed = Vec::new();
+  for field in fields {
+    let ident = field.ident.as_ref().unwrap();
+    let mut kind = None;
+    let mut rename = None;
+    let mut default = None;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("kdl")) {
+      attr.parse_nested_meta(|meta| {
+        let path = &meta.path;
+        let new_kind = if path.is_ident("arg") {
+          args += 1;
+          Kind::Arg(args - 1)
+        } else if path.is_ident("arguments") {
+          Kind::Arguments
+        } else if path.is_ident("property") {
+          Kind::Property
+        } else if path.is_ident("child") {
+          Kind::Child
+        } else if path.is_ident("children") {
+          Kind::Children
+        } else if path.is_ident("type_name") {
+          Kind::TypeName
+        } else if path.is_ident("node_name") {
+          Kind::NodeName
+        } else if path.is_ident("rename") {
+          rename = Some(meta.value()?.parse::<LitStr>()?.value());
+          return Ok(());
+        } else if path.is_ident("default") {
+          default = Some(match meta.input.is_empty() || meta.input.peek(syn::Token![,]) {
+            true => Default::Trait,
+            false => Default::Function(meta.value()?.parse::<LitStr>()?.parse()?),
+          });
+          return Ok(());
+        } else {
+          return Err(meta.error(
+            "unknown kdl attribute, expected one of `arg`, `arguments`, `property`, `child`, `children`, \
+            `type_name`, `node_name`, `rename`, or `default`",
+          ));
+        };
+        match kind.replace(new_kind) {
+          Some(_) => Err(meta.error("field already has a place in the node")),
+          None => Ok(()),
+        }
+      })?;
+    }
+    let kind = kind.ok_or_else(|| {
+      Error::new(
+        ident.span(),
+        "field needs a place in the node, like `#[kdl(arg)]`, `#[kdl(property)]`, or `#[kdl(child)]`",
+      )
+    })?;
+    parsed.push(Field {
+      ident,
+      ty: &field.ty,
+      kind,
+      rename,
+      default,
+    });
+  }
+  Ok(parsed)
+}
+
+/// `T` for `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+  let Type::Path(path) = ty else { return None };
+  let segment = path.path.segments.last()?;
+  let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+    return None;
+  };
+  match (segment.ident == "Option", arguments.args.first()) {
+    (true, Some(syn::GenericArgument::Type(inner))) if arguments.args.len() == 1 => Some(inner),
+    _ => None,
+  }
+}
+
+fn from_node(input: &DeriveInput) -> syn::Result<TokenStream> {
+  let fields = fields(input)?;
+  let args = fields.iter().filter(|field| matches!(field.kind, Kind::Arg(_))).count();
+  let convert = quote!(::kdlite::convert);
+  let mut values = Vec::new();
+  for field in &fields {
+    let ident = field.ident;
+    let key = field.key();
+    let (ty, inner) = (field.ty, option_inner(field.ty));
+    let what = match field.kind {
+      Kind::Arguments => {
+        values.push(quote!(#ident: #convert::arguments(node, #args)?));
+        continue;
+      }
+      Kind::Children => {
+        let name = match &field.rename {
+          Some(name) => quote!(::core::option::Option::Some(#name)),
+          None => quote!(::core::option::Option::None),
+        };
+        values.push(quote!(#ident: #convert::children(node, #name)?));
+        continue;
+      }
+      Kind::NodeName => {
+        values.push(quote!(#ident: ::core::convert::Into::into(node.name())));
+        continue;
+      }
+      Kind::Arg(index) => format!("argument {index}"),
+      Kind::Property => format!("property `{key}`"),
+      Kind::Child => format!("child `{key}`"),
+      Kind::TypeName => "type annotation".to_owned(),
+    };
+    // looks up an `Option<ty>`
+    let lookup = |ty: &Type| match field.kind {
+      Kind::Arg(index) => quote!(#convert::argument::<#ty>(node, #index)?),
+      Kind::Property => quote!(#convert::property::<#ty>(node, #key)?),
+      Kind::Child => quote!(#convert::child::<#ty>(node, #key)?),
+      _ => quote!(node.type_hint().map(::core::convert::Into::<#ty>::into)),
+    };
+    let value = match (&field.default, inner) {
+      (Some(default), _) => {
+        let default = match default {
+          Default::Trait => quote!(::core::default::Default::default()),
+          Default::Function(path) => quote!(#path()),
+        };
+        let lookup = lookup(ty);
+        quote!(match #lookup {
+          ::core::option::Option::Some(value) => value,
+          ::core::option::Option::None => #default,
+        })
+      }
+      // through `FromValue for Option<T>`, so `#null` is `None` too
+      (None, Some(_)) if matches!(field.kind, Kind::Arg(_) | Kind::Property) => {
+        let lookup = lookup(ty);
+        quote!(#lookup.flatten())
+      }
+      (None, Some(inner)) => lookup(inner),
+      (None, None) => {
+        let lookup = lookup(ty);
+        quote!(match #lookup {
+          ::core::option::Option::Some(value) => value,
+          ::core::option::Option::None => return ::core::result::Result::Err(#convert::missing(node, #what)),
+        })
+      }
+    };
+    values.push(quote!(#ident: #value));
+  }
+  // borrow from the node for the struct's lifetime, if it has one
+  let lifetime = input.generics.lifetimes().next().map(|param| param.lifetime.clone());
+  let mut generics = input.generics.clone();
+  let lifetime = lifetime.unwrap_or_else(|| {
+    let lifetime = syn::Lifetime::new("'__node", Span::call_site());
+    generics
+      .params
+      .insert(0, GenericParam::Lifetime(syn::LifetimeParam::new(lifetime.clone())));
+    lifetime
+  });
+  let (impl_generics, _, _) = generics.split_for_impl();
+  let (_, type_generics, where_clause) = input.generics.split_for_impl();
+  let name = &input.ident;
+  Ok(quote! {
+    impl #impl_generics #convert::FromNode<#lifetime> for #name #type_generics #where_clause {
+      fn from_node(node: &#lifetime ::kdlite::dom::Node<'_>) -> ::core::result::Result<Self, #convert::Error> {
+        ::core::result::Result::Ok(Self { #(#values,)* })
+      }
+    }
+  })
+}
+
+fn to_node(input: &DeriveInput) -> syn::Result<TokenStream> {
+  let fields = fields(input)?;
+  let convert = quote!(::kdlite::convert);
+  let dom = quote!(::kdlite::dom);
+  let node_name = container_name(input)?.unwrap_or_else(|| "-".to_owned());
+  // in node order, whatever the field order is
+  let order = |field: &Field| match field.kind {
+    Kind::NodeName | Kind::TypeName => 0,
+    Kind::Arg(_) => 1,
+    Kind::Arguments => 2,
+    Kind::Property => 3,
+    Kind::Child | Kind::Children => 4,
+  };
+  let mut sorted: Vec<_> = fields.iter().collect();
+  sorted.sort_by_key(|field| order(field));
+  let mut statements = Vec::new();
+  for field in sorted {
+    let ident = field.ident;
+    let key = field.key();
+    let value = quote!(value);
+    let push = match field.kind {
+      Kind::NodeName => quote!(node.set_name(::core::convert::AsRef::<str>::as_ref(#value))),
+      Kind::TypeName => quote!(node.set_type_hint(::core::option::Option::Some(
+        ::core::convert::AsRef::<str>::as_ref(#value)
+      ))),
+      Kind::Arg(_) => quote!(node.entries.push(#dom::Entry::new_value(#convert::ToValue::to_value(#value)))),
+      Kind::Arguments => quote!(for #value in #value {
+        node.entries.push(#dom::Entry::new_value(#convert::ToValue::to_value(#value)));
+      }),
+      Kind::Property => quote!(node.entries.push(#dom::Entry::new_prop(#key, #convert::ToValue::to_value(#value)))),
+      Kind::Child => quote!({
+        let mut child = #convert::ToNode::to_node(#value);
+        child.set_name(#key);
+        children.push(child);
+      }),
+      Kind::Children => {
+        let rename = field.rename.as_ref().map(|name| quote!(child.set_name(#name);));
+        quote!(for #value in #value {
+          #[allow(unused_mut)]
+          let mut child = #convert::ToNode::to_node(#value);
+          #rename
+          children.push(child);
+        })
+      }
+    };
+    // `None` arguments are `#null` so the ones after them keep their place
+    let optional =
+      option_inner(field.ty).is_some() && !matches!(field.kind, Kind::Arg(_) | Kind::Arguments | Kind::Children);
+    statements.push(match optional {
+      true => quote!(if let ::core::option::Option::Some(#value) = &self.#ident { #push; }),
+      false => quote!({ let #value = &self.#ident; #push; }),
+    });
+  }
+  let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+  let name = &input.ident;
+  Ok(quote! {
+    impl #impl_generics #convert::ToNode for #name #type_generics #where_clause {
+      fn to_node(&self) -> #dom::Node<'_> {
+        let mut node = #dom::Node::new(#node_name);
+        let mut children = ::std::vec::Vec::new();
+        #(#statements)*
+        if !children.is_empty() {
+          node.children = ::core::option::Option::Some(#dom::Document { nodes: children });
+        }
+        node
+      }
+    }
+  })
+}
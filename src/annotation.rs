@@ -0,0 +1,1248 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Type annotations reserved by the spec
+//!
+//! KDL [reserves] annotations like `(u8)`, `(date)`, or `(base64)` for values
+//! of those types, but the parser keeps every annotation as a plain string.
+//! [`validate`] checks the annotated values of a document, and
+//! [`validate_events`] does the same for a parser as it goes:
+//!
+//! ```
+//! use kdlite::annotation;
+//! use kdlite::dom::Document;
+//!
+//! let document = Document::parse(r#"dhcp lease=(duration)PT12H { server (ipv4)"10.0.0.300" (u8)300 }"#).unwrap();
+//! let errors = annotation::validate(&document);
+//! assert_eq!(errors.len(), 2);
+//! assert_eq!(
+//!   errors[0].to_string(),
+//!   "dhcp > server.0: invalid value: string \"10.0.0.300\", expected an IPv4 address"
+//! );
+//! assert_eq!(errors[1].to_string(), "dhcp > server.1: invalid value: integer `300`, expected u8");
+//! ```
+//!
+//! Annotations that aren't reserved are never errors, but you can register
+//! [`Handlers`] to check or convert your own. Values with the
+//! string annotations also convert to types that decode them, see
+//! [`convert`](crate::convert):
+//!
+//! |Annotation|Type|
+//! |:-|:-|
+//! |`date-time`|[`DateTime`]|
+//! |`date`|[`Date`]|
+//! |`time`|[`Time`]|
+//! |`duration`|[`Duration`]|
+//! |`uuid`|[`Uuid`]|
+//! |`ipv4`|[`Ipv4Addr`]|
+//! |`ipv6`|[`Ipv6Addr`]|
+//! |`url`, `url-reference`, `irl`, `irl-reference`|[`Url`]|
+//! |`base64`|[`Base64`]|
+//!
+//! ```
+//! use kdlite::annotation::{Base64, Date, Duration};
+//! use kdlite::dom::Document;
+//!
+//! let document = Document::parse(r#"backup since=(date)"2024-02-29" every=(duration)P1W key=(base64)a2Rs"#).unwrap();
+//! let backup = &document.nodes[0];
+//! let since = backup.get::<Date>("since").unwrap();
+//! assert_eq!((since.year, since.month, since.day), (2024, 2, 29));
+//! assert_eq!(backup.get::<Duration>("every").unwrap().weeks, 1);
+//! assert_eq!(backup.get::<Base64>("key").unwrap().0, b"kdl");
+//! ```
+//!
+//! Checks are syntactic: dates have to exist, but country and currency
+//! codes only need to look like ones, and regular expressions only need
+//! balanced groups and sensible quantifiers.
+//!
+//! [reserves]: https://github.com/kdl-org/kdl/blob/2.0.0/SPEC.md#reserved-type-annotations-for-strings
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+pub use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::convert::{
+  child_segment, entry_span, invalid_type, invalid_value, node_span, Error, FromValue, Segment, ToValue,
+};
+use crate::dom::{Document, Entry, Value};
+use crate::stream::{self, Event};
+
+macro_rules! reserved {
+  ($($variant:ident $name:literal $expected:literal,)*) => {
+    /// A type annotation reserved by the spec
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[non_exhaustive]
+    pub enum Reserved {
+      $(
+        #[doc = concat!("`(", $name, ")`, ", $expected)]
+        $variant,
+      )*
+    }
+
+    impl Reserved {
+      /// The reserved annotation with a name, if there is one
+      pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+          $($name => Some(Self::$variant),)*
+          _ => None,
+        }
+      }
+      /// The annotation's name, like `date-time`
+      pub fn name(self) -> &'static str {
+        match self {
+          $(Self::$variant => $name,)*
+        }
+      }
+      /// What the annotation expects, for error messages
+      fn expected(self) -> &'static str {
+        match self {
+          $(Self::$variant => $expected,)*
+        }
+      }
+    }
+  };
+}
+
+reserved! {
+  I8 "i8" "i8",
+  I16 "i16" "i16",
+  I32 "i32" "i32",
+  I64 "i64" "i64",
+  I128 "i128" "i128",
+  U8 "u8" "u8",
+  U16 "u16" "u16",
+  U32 "u32" "u32",
+  U64 "u64" "u64",
+  U128 "u128" "u128",
+  Isize "isize" "isize",
+  Usize "usize" "usize",
+  F32 "f32" "f32",
+  F64 "f64" "f64",
+  Decimal64 "decimal64" "a number",
+  Decimal128 "decimal128" "a number",
+  DateTime "date-time" "a date-time",
+  Time "time" "a time",
+  Date "date" "a date",
+  Duration "duration" "a duration",
+  Decimal "decimal" "a decimal number",
+  Currency "currency" "a currency code",
+  Country2 "country-2" "a 2-letter country code",
+  Country3 "country-3" "a 3-letter country code",
+  CountrySubdivision "country-subdivision" "a country subdivision code",
+  Email "email" "an email address",
+  IdnEmail "idn-email" "an email address",
+  Hostname "hostname" "a hostname",
+  IdnHostname "idn-hostname" "a hostname",
+  Ipv4 "ipv4" "an IPv4 address",
+  Ipv6 "ipv6" "an IPv6 address",
+  Url "url" "a URL",
+  UrlReference "url-reference" "a URL reference",
+  Irl "irl" "an IRL",
+  IrlReference "irl-reference" "an IRL reference",
+  UrlTemplate "url-template" "a URL template",
+  Uuid "uuid" "a UUID",
+  Regex "regex" "a regular expression",
+  Base64 "base64" "base64",
+}
+
+impl Reserved {
+  /// Check that a value fits the annotation
+  pub fn check(self, value: &Value) -> Result<(), Error> {
+    let expected = self.expected();
+    let text = match self {
+      Self::I8 => return i8::from_value(value).map(drop),
+      Self::I16 => return i16::from_value(value).map(drop),
+      Self::I32 => return i32::from_value(value).map(drop),
+      Self::I64 => return i64::from_value(value).map(drop),
+      Self::I128 => return i128::from_value(value).map(drop),
+      Self::U8 => return u8::from_value(value).map(drop),
+      Self::U16 => return u16::from_value(value).map(drop),
+      Self::U32 => return u32::from_value(value).map(drop),
+      Self::U64 => return u64::from_value(value).map(drop),
+      Self::U128 => return u128::from_value(value).map(drop),
+      Self::Isize => return isize::from_value(value).map(drop),
+      Self::Usize => return usize::from_value(value).map(drop),
+      Self::F32 => return f32::from_value(value).map(drop),
+      Self::F64 => return f64::from_value(value).map(drop),
+      Self::Decimal64 | Self::Decimal128 => {
+        return match value {
+          Value::Integer(_) | Value::BigInteger(_) | Value::Float(_) => Ok(()),
+          _ => Err(invalid_type(value, expected)),
+        }
+      }
+      _ => value.as_str().ok_or_else(|| invalid_type(value, expected))?,
+    };
+    let valid = match self {
+      Self::DateTime => date_time(text).is_some(),
+      Self::Time => time(text).is_some(),
+      Self::Date => date(text).is_some(),
+      Self::Duration => duration(text).is_some(),
+      Self::Decimal => decimal(text),
+      Self::Currency | Self::Country3 => upper(text, 3),
+      Self::Country2 => upper(text, 2),
+      Self::CountrySubdivision => text.split_once('-').is_some_and(|(country, subdivision)| {
+        upper(country, 2)
+          && (1..=3).contains(&subdivision.len())
+          && subdivision
+            .bytes()
+            .all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit())
+      }),
+      Self::Email => email(text, false),
+      Self::IdnEmail => email(text, true),
+      Self::Hostname => hostname(text, false),
+      Self::IdnHostname => hostname(text, true),
+      Self::Ipv4 => text.parse::<Ipv4Addr>().is_ok(),
+      Self::Ipv6 => text.parse::<Ipv6Addr>().is_ok(),
+      Self::Url => url(text, false, false).is_some(),
+      Self::UrlReference => url(text, false, true).is_some(),
+      Self::Irl => url(text, true, false).is_some(),
+      Self::IrlReference => url(text, true, true).is_some(),
+      Self::UrlTemplate => url_template(text),
+      Self::Uuid => uuid(text).is_some(),
+      Self::Regex => regex(text),
+      Self::Base64 => base64(text).is_some(),
+      _ => unreachable!("numbers are checked above"),
+    };
+    if valid {
+      Ok(())
+    } else {
+      Err(invalid_value(value, expected))
+    }
+  }
+}
+
+impl fmt::Display for Reserved {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+/// Check the value of an entry against its annotation, if it's reserved
+pub fn check(entry: &Entry) -> Result<(), Error> {
+  match entry.type_hint().and_then(Reserved::from_name) {
+    Some(reserved) => reserved.check(&entry.value),
+    None => Ok(()),
+  }
+}
+
+/// Check every entry with a reserved annotation in a document
+///
+/// Errors have the path to their entry, and a span if the document was
+/// parsed with them.
+pub fn validate(document: &Document) -> Vec<Error> {
+  let mut errors = Vec::new();
+  for (index, node) in document.nodes.iter().enumerate() {
+    let mut node_errors = Vec::new();
+    let mut arguments = 0;
+    for entry in &node.entries {
+      let segment = entry_segment(entry.key(), &mut arguments);
+      if let Err(error) = check(entry) {
+        node_errors.push(error.at(segment, entry_span(entry)));
+      }
+    }
+    if let Some(children) = &node.children {
+      node_errors.extend(validate(children));
+    }
+    let segment = child_segment(&document.nodes, index);
+    errors.extend(
+      node_errors
+        .into_iter()
+        .map(|error| error.at(segment.clone(), node_span(node))),
+    );
+  }
+  errors
+}
+
+/// Check entries with reserved annotations in parser events as they go by
+///
+/// An entry that doesn't fit its annotation is replaced by an error with
+/// its path, without indices among nodes of the same name. Parse errors are
+/// passed along.
+///
+/// ```
+/// use kdlite::annotation;
+/// use kdlite::stream::Parser;
+///
+/// let mut events = annotation::validate_events(Parser::new(r#"retry (u8)3 delay=(duration)"5s""#));
+/// assert!(events.by_ref().take(2).all(|event| event.is_ok()));
+/// let error = events.next().unwrap().unwrap_err();
+/// assert_eq!(error.to_string(), "retry.delay: invalid value: string \"5s\", expected a duration");
+/// ```
+pub fn validate_events<'text, I>(events: I) -> Validate<I::IntoIter>
+where
+  I: IntoIterator<Item = Result<Event<'text>, stream::Error>>,
+{
+  Validate {
+    events: events.into_iter(),
+    path: Path::default(),
+  }
+}
+
+/// An [`Iterator`] of events with checked annotations, see [`validate_events`]
+pub struct Validate<I> {
+  events: I,
+  path: Path,
+}
+
+impl<'text, I: Iterator<Item = Result<Event<'text>, stream::Error>>> Iterator for Validate<I> {
+  type Item = Result<Event<'text>, Error>;
+  fn next(&mut self) -> Option<Self::Item> {
+    let event = match self.events.next()? {
+      Ok(event) => event,
+      Err(error) => return Some(Err(error.into())),
+    };
+    let segment = self.path.follow(&event);
+    if let (Some(segment), Event::Entry { r#type, value, .. }) = (segment, &event) {
+      if let Some(reserved) = r#type.as_deref().and_then(Reserved::from_name) {
+        if let Err(error) = reserved.check(value) {
+          return Some(Err(self.path.error(error, segment)));
+        }
+      }
+    }
+    Some(Ok(event))
+  }
+}
+
+/// The path of an entry, counting arguments as they go by
+fn entry_segment(key: Option<&str>, arguments: &mut usize) -> Segment {
+  match key {
+    Some(key) => Segment::Property(key.to_owned()),
+    None => {
+      *arguments += 1;
+      Segment::Argument(*arguments - 1)
+    }
+  }
+}
+
+/// Where parser events are up to, for errors
+#[derive(Default)]
+struct Path {
+  // names of the nodes with open children blocks, outermost first
+  parents: Vec<String>,
+  node: String,
+  arguments: usize,
+}
+
+impl Path {
+  /// Move past an event, with the path segment if it's an entry
+  fn follow(&mut self, event: &Event) -> Option<Segment> {
+    match event {
+      Event::Node { name, .. } => {
+        self.node = name.to_string();
+        self.arguments = 0;
+      }
+      Event::Entry { key, .. } => return Some(entry_segment(key.as_deref(), &mut self.arguments)),
+      Event::Begin => self.parents.push(mem::take(&mut self.node)),
+      Event::End => self.node = self.parents.pop().unwrap_or_default(),
+    }
+    None
+  }
+
+  /// Put an error for an entry of the current node at its path
+  fn error(&self, error: Error, segment: Segment) -> Error {
+    let error = error.at(segment, None).at(Segment::Node(self.node.clone(), None), None);
+    self
+      .parents
+      .iter()
+      .rev()
+      .fold(error, |error, name| error.at(Segment::Node(name.clone(), None), None))
+  }
+}
+
+type Handler = Box<dyn for<'a> Fn(&mut Value<'a>) -> Result<(), Error> + Send + Sync>;
+
+/// Handlers for your own type annotations
+///
+/// A handler gets the value of every entry with its annotation, and can
+/// reject it with an error or replace it with something else. Reserved
+/// annotations without a handler can be checked too, see
+/// [`with_reserved`](Self::with_reserved).
+///
+/// ```
+/// use kdlite::annotation::Handlers;
+/// use kdlite::convert::{invalid_type, invalid_value};
+/// use kdlite::dom::Value;
+///
+/// let handlers = Handlers::new().with("bytes", |value: &mut Value| {
+///   let text = value.as_str().ok_or_else(|| invalid_type(value, "a size"))?;
+///   let (number, unit) = text.split_at(text.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(text.len()));
+///   let scale = match unit {
+///     "" | "B" => 1,
+///     "KiB" => 1 << 10,
+///     "MiB" => 1 << 20,
+///     _ => return Err(invalid_value(value, "a size")),
+///   };
+///   let number: i128 = number.parse().map_err(|_| invalid_value(value, "a size"))?;
+///   *value = Value::Integer(number * scale);
+///   Ok(())
+/// });
+///
+/// let document = handlers.parse(r#"cache max=(bytes)"10MiB""#).unwrap();
+/// assert_eq!(document.nodes[0].get::<u64>("max").unwrap(), 10 << 20);
+///
+/// let error = handlers.parse("cache {\n  disk max=(bytes)\"10MB\"\n}").unwrap_err();
+/// assert_eq!(error.to_string(), "cache > disk.max: invalid value: string \"10MB\", expected a size");
+/// assert_eq!(error.span().unwrap().start, 15);
+/// ```
+#[derive(Default)]
+pub struct Handlers {
+  handlers: HashMap<String, Handler>,
+  reserved: bool,
+}
+
+impl Handlers {
+  /// Create a registry with no handlers
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a handler for an annotation, replacing any previous one
+  ///
+  /// Handlers take priority over reserved annotations with the same name.
+  pub fn insert<F>(&mut self, name: impl Into<String>, handler: F)
+  where
+    F: for<'a> Fn(&mut Value<'a>) -> Result<(), Error> + Send + Sync + 'static,
+  {
+    self.handlers.insert(name.into(), Box::new(handler));
+  }
+
+  /// Add a handler for an annotation, see [`insert`](Self::insert)
+  pub fn with<F>(mut self, name: impl Into<String>, handler: F) -> Self
+  where
+    F: for<'a> Fn(&mut Value<'a>) -> Result<(), Error> + Send + Sync + 'static,
+  {
+    self.insert(name, handler);
+    self
+  }
+
+  /// Also [`check`] reserved annotations that don't have a handler (default
+  /// false)
+  pub fn with_reserved(mut self, reserved: bool) -> Self {
+    self.reserved = reserved;
+    self
+  }
+
+  /// Remove the handler for an annotation, returning whether there was one
+  pub fn remove(&mut self, name: &str) -> bool {
+    self.handlers.remove(name).is_some()
+  }
+
+  /// Check if an annotation has a handler
+  pub fn contains(&self, name: &str) -> bool {
+    self.handlers.contains_key(name)
+  }
+
+  /// Run the handler for the annotation of an entry, if there is one
+  pub fn handle(&self, entry: &mut Entry) -> Result<(), Error> {
+    let Some(name) = entry.type_hint() else {
+      return Ok(());
+    };
+    match self.handlers.get(name) {
+      Some(handler) => handler(&mut entry.value),
+      None if self.reserved => check(entry),
+      None => Ok(()),
+    }
+  }
+
+  /// Run handlers for every entry in a document
+  ///
+  /// Errors have the path to their entry, and a span if the document was
+  /// parsed with them.
+  pub fn handle_document(&self, document: &mut Document) -> Vec<Error> {
+    let mut errors = Vec::new();
+    for index in 0..document.nodes.len() {
+      let segment = child_segment(&document.nodes, index);
+      let node = &mut document.nodes[index];
+      let mut node_errors = Vec::new();
+      let mut arguments = 0;
+      for entry in &mut node.entries {
+        let segment = entry_segment(entry.key(), &mut arguments);
+        if let Err(error) = self.handle(entry) {
+          node_errors.push(error.at(segment, entry_span(entry)));
+        }
+      }
+      if let Some(children) = &mut node.children {
+        node_errors.extend(self.handle_document(children));
+      }
+      errors.extend(
+        node_errors
+          .into_iter()
+          .map(|error| error.at(segment.clone(), node_span(node))),
+      );
+    }
+    errors
+  }
+
+  /// Run handlers for entries in parser events as they go by
+  ///
+  /// Like [`validate_events`], an entry that fails is replaced by an error
+  /// with its path.
+  pub fn handle_events<'text, I>(&self, events: I) -> HandleEvents<'_, I::IntoIter>
+  where
+    I: IntoIterator<Item = Result<Event<'text>, stream::Error>>,
+  {
+    HandleEvents {
+      handlers: self,
+      events: events.into_iter(),
+      path: Path::default(),
+    }
+  }
+
+  /// Parse a document with spans and run handlers for it, failing with the
+  /// first error
+  pub fn parse<'text>(&self, text: &'text str) -> Result<Document<'text>, Error> {
+    let mut document = Document::parse_spanned(text)?;
+    match self.handle_document(&mut document).into_iter().next() {
+      Some(error) => Err(error),
+      None => Ok(document),
+    }
+  }
+
+  /// Parse a document, run handlers for it, and deserialize it, see
+  /// [`de::from_str`](crate::de::from_str)
+  #[cfg(feature = "serde")]
+  pub fn from_str<T: serde::de::DeserializeOwned>(&self, text: &str) -> Result<T, Error> {
+    crate::de::from_document(&self.parse(text)?)
+  }
+}
+
+impl fmt::Debug for Handlers {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut names: Vec<_> = self.handlers.keys().collect();
+    names.sort();
+    f.debug_struct("Handlers")
+      .field("handlers", &names)
+      .field("reserved", &self.reserved)
+      .finish()
+  }
+}
+
+/// An [`Iterator`] of events with handled annotations, see
+/// [`Handlers::handle_events`]
+pub struct HandleEvents<'h, I> {
+  handlers: &'h Handlers,
+  events: I,
+  path: Path,
+}
+
+impl<'text, I: Iterator<Item = Result<Event<'text>, stream::Error>>> Iterator for HandleEvents<'_, I> {
+  type Item = Result<Event<'text>, Error>;
+  fn next(&mut self) -> Option<Self::Item> {
+    let mut event = match self.events.next()? {
+      Ok(event) => event,
+      Err(error) => return Some(Err(error.into())),
+    };
+    let segment = self.path.follow(&event);
+    if let (
+      Some(segment),
+      Event::Entry {
+        r#type: Some(name),
+        value,
+        ..
+      },
+    ) = (segment, &mut event)
+    {
+      let result = match self.handlers.handlers.get(name.as_ref()) {
+        Some(handler) => handler(value),
+        None if self.handlers.reserved => Reserved::from_name(name).map_or(Ok(()), |reserved| reserved.check(value)),
+        None => Ok(()),
+      };
+      if let Err(error) = result {
+        return Some(Err(self.path.error(error, segment)));
+      }
+    }
+    Some(Ok(event))
+  }
+}
+
+/// Convert from a string value with a parser for an annotation
+fn from_text<'a, T>(
+  value: &'a Value,
+  reserved: Reserved,
+  parse: impl FnOnce(&'a str) -> Option<T>,
+) -> Result<T, Error> {
+  let text = value.as_str().ok_or_else(|| invalid_type(value, reserved.expected()))?;
+  parse(text).ok_or_else(|| invalid_value(value, reserved.expected()))
+}
+
+/// Parse a string with a parser for an annotation
+fn from_str<'a, T>(text: &'a str, reserved: Reserved, parse: impl FnOnce(&'a str) -> Option<T>) -> Result<T, Error> {
+  parse(text).ok_or_else(|| invalid_value(&Value::from(text), reserved.expected()))
+}
+
+macro_rules! decoded {
+  ($($type:ident $reserved:ident $parse:ident,)*) => {$(
+    impl FromStr for $type {
+      type Err = Error;
+      fn from_str(text: &str) -> Result<Self, Error> {
+        from_str(text, Reserved::$reserved, $parse)
+      }
+    }
+    impl FromValue<'_> for $type {
+      fn from_value(value: &Value) -> Result<Self, Error> {
+        from_text(value, Reserved::$reserved, $parse)
+      }
+    }
+    impl ToValue for $type {
+      fn to_value(&self) -> Value<'_> {
+        Value::String(Cow::Owned(self.to_string()))
+      }
+    }
+  )*};
+}
+
+decoded! {
+  DateTime DateTime date_time,
+  Date Date date,
+  Time Time time,
+  Duration Duration duration,
+  Uuid Uuid uuid,
+  Base64 Base64 base64,
+}
+
+impl FromValue<'_> for Ipv4Addr {
+  fn from_value(value: &Value) -> Result<Self, Error> {
+    from_text(value, Reserved::Ipv4, |text| text.parse().ok())
+  }
+}
+impl FromValue<'_> for Ipv6Addr {
+  fn from_value(value: &Value) -> Result<Self, Error> {
+    from_text(value, Reserved::Ipv6, |text| text.parse().ok())
+  }
+}
+/// Any kind of URL or IRL, absolute or relative
+impl<'a> FromValue<'a> for Url<'a> {
+  fn from_value(value: &'a Value) -> Result<Self, Error> {
+    from_text(value, Reserved::IrlReference, |text| url(text, true, true))
+  }
+}
+
+/// `count` ASCII digits at the start of `text`, and what's after them
+fn digits(text: &str, count: usize) -> Option<(u32, &str)> {
+  let head = text.get(..count)?;
+  if !head.bytes().all(|ch| ch.is_ascii_digit()) {
+    return None;
+  }
+  Some((head.parse().ok()?, &text[count..]))
+}
+
+/// `count` uppercase ASCII letters
+fn upper(text: &str, count: usize) -> bool {
+  text.len() == count && text.bytes().all(|ch| ch.is_ascii_uppercase())
+}
+
+/// A calendar date, `(date)`, like `2024-02-29`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+  /// Year, from 0 to 9999
+  pub year: u16,
+  /// Month, from 1 to 12
+  pub month: u8,
+  /// Day of the month, from 1
+  pub day: u8,
+}
+
+impl Date {
+  /// Number of days in a month of a year, 0 if the month doesn't exist
+  pub fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+      2 if leap => 29,
+      2 => 28,
+      4 | 6 | 9 | 11 => 30,
+      1..=12 => 31,
+      _ => 0,
+    }
+  }
+}
+
+/// `date-fullyear "-" date-month "-" date-mday`, and what's after it
+fn date_prefix(text: &str) -> Option<(Date, &str)> {
+  let (year, text) = digits(text, 4)?;
+  let (month, text) = digits(text.strip_prefix('-')?, 2)?;
+  let (day, text) = digits(text.strip_prefix('-')?, 2)?;
+  let date = Date {
+    year: year as u16,
+    month: month as u8,
+    day: day as u8,
+  };
+  (date.day >= 1 && date.day <= Date::days_in_month(date.year, date.month)).then_some((date, text))
+}
+
+fn date(text: &str) -> Option<Date> {
+  match date_prefix(text)? {
+    (date, "") => Some(date),
+    _ => None,
+  }
+}
+
+impl fmt::Display for Date {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+  }
+}
+
+/// A time of day, `(time)`, like `13:45:00.5`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Time {
+  /// Hour, from 0 to 23
+  pub hour: u8,
+  /// Minute, from 0 to 59
+  pub minute: u8,
+  /// Second, from 0 to 60 for leap seconds
+  pub second: u8,
+  /// Fraction of the second, in nanoseconds
+  pub nanosecond: u32,
+}
+
+/// `"." 1*DIGIT`, as nanoseconds, and what's after it
+fn fraction(text: &str) -> Option<(u32, &str)> {
+  let end = text.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(text.len());
+  if end == 0 {
+    return None;
+  }
+  // anything past nanoseconds is cut off
+  let nanos = format!("{:0<9}", &text[..end.min(9)]);
+  Some((nanos.parse().ok()?, &text[end..]))
+}
+
+/// `partial-time`, and what's after it
+fn time_prefix(text: &str) -> Option<(Time, &str)> {
+  let (hour, text) = digits(text, 2)?;
+  let (minute, text) = digits(text.strip_prefix(':')?, 2)?;
+  let (second, text) = digits(text.strip_prefix(':')?, 2)?;
+  let (nanosecond, text) = match text.strip_prefix('.') {
+    Some(text) => fraction(text)?,
+    None => (0, text),
+  };
+  let time = Time {
+    hour: hour as u8,
+    minute: minute as u8,
+    second: second as u8,
+    nanosecond,
+  };
+  (time.hour < 24 && time.minute < 60 && time.second <= 60).then_some((time, text))
+}
+
+fn time(text: &str) -> Option<Time> {
+  match time_prefix(text)? {
+    (time, "") => Some(time),
+    _ => None,
+  }
+}
+
+impl fmt::Display for Time {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)?;
+    if self.nanosecond > 0 {
+      let nanos = format!("{:09}", self.nanosecond);
+      write!(f, ".{}", nanos.trim_end_matches('0'))?;
+    }
+    Ok(())
+  }
+}
+
+/// A date and time, `(date-time)`, like `2024-02-29T13:45:00+01:00`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DateTime {
+  /// The date
+  pub date: Date,
+  /// The time of day
+  pub time: Time,
+  /// Offset from UTC in minutes, `None` for local time
+  pub offset: Option<i16>,
+}
+
+fn date_time(text: &str) -> Option<DateTime> {
+  let (date, text) = date_prefix(text)?;
+  let (time, text) = time_prefix(text.strip_prefix(['T', 't', ' '])?)?;
+  let offset = match text.as_bytes().first() {
+    None => None,
+    Some(b'Z' | b'z') if text.len() == 1 => Some(0),
+    Some(&sign @ (b'+' | b'-')) => {
+      let (hours, rest) = digits(&text[1..], 2)?;
+      let (minutes, rest) = digits(rest.strip_prefix(':')?, 2)?;
+      if !rest.is_empty() || hours >= 24 || minutes >= 60 {
+        return None;
+      }
+      let offset = (hours * 60 + minutes) as i16;
+      Some(if sign == b'-' { -offset } else { offset })
+    }
+    Some(_) => return None,
+  };
+  Some(DateTime { date, time, offset })
+}
+
+impl fmt::Display for DateTime {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}T{}", self.date, self.time)?;
+    match self.offset {
+      None => Ok(()),
+      Some(0) => f.write_str("Z"),
+      Some(offset) => {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.unsigned_abs();
+        write!(f, "{sign}{:02}:{:02}", offset / 60, offset % 60)
+      }
+    }
+  }
+}
+
+/// An ISO 8601 duration, `(duration)`, like `P1DT12H` or `PT0.5S`
+///
+/// The parts are kept separate, since months and years don't have a fixed
+/// length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Duration {
+  /// Number of years
+  pub years: u64,
+  /// Number of months
+  pub months: u64,
+  /// Number of weeks
+  pub weeks: u64,
+  /// Number of days
+  pub days: u64,
+  /// Number of hours
+  pub hours: u64,
+  /// Number of minutes
+  pub minutes: u64,
+  /// Number of seconds
+  pub seconds: u64,
+  /// Fraction of a second, in nanoseconds
+  pub nanoseconds: u32,
+}
+
+/// `number unit` pairs with units in the order of `units`, like `1Y2M`,
+/// as the number, its fraction if it has one, and the unit's index
+fn duration_parts(mut text: &str, units: &str) -> Option<Vec<(u64, Option<u32>, usize)>> {
+  let mut parts = Vec::new();
+  let mut next = 0;
+  while !text.is_empty() {
+    let end = text.find(|ch: char| !ch.is_ascii_digit()).filter(|&end| end > 0)?;
+    let number = text[..end].parse().ok()?;
+    let (fraction, rest) = match text[end..].strip_prefix(['.', ',']) {
+      Some(rest) => fraction(rest).map(|(fraction, rest)| (Some(fraction), rest))?,
+      None => (None, &text[end..]),
+    };
+    let unit = rest.chars().next()?;
+    let index = units[next..].find(unit)? + next;
+    parts.push((number, fraction, index));
+    next = index + 1;
+    text = &rest[unit.len_utf8()..];
+  }
+  Some(parts)
+}
+
+fn duration(text: &str) -> Option<Duration> {
+  let text = text.strip_prefix('P')?;
+  let (date, time) = match text.split_once('T') {
+    Some((date, time)) => (date, time),
+    None => (text, ""),
+  };
+  let date = duration_parts(date, "YMWD")?;
+  let time = duration_parts(time, "HMS")?;
+  // something has to come after the `P` and the `T`
+  if date.is_empty() && time.is_empty() || text.ends_with('T') {
+    return None;
+  }
+  let mut duration = Duration::default();
+  for (number, fraction, unit) in date {
+    if fraction.is_some() {
+      return None;
+    }
+    *[
+      &mut duration.years,
+      &mut duration.months,
+      &mut duration.weeks,
+      &mut duration.days,
+    ][unit] = number;
+  }
+  for (number, fraction, unit) in time {
+    // only seconds can have a fraction
+    match (unit, fraction) {
+      (2, fraction) => duration.nanoseconds = fraction.unwrap_or(0),
+      (_, Some(_)) => return None,
+      _ => {}
+    }
+    *[&mut duration.hours, &mut duration.minutes, &mut duration.seconds][unit] = number;
+  }
+  Some(duration)
+}
+
+impl fmt::Display for Duration {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("P")?;
+    let date = [
+      (self.years, 'Y'),
+      (self.months, 'M'),
+      (self.weeks, 'W'),
+      (self.days, 'D'),
+    ];
+    let time = [(self.hours, 'H'), (self.minutes, 'M')];
+    for (number, unit) in date {
+      if number > 0 {
+        write!(f, "{number}{unit}")?;
+      }
+    }
+    let seconds = self.seconds > 0 || self.nanoseconds > 0;
+    let empty = !seconds && date.iter().chain(&time).all(|&(number, _)| number == 0);
+    if !seconds && !empty && time.iter().all(|&(number, _)| number == 0) {
+      return Ok(());
+    }
+    f.write_str("T")?;
+    for (number, unit) in time {
+      if number > 0 {
+        write!(f, "{number}{unit}")?;
+      }
+    }
+    if seconds || empty {
+      write!(f, "{}", self.seconds)?;
+      if self.nanoseconds > 0 {
+        let nanos = format!("{:09}", self.nanoseconds);
+        write!(f, ".{}", nanos.trim_end_matches('0'))?;
+      }
+      f.write_str("S")?;
+    }
+    Ok(())
+  }
+}
+
+/// A UUID, `(uuid)`, like `0f8fad5b-d9cb-469f-a165-70867728950e`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid(pub u128);
+
+fn uuid(text: &str) -> Option<Uuid> {
+  let bytes = text.as_bytes();
+  let hyphens = [8, 13, 18, 23];
+  let valid = bytes.len() == 36
+    && bytes.iter().enumerate().all(|(i, ch)| {
+      if hyphens.contains(&i) {
+        *ch == b'-'
+      } else {
+        ch.is_ascii_hexdigit()
+      }
+    });
+  if !valid {
+    return None;
+  }
+  u128::from_str_radix(&text.replace('-', ""), 16).ok().map(Uuid)
+}
+
+impl fmt::Display for Uuid {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let hex = format!("{:032x}", self.0);
+    write!(
+      f,
+      "{}-{}-{}-{}-{}",
+      &hex[..8],
+      &hex[8..12],
+      &hex[12..16],
+      &hex[16..20],
+      &hex[20..]
+    )
+  }
+}
+
+/// Bytes written as standard padded base64, `(base64)`, like `a2Rs`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Base64(pub Vec<u8>);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(text: &str) -> Option<Base64> {
+  if !text.len().is_multiple_of(4) {
+    return None;
+  }
+  let data = text.trim_end_matches('=');
+  if text.len() - data.len() > 2 {
+    return None;
+  }
+  let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+  let (mut buffer, mut bits) = (0_u32, 0);
+  for ch in data.bytes() {
+    buffer = buffer << 6 | BASE64.iter().position(|&digit| digit == ch)? as u32;
+    bits += 6;
+    if bits >= 8 {
+      bits -= 8;
+      bytes.push((buffer >> bits) as u8);
+      buffer &= (1 << bits) - 1;
+    }
+  }
+  // the padding bits have to be zero
+  (buffer == 0).then_some(Base64(bytes))
+}
+
+impl fmt::Display for Base64 {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for chunk in self.0.chunks(3) {
+      let buffer = chunk
+        .iter()
+        .enumerate()
+        .fold(0_u32, |buffer, (i, &byte)| buffer | u32::from(byte) << (16 - 8 * i));
+      for i in 0..4 {
+        if i <= chunk.len() {
+          let digit = BASE64[(buffer >> (18 - 6 * i) & 0x3F) as usize];
+          fmt::Write::write_char(f, digit.into())?;
+        } else {
+          f.write_str("=")?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The parts of a URL or IRL, `(url)`, `(url-reference)`, `(irl)`, or
+/// `(irl-reference)`, like `https://example.com/path?query#fragment`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Url<'a> {
+  /// The scheme, like `https`, `None` for a relative reference
+  pub scheme: Option<&'a str>,
+  /// The authority after `//`, like `user@example.com:8080`
+  pub authority: Option<&'a str>,
+  /// The path, possibly empty
+  pub path: &'a str,
+  /// The query after `?`
+  pub query: Option<&'a str>,
+  /// The fragment after `#`
+  pub fragment: Option<&'a str>,
+}
+
+impl<'a> Url<'a> {
+  /// Parse an absolute URL, `(url)`
+  pub fn parse(text: &'a str) -> Result<Self, Error> {
+    from_str(text, Reserved::Url, |text| url(text, false, false))
+  }
+  /// Parse an absolute or relative URL, `(url-reference)`
+  pub fn parse_reference(text: &'a str) -> Result<Self, Error> {
+    from_str(text, Reserved::UrlReference, |text| url(text, false, true))
+  }
+  /// Parse an absolute IRL, which is a URL that can have non-ASCII
+  /// characters, `(irl)`
+  pub fn parse_irl(text: &'a str) -> Result<Self, Error> {
+    from_str(text, Reserved::Irl, |text| url(text, true, false))
+  }
+  /// Parse an absolute or relative IRL, `(irl-reference)`
+  pub fn parse_irl_reference(text: &'a str) -> Result<Self, Error> {
+    from_str(text, Reserved::IrlReference, |text| url(text, true, true))
+  }
+}
+
+/// A character that can be in a URL, or IRL if `iri`
+fn url_char(ch: char, iri: bool) -> bool {
+  ch.is_ascii_alphanumeric()
+    || "-._~:/?#[]@!$&'()*+,;=%".contains(ch)
+    || (iri && !ch.is_ascii() && !ch.is_control() && !ch.is_whitespace())
+}
+
+fn url(text: &str, iri: bool, relative: bool) -> Option<Url<'_>> {
+  let escapes = text
+    .split('%')
+    .skip(1)
+    .all(|escape| escape.get(..2).is_some_and(|hex| u8::from_str_radix(hex, 16).is_ok()));
+  if !escapes || !text.chars().all(|ch| url_char(ch, iri)) {
+    return None;
+  }
+  let (rest, fragment) = match text.split_once('#') {
+    Some((rest, fragment)) => (rest, Some(fragment)),
+    None => (text, None),
+  };
+  let (rest, query) = match rest.split_once('?') {
+    Some((rest, query)) => (rest, Some(query)),
+    None => (rest, None),
+  };
+  let scheme = rest.split_once(':').map(|(scheme, _)| scheme).filter(|scheme| {
+    scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+      && scheme
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '-' | '.'))
+  });
+  if scheme.is_none() && !relative {
+    return None;
+  }
+  let rest = scheme.map_or(rest, |scheme| &rest[scheme.len() + 1..]);
+  let (authority, path) = match rest.strip_prefix("//") {
+    Some(rest) => {
+      let end = rest.find('/').unwrap_or(rest.len());
+      (Some(&rest[..end]), &rest[end..])
+    }
+    None => (None, rest),
+  };
+  // a relative path can't look like it starts with a scheme
+  if scheme.is_none() && authority.is_none() && path.split('/').next().is_some_and(|first| first.contains(':')) {
+    return None;
+  }
+  Some(Url {
+    scheme,
+    authority,
+    path,
+    query,
+    fragment,
+  })
+}
+
+impl fmt::Display for Url<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(scheme) = self.scheme {
+      write!(f, "{scheme}:")?;
+    }
+    if let Some(authority) = self.authority {
+      write!(f, "//{authority}")?;
+    }
+    f.write_str(self.path)?;
+    if let Some(query) = self.query {
+      write!(f, "?{query}")?;
+    }
+    if let Some(fragment) = self.fragment {
+      write!(f, "#{fragment}")?;
+    }
+    Ok(())
+  }
+}
+impl ToValue for Url<'_> {
+  fn to_value(&self) -> Value<'_> {
+    Value::String(Cow::Owned(self.to_string()))
+  }
+}
+
+/// RFC 6570 URL template, with `{…}` expressions between IRL characters
+fn url_template(text: &str) -> bool {
+  let mut rest = text;
+  while let Some(start) = rest.find('{') {
+    let Some(length) = rest[start..].find('}') else {
+      return false;
+    };
+    let expression = &rest[start + 1..start + length];
+    let expression = expression
+      .strip_prefix(['+', '#', '.', '/', ';', '?', '&'])
+      .unwrap_or(expression);
+    let variables = expression.split(',').all(|variable| {
+      let variable = variable.strip_suffix('*').unwrap_or(variable);
+      let (name, prefix) = variable.split_once(':').unwrap_or((variable, "1"));
+      !name.is_empty()
+        && name
+          .chars()
+          .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '.' | '%'))
+        && (1..=4).contains(&prefix.len())
+        && prefix.bytes().all(|ch| ch.is_ascii_digit())
+    });
+    if !variables || !url_chars(&rest[..start]) {
+      return false;
+    }
+    rest = &rest[start + length + 1..];
+  }
+  url_chars(rest)
+}
+
+/// Literal text in a [`url_template`]
+fn url_chars(text: &str) -> bool {
+  text.chars().all(|ch| url_char(ch, true) && ch != '}')
+}
+
+/// `decimal`, like `-12.50e3`
+fn decimal(text: &str) -> bool {
+  let number = |text: &str| !text.is_empty() && text.bytes().all(|ch| ch.is_ascii_digit());
+  let text = text.strip_prefix(['+', '-']).unwrap_or(text);
+  let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+    Some((mantissa, exponent)) => (mantissa, Some(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))),
+    None => (text, None),
+  };
+  let (whole, fraction) = match mantissa.split_once('.') {
+    Some((whole, fraction)) => (whole, Some(fraction)),
+    None => (mantissa, None),
+  };
+  number(whole) && fraction.is_none_or(number) && exponent.is_none_or(number)
+}
+
+/// `hostname` (RFC 1123), or `idn-hostname` with non-ASCII letters
+fn hostname(text: &str, idn: bool) -> bool {
+  let text = text.strip_suffix('.').unwrap_or(text);
+  let label = |label: &str| {
+    (1..=63).contains(&label.len())
+      && !label.starts_with('-')
+      && !label.ends_with('-')
+      && (label.chars())
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || (idn && !ch.is_ascii() && ch.is_alphanumeric()))
+  };
+  text.len() <= 253 && text.split('.').all(label)
+}
+
+/// `email` (RFC 5322 without quoted or commented parts), or `idn-email`
+fn email(text: &str, idn: bool) -> bool {
+  let Some((local, domain)) = text.rsplit_once('@') else {
+    return false;
+  };
+  let atom = |atom: &str| {
+    !atom.is_empty()
+      && atom.chars().all(|ch| {
+        ch.is_ascii_alphanumeric()
+          || "!#$%&'*+-/=?^_`{|}~".contains(ch)
+          || (idn && !ch.is_ascii() && !ch.is_control() && !ch.is_whitespace())
+      })
+  };
+  let literal = |domain: &str| {
+    let address = domain.strip_prefix('[').and_then(|domain| domain.strip_suffix(']'));
+    address.is_some_and(|address| match address.strip_prefix("IPv6:") {
+      Some(address) => address.parse::<Ipv6Addr>().is_ok(),
+      None => address.parse::<Ipv4Addr>().is_ok(),
+    })
+  };
+  local.len() <= 64 && local.split('.').all(atom) && (hostname(domain, idn) || literal(domain))
+}
+
+/// A regular expression that's well-formed in the common syntax: groups and
+/// classes are closed, and quantifiers follow something
+fn regex(text: &str) -> bool {
+  let mut groups = 0_usize;
+  // nothing to repeat yet
+  let mut start = true;
+  let mut chars = text.chars();
+  while let Some(ch) = chars.next() {
+    match ch {
+      '\\' if chars.next().is_none() => return false,
+      '[' => {
+        // a `]` right after the `[` or `[^` is part of the class
+        let class = chars.as_str();
+        let class = class.strip_prefix('^').unwrap_or(class);
+        let class = class.strip_prefix(']').unwrap_or(class);
+        chars = class.chars();
+        loop {
+          match chars.next() {
+            None => return false,
+            Some('\\') if chars.next().is_none() => return false,
+            Some(']') => break,
+            Some(_) => {}
+          }
+        }
+      }
+      '(' => {
+        groups += 1;
+        start = true;
+        // `(?` starts a special group
+        if let Some(rest) = chars.as_str().strip_prefix('?') {
+          chars = rest.chars();
+        }
+        continue;
+      }
+      ')' if groups == 0 => return false,
+      ')' => groups -= 1,
+      '|' => {
+        start = true;
+        continue;
+      }
+      '*' | '+' | '?' if start => return false,
+      '*' | '+' | '?' => {
+        // lazy or possessive quantifiers
+        if let Some(rest) = chars.as_str().strip_prefix(['?', '+']) {
+          chars = rest.chars();
+        }
+        start = true;
+        continue;
+      }
+      _ => {}
+    }
+    start = false;
+  }
+  groups == 0
+}
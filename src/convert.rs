@@ -0,0 +1,499 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Conversion between document nodes and Rust types
+//!
+//! [`FromValue`] and [`ToValue`] convert entry values, [`FromNode`] and
+//! [`ToNode`] convert whole nodes. With the `derive` feature, `FromNode` and
+//! `ToNode` can be derived for structs, with each field marked as an
+//! argument, property, child, etc. (see [`derive@FromNode`]).
+//!
+//! The easiest way in is through [`Node::get`], [`Node::arg`],
+//! [`Node::child`], and [`Document::node`]:
+//!
+//! ```
+//! use kdlite::dom::{Document, Node};
+//!
+//! let document = Document::parse("server example.com port=8080 { tags a b }").unwrap();
+//! let server = document.node::<&Node>("server").unwrap();
+//! assert_eq!(server.arg::<&str>(0).unwrap(), "example.com");
+//! assert_eq!(server.get::<u16>("port").unwrap(), 8080);
+//! assert_eq!(server.get::<Option<u16>>("timeout").unwrap(), None);
+//! assert_eq!(server.child::<Vec<String>>("tags").unwrap(), ["a", "b"]);
+//! assert_eq!(server.get::<u8>("port").unwrap_err().to_string(), "port: invalid value: integer `8080`, expected u8");
+//! ```
+//!
+//! Values convert to integers (checking their range), floats, `bool`,
+//! `String`, `&str`, and `Cow<str>`, with `Option` for `#null`. Nodes
+//! convert to any of those when they have a single argument and nothing else,
+//! to `Vec` from their arguments, and to `HashMap` and `BTreeMap` from their
+//! properties. A node with a single `#null` argument is `None` as an
+//! `Option`.
+//! Strings with reserved type annotations, like dates or UUIDs, convert to
+//! the types in [`annotation`](crate::annotation).
+//!
+//! The functions in this module read parts of a node and add where they are
+//! to errors, for implementing [`FromNode`] by hand:
+//!
+//! ```
+//! use kdlite::convert::{self, Error, FromNode};
+//! use kdlite::dom::{Document, Node};
+//!
+//! #[derive(Debug)]
+//! struct Listen {
+//!   host: String,
+//!   port: u16,
+//! }
+//! impl FromNode<'_> for Listen {
+//!   fn from_node(node: &Node) -> Result<Self, Error> {
+//!     Ok(Self {
+//!       host: convert::argument(node, 0)?.ok_or_else(|| convert::missing(node, "argument 0"))?,
+//!       port: convert::property(node, "port")?.unwrap_or(80),
+//!     })
+//!   }
+//! }
+//! let document = Document::parse("server { listen localhost port=99999 }").unwrap();
+//! let error = convert::child::<Listen>(&document.nodes[0], "listen").unwrap_err();
+//! assert_eq!(error.to_string(), "listen.port: invalid value: integer `99999`, expected u16");
+//! ```
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+#[cfg(doc)]
+use crate::dom::Document;
+use crate::dom::{Entry, Node, Value};
+pub(crate) use crate::error::{child_segment, entry_span, node_span, Segment};
+
+pub use crate::error::Error;
+#[cfg(feature = "derive")]
+pub use kdlite_derive::{FromNode, ToNode};
+
+/// Convert from an entry value
+///
+/// The lifetime allows borrowing from the value, like for `&str`.
+pub trait FromValue<'a>: Sized {
+  /// Convert from a value
+  fn from_value(value: &'a Value<'_>) -> Result<Self, Error>;
+  /// What to use when there's no entry at all, `None` if it's required
+  ///
+  /// `Option<T>` gives `Some(None)`.
+  fn from_missing() -> Option<Self> {
+    None
+  }
+}
+
+/// Convert from a node
+///
+/// The lifetime allows borrowing from the node, like for `&str`.
+pub trait FromNode<'a>: Sized {
+  /// Convert from a node, ignoring its name
+  fn from_node(node: &'a Node<'_>) -> Result<Self, Error>;
+  /// What to use when there's no node at all, `None` if it's required
+  ///
+  /// `Option<T>` gives `Some(None)`.
+  fn from_missing() -> Option<Self> {
+    None
+  }
+}
+
+/// Convert into an entry value
+pub trait ToValue {
+  /// Convert into a value, possibly borrowing from `self`
+  fn to_value(&self) -> Value<'_>;
+}
+
+/// Convert into a node
+pub trait ToNode {
+  /// Convert into a node, possibly borrowing from `self`
+  ///
+  /// Parents may rename the node to fit where it goes.
+  fn to_node(&self) -> Node<'_>;
+}
+
+/// The value itself, for keeping values as they are
+impl<'a> FromValue<'a> for Value<'a> {
+  fn from_value(value: &'a Value<'_>) -> Result<Self, Error> {
+    Ok(value.clone())
+  }
+}
+impl ToValue for Value<'_> {
+  fn to_value(&self) -> Value<'_> {
+    self.clone()
+  }
+}
+
+/// Describe a value in an error, like `integer `5``
+fn unexpected(value: &Value) -> String {
+  match value {
+    Value::String(text) => format!("string {text:?}"),
+    Value::Integer(value) => format!("integer `{value}`"),
+    Value::BigInteger(value) => format!("integer `{value}`"),
+    Value::Float(value) => format!("floating point `{value}`"),
+    Value::Bool(value) => format!("boolean `{value}`"),
+    Value::Null => "null".into(),
+  }
+}
+
+/// An error for a value of the wrong type
+pub fn invalid_type(value: &Value, expected: &str) -> Error {
+  Error::new(format_args!("invalid type: {}, expected {expected}", unexpected(value)))
+}
+
+/// An error for a value of the right type that's still invalid, like an
+/// integer out of range
+pub fn invalid_value(value: &Value, expected: &str) -> Error {
+  Error::new(format_args!(
+    "invalid value: {}, expected {expected}",
+    unexpected(value)
+  ))
+}
+
+/// An error for something missing from a node, like `argument 0`
+pub fn missing(node: &Node, what: impl fmt::Display) -> Error {
+  let mut error = Error::new(format_args!("missing {what}"));
+  error.span = node_span(node);
+  error
+}
+
+macro_rules! from_integer {
+  ($($type:ty)*) => {$(
+    impl FromValue<'_> for $type {
+      fn from_value(value: &Value) -> Result<Self, Error> {
+        let number = match value {
+          Value::Integer(number) => <$type>::try_from(*number).ok(),
+          Value::BigInteger(number) => number.parse().ok(),
+          _ => return Err(invalid_type(value, stringify!($type))),
+        };
+        number.ok_or_else(|| invalid_value(value, stringify!($type)))
+      }
+    }
+  )*};
+}
+
+macro_rules! to_integer {
+  ($($type:ty)*) => {$(
+    impl ToValue for $type {
+      fn to_value(&self) -> Value<'_> {
+        Value::Integer(*self as i128)
+      }
+    }
+  )*};
+}
+
+from_integer! { i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize }
+to_integer! { i8 i16 i32 i64 i128 isize u8 u16 u32 u64 usize }
+
+impl ToValue for u128 {
+  fn to_value(&self) -> Value<'_> {
+    match i128::try_from(*self) {
+      Ok(number) => Value::Integer(number),
+      Err(_) => Value::BigInteger(Cow::Owned(self.to_string())),
+    }
+  }
+}
+
+impl FromValue<'_> for f64 {
+  fn from_value(value: &Value) -> Result<Self, Error> {
+    match *value {
+      Value::Float(number) => Ok(number),
+      Value::Integer(number) => Ok(number as f64),
+      Value::BigInteger(ref number) => number.parse().map_err(|_| invalid_value(value, "f64")),
+      _ => Err(invalid_type(value, "f64")),
+    }
+  }
+}
+impl ToValue for f64 {
+  fn to_value(&self) -> Value<'_> {
+    Value::Float(*self)
+  }
+}
+
+impl FromValue<'_> for f32 {
+  fn from_value(value: &Value) -> Result<Self, Error> {
+    match *value {
+      Value::Float(number) if number.is_finite() && (number as f32).is_infinite() => Err(invalid_value(value, "f32")),
+      Value::Float(number) => Ok(number as f32),
+      Value::Integer(number) => Ok(number as f32),
+      Value::BigInteger(ref number) => number.parse().map_err(|_| invalid_value(value, "f32")),
+      _ => Err(invalid_type(value, "f32")),
+    }
+  }
+}
+impl ToValue for f32 {
+  fn to_value(&self) -> Value<'_> {
+    Value::Float((*self).into())
+  }
+}
+
+impl FromValue<'_> for bool {
+  fn from_value(value: &Value) -> Result<Self, Error> {
+    match *value {
+      Value::Bool(value) => Ok(value),
+      _ => Err(invalid_type(value, "a boolean")),
+    }
+  }
+}
+impl ToValue for bool {
+  fn to_value(&self) -> Value<'_> {
+    Value::Bool(*self)
+  }
+}
+
+impl<'a> FromValue<'a> for &'a str {
+  fn from_value(value: &'a Value) -> Result<Self, Error> {
+    match value {
+      Value::String(text) => Ok(text),
+      _ => Err(invalid_type(value, "a string")),
+    }
+  }
+}
+impl ToValue for &str {
+  fn to_value(&self) -> Value<'_> {
+    Value::String(Cow::Borrowed(self))
+  }
+}
+
+impl<'a> FromValue<'a> for Cow<'a, str> {
+  fn from_value(value: &'a Value) -> Result<Self, Error> {
+    <&str>::from_value(value).map(Cow::Borrowed)
+  }
+}
+impl ToValue for Cow<'_, str> {
+  fn to_value(&self) -> Value<'_> {
+    Value::String(Cow::Borrowed(self))
+  }
+}
+
+impl FromValue<'_> for String {
+  fn from_value(value: &Value) -> Result<Self, Error> {
+    <&str>::from_value(value).map(str::to_owned)
+  }
+}
+impl ToValue for String {
+  fn to_value(&self) -> Value<'_> {
+    Value::String(Cow::Borrowed(self))
+  }
+}
+
+/// Convert an argument of a node, `None` if there aren't enough
+pub fn argument<'a, T: FromValue<'a>>(node: &'a Node, index: usize) -> Result<Option<T>, Error> {
+  node
+    .entry(index)
+    .map(|entry| from_entry(entry, Segment::Argument(index)))
+    .transpose()
+}
+
+/// Convert the arguments of a node, starting at `skip`
+pub fn arguments<'a, T: FromValue<'a>, C: FromIterator<T>>(node: &'a Node, skip: usize) -> Result<C, Error> {
+  let arguments = node.entries.iter().filter(|entry| entry.key().is_none());
+  let arguments = arguments.enumerate().skip(skip);
+  arguments
+    .map(|(i, entry)| from_entry(entry, Segment::Argument(i)))
+    .collect()
+}
+
+/// Convert a property of a node, with the last one winning if it's repeated
+pub fn property<'a, T: FromValue<'a>>(node: &'a Node, key: &str) -> Result<Option<T>, Error> {
+  node
+    .entry(key)
+    .map(|entry| from_entry(entry, Segment::Property(key.to_owned())))
+    .transpose()
+}
+
+fn from_entry<'a, T: FromValue<'a>>(entry: &'a Entry, segment: Segment) -> Result<T, Error> {
+  T::from_value(&entry.value).map_err(|error| error.at(segment, entry_span(entry)))
+}
+
+fn from_child<'a, T: FromNode<'a>>(nodes: &'a [Node], index: usize) -> Result<T, Error> {
+  T::from_node(&nodes[index]).map_err(|error| error.at(child_segment(nodes, index), node_span(&nodes[index])))
+}
+
+fn nodes<'a, 'text>(node: &'a Node<'text>) -> &'a [Node<'text>] {
+  node.children.as_ref().map_or(&[], |children| &children.nodes)
+}
+
+/// Convert the only node in `nodes` with a name, `None` if there isn't one
+pub(crate) fn only<'a, T: FromNode<'a>>(nodes: &'a [Node], name: &str) -> Result<Option<T>, Error> {
+  let mut same = (0..nodes.len()).filter(|&i| nodes[i].name() == name);
+  match (same.next(), same.count()) {
+    (None, _) => Ok(None),
+    (Some(index), 0) => from_child(nodes, index).map(Some),
+    (Some(index), more) => Err(
+      Error::new(format_args!("expected one `{name}` node, found {}", more + 1))
+        .at(Segment::Node(name.to_owned(), None), node_span(&nodes[index])),
+    ),
+  }
+}
+
+/// Convert the only child of a node with a name, `None` if there isn't one
+pub fn child<'a, T: FromNode<'a>>(node: &'a Node, name: &str) -> Result<Option<T>, Error> {
+  only(nodes(node), name)
+}
+
+/// Convert the children of a node, or only those with a name
+pub fn children<'a, T: FromNode<'a>, C: FromIterator<T>>(node: &'a Node, name: Option<&str>) -> Result<C, Error> {
+  let nodes = nodes(node);
+  (0..nodes.len())
+    .filter(|&i| name.is_none_or(|name| nodes[i].name() == name))
+    .map(|i| from_child(nodes, i))
+    .collect()
+}
+
+/// The only argument of a node with nothing else, for nodes that are a
+/// single value
+pub fn single<'a, 'text>(node: &'a Node<'text>) -> Result<&'a Value<'text>, Error> {
+  match (&*node.entries, nodes(node)) {
+    ([entry], []) if entry.key().is_none() => Ok(&entry.value),
+    (entries, children) => {
+      let arguments = entries.iter().filter(|entry| entry.key().is_none()).count();
+      Err(Error::new(format_args!(
+        "expected a single argument, found {arguments} arguments, {} properties, and {} children",
+        entries.len() - arguments,
+        children.len(),
+      )))
+    }
+  }
+}
+
+impl<'a, T: FromValue<'a>> FromValue<'a> for Option<T> {
+  fn from_value(value: &'a Value) -> Result<Self, Error> {
+    match value {
+      Value::Null => Ok(None),
+      value => T::from_value(value).map(Some),
+    }
+  }
+  fn from_missing() -> Option<Self> {
+    Some(None)
+  }
+}
+impl<T: ToValue> ToValue for Option<T> {
+  fn to_value(&self) -> Value<'_> {
+    match self {
+      Some(value) => value.to_value(),
+      None => Value::Null,
+    }
+  }
+}
+
+macro_rules! single_value {
+  ($($type:ty)*) => {$(
+    impl<'a> FromNode<'a> for $type {
+      fn from_node(node: &'a Node) -> Result<Self, Error> {
+        let value = single(node)?;
+        <$type>::from_value(value).map_err(|error| error.at(Segment::Argument(0), entry_span(&node.entries[0])))
+      }
+    }
+  )*};
+}
+
+macro_rules! to_single_value {
+  ($($type:ty)*) => {$(
+    impl<'a> ToNode for $type {
+      fn to_node(&self) -> Node<'_> {
+        let mut node = Node::new("-");
+        node.entries.push(Entry::new_value(self.to_value()));
+        node
+      }
+    }
+  )*};
+}
+
+single_value! { i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize f32 f64 bool &'a str Cow<'a, str> String }
+to_single_value! { i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize f32 f64 bool &'a str Cow<'a, str> String }
+
+/// The node itself, for reading it some other way
+impl<'a> FromNode<'a> for &'a Node<'a> {
+  fn from_node(node: &'a Node) -> Result<Self, Error> {
+    Ok(node)
+  }
+}
+
+/// A single `#null` argument is `None`
+impl<'a, T: FromNode<'a>> FromNode<'a> for Option<T> {
+  fn from_node(node: &'a Node) -> Result<Self, Error> {
+    match single(node) {
+      Ok(Value::Null) => Ok(None),
+      _ => T::from_node(node).map(Some),
+    }
+  }
+  fn from_missing() -> Option<Self> {
+    Some(None)
+  }
+}
+/// `None` is a single `#null` argument
+impl<T: ToNode> ToNode for Option<T> {
+  fn to_node(&self) -> Node<'_> {
+    match self {
+      Some(value) => value.to_node(),
+      None => {
+        let mut node = Node::new("-");
+        node.entries.push(Entry::new_value(Value::Null));
+        node
+      }
+    }
+  }
+}
+
+/// The node's arguments
+impl<'a, T: FromValue<'a>> FromNode<'a> for Vec<T> {
+  fn from_node(node: &'a Node) -> Result<Self, Error> {
+    arguments(node, 0)
+  }
+}
+/// The node's arguments
+impl<T: ToValue> ToNode for Vec<T> {
+  fn to_node(&self) -> Node<'_> {
+    let mut node = Node::new("-");
+    node.entries = self.iter().map(|value| Entry::new_value(value.to_value())).collect();
+    node
+  }
+}
+
+fn properties<'a, K: From<&'a str>, V: FromValue<'a>, C: FromIterator<(K, V)>>(node: &'a Node) -> Result<C, Error> {
+  let properties = node.entries.iter().filter_map(|entry| Some((entry.key()?, entry)));
+  properties
+    .map(|(key, entry)| Ok((K::from(key), from_entry(entry, Segment::Property(key.to_owned()))?)))
+    .collect()
+}
+
+fn to_properties<'a, K: AsRef<str> + 'a, V: ToValue + 'a>(
+  properties: impl Iterator<Item = (&'a K, &'a V)>,
+) -> Node<'a> {
+  let mut node = Node::new("-");
+  node.entries = properties
+    .map(|(key, value)| Entry::new_prop(key.as_ref(), value.to_value()))
+    .collect();
+  node
+}
+
+/// The node's properties, with the last one winning if it's repeated
+impl<'a, K, V, S> FromNode<'a> for HashMap<K, V, S>
+where
+  K: From<&'a str> + Eq + Hash,
+  V: FromValue<'a>,
+  S: BuildHasher + Default,
+{
+  fn from_node(node: &'a Node) -> Result<Self, Error> {
+    properties(node)
+  }
+}
+/// The node's properties
+impl<K: AsRef<str>, V: ToValue, S> ToNode for HashMap<K, V, S> {
+  fn to_node(&self) -> Node<'_> {
+    to_properties(self.iter())
+  }
+}
+
+/// The node's properties, with the last one winning if it's repeated
+impl<'a, K: From<&'a str> + Ord, V: FromValue<'a>> FromNode<'a> for BTreeMap<K, V> {
+  fn from_node(node: &'a Node) -> Result<Self, Error> {
+    properties(node)
+  }
+}
+/// The node's properties
+impl<K: AsRef<str>, V: ToValue> ToNode for BTreeMap<K, V> {
+  fn to_node(&self) -> Node<'_> {
+    to_properties(self.iter())
+  }
+}
@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Moving around a document from node to node, see [`DocumentCursor`] and
+//! [`DocumentCursorMut`]
+//!
+//! Nodes don't know their parent, so a cursor keeps track of the way down
+//! to the current node instead:
+//!
+//! ```
+//! use kdlite::dom::Document;
+//!
+//! let document = Document::parse("http { server { listen 80 } }").unwrap();
+//! let mut cursor = document.cursor().unwrap();
+//! assert!(cursor.first_child() && cursor.first_child());
+//! assert_eq!(cursor.node().name(), "listen");
+//! assert_eq!(cursor.depth(), 2);
+//! let names: Vec<_> = cursor.ancestors().map(|node| node.name()).collect();
+//! assert_eq!(names, ["server", "http"]);
+//! ```
+
+use std::mem;
+
+use crate::dom::{Document, IndexPath, Node};
+
+/// A read-only position in a document, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct DocumentCursor<'a, 'text> {
+  /// the siblings at each level, and the index of the node among them
+  stack: Vec<(&'a [Node<'text>], usize)>,
+}
+
+impl<'a, 'text> DocumentCursor<'a, 'text> {
+  pub(crate) fn new(document: &'a Document<'text>, path: &[usize]) -> Option<Self> {
+    let mut stack = Vec::with_capacity(path.len());
+    let mut nodes = &document.nodes[..];
+    for &index in path {
+      nodes.get(index)?;
+      stack.push((nodes, index));
+      nodes = nodes[index].children.as_ref().map_or(&[], |children| &children.nodes);
+    }
+    (!stack.is_empty()).then_some(Self { stack })
+  }
+
+  /// The current node
+  pub fn node(&self) -> &'a Node<'text> {
+    let &(nodes, index) = self.stack.last().unwrap();
+    &nodes[index]
+  }
+  /// Position of the current node
+  pub fn path(&self) -> IndexPath {
+    IndexPath {
+      nodes: self.stack.iter().map(|&(_, index)| index).collect(),
+      entry: None,
+    }
+  }
+  /// Number of nodes around the current one, 0 at the top level
+  pub fn depth(&self) -> usize {
+    self.stack.len() - 1
+  }
+  /// The nodes around the current one, innermost first
+  pub fn ancestors(&self) -> impl Iterator<Item = &'a Node<'text>> + '_ {
+    self.stack.iter().rev().skip(1).map(|&(nodes, index)| &nodes[index])
+  }
+
+  /// Move to the node around this one, if there is one
+  pub fn parent(&mut self) -> bool {
+    self.stack.len() > 1 && self.stack.pop().is_some()
+  }
+  /// Move to the first child of this node, if there is one
+  pub fn first_child(&mut self) -> bool {
+    match &self.node().children {
+      Some(children) if !children.nodes.is_empty() => {
+        self.stack.push((&children.nodes, 0));
+        true
+      }
+      _ => false,
+    }
+  }
+  /// Move to the node after this one, if there is one
+  pub fn next_sibling(&mut self) -> bool {
+    let (nodes, index) = self.stack.last_mut().unwrap();
+    let next = *index + 1 < nodes.len();
+    *index += next as usize;
+    next
+  }
+  /// Move to the node before this one, if there is one
+  pub fn prev_sibling(&mut self) -> bool {
+    let (_, index) = self.stack.last_mut().unwrap();
+    let prev = *index > 0;
+    *index -= prev as usize;
+    prev
+  }
+}
+
+/// A position in a document that can change it, see the
+/// [module docs](self)
+///
+/// The cursor only keeps the path to the current node, so every step finds
+/// the node from the top again.
+#[derive(Debug)]
+pub struct DocumentCursorMut<'a, 'text> {
+  document: &'a mut Document<'text>,
+  /// never empty
+  path: Vec<usize>,
+}
+
+impl<'a, 'text> DocumentCursorMut<'a, 'text> {
+  pub(crate) fn new(document: &'a mut Document<'text>, path: &[usize]) -> Option<Self> {
+    DocumentCursor::new(document, path)?;
+    Some(Self {
+      document,
+      path: path.to_vec(),
+    })
+  }
+
+  /// The current node and its siblings
+  fn siblings(&self) -> &Vec<Node<'text>> {
+    let mut document = &*self.document;
+    for &index in &self.path[..self.path.len() - 1] {
+      document = document.nodes[index].children.as_ref().unwrap();
+    }
+    &document.nodes
+  }
+  fn siblings_mut(&mut self) -> &mut Vec<Node<'text>> {
+    let mut document = &mut *self.document;
+    for &index in &self.path[..self.path.len() - 1] {
+      document = document.nodes[index].children.as_mut().unwrap();
+    }
+    &mut document.nodes
+  }
+  fn index(&mut self) -> &mut usize {
+    self.path.last_mut().unwrap()
+  }
+
+  /// The current node
+  pub fn node(&self) -> &Node<'text> {
+    &self.siblings()[*self.path.last().unwrap()]
+  }
+  /// Change the current node in place
+  pub fn node_mut(&mut self) -> &mut Node<'text> {
+    let index = *self.index();
+    &mut self.siblings_mut()[index]
+  }
+  /// Position of the current node
+  pub fn path(&self) -> IndexPath {
+    IndexPath {
+      nodes: self.path.clone(),
+      entry: None,
+    }
+  }
+  /// Number of nodes around the current one, 0 at the top level
+  pub fn depth(&self) -> usize {
+    self.path.len() - 1
+  }
+  /// A read-only cursor at the same position
+  pub fn as_cursor(&self) -> DocumentCursor<'_, 'text> {
+    DocumentCursor::new(&*self.document, &self.path).unwrap()
+  }
+
+  /// Move to the node around this one, if there is one
+  pub fn parent(&mut self) -> bool {
+    self.path.len() > 1 && self.path.pop().is_some()
+  }
+  /// Move to the first child of this node, if there is one
+  pub fn first_child(&mut self) -> bool {
+    let has_child = self
+      .node()
+      .children
+      .as_ref()
+      .is_some_and(|children| !children.nodes.is_empty());
+    if has_child {
+      self.path.push(0);
+    }
+    has_child
+  }
+  /// Move to the node after this one, if there is one
+  pub fn next_sibling(&mut self) -> bool {
+    let next = *self.path.last().unwrap() + 1 < self.siblings().len();
+    *self.index() += next as usize;
+    next
+  }
+  /// Move to the node before this one, if there is one
+  pub fn prev_sibling(&mut self) -> bool {
+    let prev = *self.index() > 0;
+    *self.index() -= prev as usize;
+    prev
+  }
+
+  /// Put a node before the current one, staying on the current one
+  pub fn insert_before(&mut self, node: Node<'text>) {
+    let index = *self.index();
+    self.siblings_mut().insert(index, node);
+    *self.index() += 1;
+  }
+  /// Put a node after the current one, staying on the current one
+  pub fn insert_after(&mut self, node: Node<'text>) {
+    let index = *self.index();
+    self.siblings_mut().insert(index + 1, node);
+  }
+  /// Add a child at the end of the current node, staying on the current one
+  pub fn push_child(&mut self, node: Node<'text>) {
+    self
+      .node_mut()
+      .children
+      .get_or_insert_with(Document::new)
+      .nodes
+      .push(node);
+  }
+  /// Put a node in place of the current one, returning the old one
+  pub fn replace(&mut self, node: Node<'text>) -> Node<'text> {
+    mem::replace(self.node_mut(), node)
+  }
+  /// Remove the current node and move to the next one, or the previous one,
+  /// or the parent
+  ///
+  /// The cursor needs a node to be on, so this does nothing and returns
+  /// `None` for the only node at the top level.
+  pub fn remove(&mut self) -> Option<Node<'text>> {
+    if self.path.len() == 1 && self.document.nodes.len() == 1 {
+      return None;
+    }
+    let index = *self.index();
+    let siblings = self.siblings_mut();
+    let node = siblings.remove(index);
+    if index == siblings.len() {
+      match index {
+        0 => drop(self.path.pop()),
+        _ => *self.index() -= 1,
+      }
+    }
+    Some(node)
+  }
+}
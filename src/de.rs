@@ -0,0 +1,1676 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! [serde] deserialization from the document tree, requires the `serde`
+//! feature
+//!
+//! Start with [`from_str`], or deserialize directly from a `&Document`,
+//! `&Node`, `&Entry`, or `&Value`, which all implement [`Deserializer`].
+//! Strings can be borrowed from the document.
+//!
+//! ## Mapping
+//!
+//! A [`Document`] is a map (or struct) from node names to nodes, or a
+//! sequence of its nodes. Nodes with the same name are grouped together:
+//! a group of several nodes is a sequence of those nodes, and any other type
+//! needs exactly one node.
+//!
+//! A [`Node`] can be:
+//! - A single value, if it has exactly one argument and nothing else:
+//!   `port 8080`.
+//!   A single `#null` argument is `None`.
+//! - A sequence or tuple of its arguments followed by its children:
+//!   `listen "0.0.0.0" 80` or `hosts { - "a"; - "b" }`.
+//! - A struct or map of its properties and children, grouped by name like a
+//!   document: `server port=80 { host "a" }`. When the same property is
+//!   given multiple times, the last one is used.
+//!   Structs can also have these fields, which are all optional:
+//!   - [`ARGUMENTS`] (`"$args"`): all arguments as a sequence
+//!   - `"0"`, `"1"`, …: a single argument, by position
+//!   - [`NAME`] (`"$name"`): the node name
+//!   - [`TYPE`] (`"$type"`): the node type annotation, if any
+//!   - [`CHILDREN`] (`"$children"`): all children as a sequence
+//!
+//!   Maps get arguments as a [`ARGUMENTS`] key.
+//! - An enum variant, chosen by:
+//!   - the node type annotation: `(tcp)listen 80`, with the rest of the
+//!     node as the variant contents
+//!   - a single string argument for unit variants: `mode "fast"`
+//!   - a single child with no entries: `listen { tcp 80 }`, with the child
+//!     as the variant contents
+//! - Unit, with anything in it.
+//!
+//! When a group of one node is deserialized as a sequence, the node is the
+//! only element if it has properties, or children not all named `-`.
+//! Otherwise it's a sequence of its arguments and children, as above.
+//!
+//! A [`Value`] is deserialized as itself, with `#null` as unit or `None`.
+//! Integers outside the range of the output type are errors (a
+//! [`Value::BigInteger`] can only be a `u128`), and strings are unit enum
+//! variants. An [`Entry`] is its value, and its type annotation chooses an
+//! enum variant like for nodes: `(udp)53`.
+//!
+//! ## Streaming
+//!
+//! [`from_parser`] deserializes straight from a [`Parser`], reading children
+//! as they're needed instead of building a document first. Since it can't
+//! look ahead, the mapping is a little different:
+//! - Only consecutive nodes with the same name are grouped, so a name that
+//!   shows up again later is a second group (and usually a duplicate field
+//!   error).
+//! - A single node is the only element of a sequence if it has properties,
+//!   or its first child isn't named `-`.
+//! - Nodes in a group only have an index in error paths after the first
+//!   one, so the first node is `listen`, not `listen[0]`.
+//! - The [`CHILDREN`] struct field takes all the children, leaving none for
+//!   the other fields.
+//! - Enum variants chosen by a child use the first child.
+//!
+//! [`Deserializer`]: serde::Deserializer
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Unexpected, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use crate::dom::{Document, Entry, Node, Value};
+use crate::error::{child_segment, entry_span, node_span, Segment};
+use crate::stream::{Event, EventSpan, Parser, Span, SpannedParser};
+
+/// Struct field name for all of a node's arguments
+pub const ARGUMENTS: &str = "$args";
+/// Struct field name for a node's name
+pub const NAME: &str = "$name";
+/// Struct field name for a node's type annotation
+pub const TYPE: &str = "$type";
+/// Struct field name for all of a node's children
+pub const CHILDREN: &str = "$children";
+
+pub use crate::error::Error;
+
+impl de::Error for Error {
+  fn custom<T: fmt::Display>(msg: T) -> Self {
+    Error::new(msg)
+  }
+}
+
+/// Parse and deserialize a document
+pub fn from_str<T: DeserializeOwned>(text: &str) -> Result<T, Error> {
+  from_document(&Document::parse_spanned(text)?)
+}
+
+/// Deserialize a document, see the [module docs](self) for the mapping
+pub fn from_document<'de, T: Deserialize<'de>>(document: &'de Document) -> Result<T, Error> {
+  T::deserialize(document)
+}
+
+/// Deserialize a single node, see the [module docs](self) for the mapping
+pub fn from_node<'de, T: Deserialize<'de>>(node: &'de Node) -> Result<T, Error> {
+  T::deserialize(node)
+}
+
+/// Deserialize a single value
+pub fn from_value<'de, T: Deserialize<'de>>(value: &'de Value) -> Result<T, Error> {
+  T::deserialize(value)
+}
+
+/// Nodes grouped by name, in order of first appearance
+fn groups<'de, 'text>(nodes: &'de [Node<'text>]) -> Vec<(&'de str, Item<'de, 'text>)> {
+  let mut groups: Vec<(&str, Item)> = Vec::new();
+  let mut seen: HashMap<&str, usize> = HashMap::new();
+  for node in nodes {
+    match seen.get(node.name()) {
+      Some(&index) => match &mut groups[index].1 {
+        Item::Group(group) => group.nodes.push(node),
+        _ => unreachable!(),
+      },
+      None => {
+        seen.insert(node.name(), groups.len());
+        groups.push((
+          node.name(),
+          Item::Group(Group {
+            name: node.name(),
+            nodes: vec![node],
+          }),
+        ));
+      }
+    }
+  }
+  groups
+}
+
+impl<'de, 'text> de::Deserializer<'de> for &'de Document<'text> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_map(visitor)
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_some(self)
+  }
+  fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(
+      (0..self.nodes.len())
+        .map(|i| Element::Child(&self.nodes, i))
+        .collect::<Elements>(),
+    )
+  }
+  fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_map(Items::new(groups(&self.nodes)))
+  }
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_map(visitor)
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    match &*self.nodes {
+      [node] => visitor.visit_enum(Enum {
+        variant: Cow::Borrowed(node.name()),
+        content: Some(NodeDe { node, typed: true }),
+        context: None,
+      }),
+      _ => Err(de::Error::invalid_length(
+        self.nodes.len(),
+        &"a single node for an enum",
+      )),
+    }
+  }
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf identifier
+  }
+}
+
+impl<'de, 'text> de::Deserializer<'de> for &'de Node<'text> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_any(visitor)
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_option(visitor)
+  }
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_seq(visitor)
+  }
+  fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_tuple(len, visitor)
+  }
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_tuple_struct(name, len, visitor)
+  }
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_map(visitor)
+  }
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_struct(name, fields, visitor)
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    NodeDe {
+      node: self,
+      typed: true,
+    }
+    .deserialize_enum(name, variants, visitor)
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf identifier
+  }
+}
+
+impl<'de, 'text> de::Deserializer<'de> for &'de Entry<'text> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    ValueDe::entry(self).deserialize_any(visitor)
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    ValueDe::entry(self).deserialize_option(visitor)
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    ValueDe::entry(self).deserialize_enum(name, variants, visitor)
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+    identifier ignored_any
+  }
+}
+
+impl<'de, 'text> de::Deserializer<'de> for &'de Value<'text> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    ValueDe::value(self).deserialize_any(visitor)
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    ValueDe::value(self).deserialize_option(visitor)
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    ValueDe::value(self).deserialize_enum(name, variants, visitor)
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+    identifier ignored_any
+  }
+}
+
+/// A value, with the type annotation of its entry
+struct ValueDe<'de, 'text> {
+  value: &'de Value<'text>,
+  r#type: Option<&'de str>,
+}
+
+impl<'de, 'text> ValueDe<'de, 'text> {
+  fn value(value: &'de Value<'text>) -> Self {
+    Self { value, r#type: None }
+  }
+  fn entry(entry: &'de Entry<'text>) -> Self {
+    Self {
+      value: &entry.value,
+      r#type: entry.type_hint(),
+    }
+  }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDe<'de, '_> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.value {
+      Value::String(value) => visitor.visit_borrowed_str(value),
+      value => visit_value(value, visitor),
+    }
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.value {
+      Value::Null => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    }
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    match (self.r#type, self.value) {
+      (Some(variant), value) => visitor.visit_enum(Enum {
+        variant: Cow::Borrowed(variant),
+        content: Some(ValueDe::value(value)),
+        context: None,
+      }),
+      (None, Value::String(variant)) => visitor.visit_enum(Enum::<ValueDe> {
+        variant: Cow::Borrowed(variant),
+        content: None,
+        context: None,
+      }),
+      (None, value) => Err(de::Error::invalid_type(
+        unexpected(value),
+        &"a string or type annotation",
+      )),
+    }
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+    identifier ignored_any
+  }
+}
+
+/// Visit a value, without borrowing strings
+fn visit_value<'de, V: Visitor<'de>>(value: &Value, visitor: V) -> Result<V::Value, Error> {
+  match *value {
+    Value::String(ref value) => visitor.visit_str(value),
+    Value::Integer(value) => match (i64::try_from(value), u64::try_from(value)) {
+      (Ok(value), _) => visitor.visit_i64(value),
+      (_, Ok(value)) => visitor.visit_u64(value),
+      _ => visitor.visit_i128(value),
+    },
+    Value::BigInteger(ref number) => match number.parse() {
+      Ok(number) => visitor.visit_u128(number),
+      Err(_) => Err(de::Error::invalid_value(unexpected(value), &visitor)),
+    },
+    Value::Float(value) => visitor.visit_f64(value),
+    Value::Bool(value) => visitor.visit_bool(value),
+    Value::Null => visitor.visit_unit(),
+  }
+}
+
+fn unexpected<'a>(value: &'a Value) -> Unexpected<'a> {
+  match value {
+    Value::String(value) => Unexpected::Str(value),
+    // close enough for an error message
+    &Value::Integer(value) => match i64::try_from(value) {
+      Ok(value) => Unexpected::Signed(value),
+      Err(_) => Unexpected::Other("large integer"),
+    },
+    Value::BigInteger(_) => Unexpected::Other("large integer"),
+    &Value::Float(value) => Unexpected::Float(value),
+    &Value::Bool(value) => Unexpected::Bool(value),
+    Value::Null => Unexpected::Unit,
+  }
+}
+
+/// A node, `typed` if its type annotation hasn't been used for an enum yet
+struct NodeDe<'de, 'text> {
+  node: &'de Node<'text>,
+  typed: bool,
+}
+
+impl<'de, 'text> NodeDe<'de, 'text> {
+  fn arguments(&self) -> impl Iterator<Item = &'de Entry<'text>> {
+    self.node.entries.iter().filter(|entry| entry.key().is_none())
+  }
+  fn properties(&self) -> impl Iterator<Item = &'de Entry<'text>> {
+    self.node.entries.iter().filter(|entry| entry.key().is_some())
+  }
+  fn children(&self) -> &'de [Node<'text>] {
+    self.node.children.as_ref().map_or(&[], |children| &children.nodes)
+  }
+  /// The only argument, for reading the node as a single value
+  fn single(&self) -> Result<&'de Entry<'text>, Error> {
+    match (&*self.node.entries, self.children()) {
+      ([entry], []) if entry.key().is_none() => Ok(entry),
+      _ => Err(de::Error::custom(format_args!(
+        "expected a single argument, found {} arguments, {} properties, and {} children",
+        self.arguments().count(),
+        self.properties().count(),
+        self.children().len(),
+      ))),
+    }
+  }
+  fn elements(&self) -> Elements<'de, 'text> {
+    let arguments = self
+      .arguments()
+      .enumerate()
+      .map(|(i, entry)| Element::Argument(i, entry));
+    arguments.chain(self.children_elements()).collect()
+  }
+  fn arguments_elements(&self) -> Elements<'de, 'text> {
+    self
+      .arguments()
+      .enumerate()
+      .map(|(i, entry)| Element::Argument(i, entry))
+      .collect()
+  }
+  fn children_elements(&self) -> impl Iterator<Item = Element<'de, 'text>> {
+    let children = self.children();
+    (0..children.len()).map(move |i| Element::Child(children, i))
+  }
+  fn properties_and_children(&self) -> Vec<(&'de str, Item<'de, 'text>)> {
+    let properties: Vec<_> = self.properties().collect();
+    let mut items = Vec::new();
+    for (i, entry) in properties.iter().enumerate() {
+      let key = entry.key().unwrap();
+      // rightmost property wins
+      if !properties[i + 1..].iter().any(|later| later.key() == Some(key)) {
+        items.push((key, Item::Property(entry)));
+      }
+    }
+    items.extend(groups(self.children()));
+    items
+  }
+}
+
+macro_rules! forward_to_single {
+  ($($method:ident)*) => {$(
+    fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      ValueDe::entry(self.single()?).$method(visitor)
+    }
+  )*};
+}
+
+impl<'de> de::Deserializer<'de> for NodeDe<'de, '_> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    if let Ok(entry) = self.single() {
+      ValueDe::entry(entry).deserialize_any(visitor)
+    } else if self.node.entries.is_empty() && self.children().is_empty() {
+      visitor.visit_unit()
+    } else if self.properties().next().is_none() && self.children().is_empty() {
+      self.deserialize_seq(visitor)
+    } else {
+      self.deserialize_map(visitor)
+    }
+  }
+  forward_to_single! {
+    deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+    deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+    deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+    deserialize_byte_buf deserialize_identifier
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.single() {
+      Ok(Entry { value: Value::Null, .. }) => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    }
+  }
+  fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(self.elements())
+  }
+  fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    let mut items = Vec::new();
+    if self.arguments().next().is_some() {
+      items.push((ARGUMENTS, Item::Elements(self.arguments_elements())));
+    }
+    items.extend(self.properties_and_children());
+    visitor.visit_map(Items::new(items))
+  }
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let mut items = Vec::new();
+    for &field in fields {
+      let item = match field {
+        ARGUMENTS => Item::Elements(self.arguments_elements()),
+        NAME => Item::Str(Some(self.node.name())),
+        TYPE => Item::Str(self.node.type_hint()),
+        CHILDREN => Item::Elements(self.children_elements().collect()),
+        _ => match field
+          .parse::<usize>()
+          .ok()
+          .and_then(|i| Some((i, self.arguments().nth(i)?)))
+        {
+          Some((i, entry)) => Item::Argument(i, entry),
+          None => continue,
+        },
+      };
+      items.push((field, item));
+    }
+    items.extend(self.properties_and_children());
+    visitor.visit_map(Items::new(items))
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    if let (true, Some(variant)) = (self.typed, self.node.type_hint()) {
+      return visitor.visit_enum(Enum {
+        variant: Cow::Borrowed(variant),
+        content: Some(NodeDe {
+          node: self.node,
+          typed: false,
+        }),
+        context: None,
+      });
+    }
+    if let Ok(Entry {
+      value: Value::String(variant),
+      ..
+    }) = self.single()
+    {
+      return visitor.visit_enum(Enum::<NodeDe> {
+        variant: Cow::Borrowed(variant),
+        content: None,
+        context: None,
+      });
+    }
+    match self.children() {
+      [child] if self.node.entries.is_empty() => visitor.visit_enum(Enum {
+        variant: Cow::Borrowed(child.name()),
+        content: Some(NodeDe {
+          node: child,
+          typed: true,
+        }),
+        context: Some((Segment::Node(child.name().to_owned(), None), node_span(child))),
+      }),
+      _ => Err(de::Error::custom(
+        "expected a type annotation, a string argument, or a single child to choose an enum variant",
+      )),
+    }
+  }
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+}
+
+/// Nodes with the same name
+struct Group<'de, 'text> {
+  name: &'de str,
+  nodes: Vec<&'de Node<'text>>,
+}
+
+impl<'de, 'text> Group<'de, 'text> {
+  fn single(&self) -> Result<NodeDe<'de, 'text>, Error> {
+    match *self.nodes {
+      [node] => Ok(NodeDe { node, typed: true }),
+      _ => Err(de::Error::custom(format_args!(
+        "expected one `{}` node, found {}",
+        self.name,
+        self.nodes.len()
+      ))),
+    }
+  }
+  fn wrap(&self, error: Error) -> Error {
+    error.at(Segment::Node(self.name.to_owned(), None), node_span(self.nodes[0]))
+  }
+  /// Whether the nodes are the elements of a sequence, instead of a single
+  /// node containing them
+  fn is_list(&self) -> bool {
+    match *self.nodes {
+      [node] => {
+        let node = NodeDe { node, typed: true };
+        node.properties().next().is_some() || node.children().iter().any(|child| child.name() != "-")
+      }
+      _ => true,
+    }
+  }
+}
+
+macro_rules! forward_to_node {
+  ($($method:ident($($arg:ident: $type:ty),*);)*) => {$(
+    fn $method<V: Visitor<'de>>(self, $($arg: $type,)* visitor: V) -> Result<V::Value, Error> {
+      let node = self.single().map_err(|error| self.wrap(error))?;
+      node.$method($($arg,)* visitor).map_err(|error| self.wrap(error))
+    }
+  )*};
+}
+
+impl<'de> de::Deserializer<'de> for Group<'de, '_> {
+  type Error = Error;
+  forward_to_node! {
+    deserialize_any(); deserialize_bool(); deserialize_i8(); deserialize_i16(); deserialize_i32();
+    deserialize_i64(); deserialize_i128(); deserialize_u8(); deserialize_u16(); deserialize_u32();
+    deserialize_u64(); deserialize_u128(); deserialize_f32(); deserialize_f64(); deserialize_char();
+    deserialize_str(); deserialize_string(); deserialize_bytes(); deserialize_byte_buf();
+    deserialize_identifier(); deserialize_unit(); deserialize_unit_struct(name: &'static str);
+    deserialize_newtype_struct(name: &'static str); deserialize_map();
+    deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+    deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+  }
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.single() {
+      Ok(node) => node.deserialize_option(visitor).map_err(|error| self.wrap(error)),
+      Err(_) => visitor.visit_some(self),
+    }
+  }
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    if !self.is_list() {
+      return self
+        .single()?
+        .deserialize_seq(visitor)
+        .map_err(|error| self.wrap(error));
+    }
+    let several = self.nodes.len() > 1;
+    let elements = self.nodes.iter().enumerate();
+    visitor.visit_seq(
+      elements
+        .map(|(i, node)| Element::Grouped(several.then_some(i), node))
+        .collect::<Elements>(),
+    )
+  }
+  fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+}
+
+enum Element<'de, 'text> {
+  Argument(usize, &'de Entry<'text>),
+  /// `nodes[index]`
+  Child(&'de [Node<'text>], usize),
+  /// a node in a group, with its index if there are several
+  Grouped(Option<usize>, &'de Node<'text>),
+}
+
+/// A sequence of arguments or nodes, also usable as a deserializer
+struct Elements<'de, 'text>(std::vec::IntoIter<Element<'de, 'text>>);
+
+impl<'de, 'text> FromIterator<Element<'de, 'text>> for Elements<'de, 'text> {
+  fn from_iter<T: IntoIterator<Item = Element<'de, 'text>>>(iter: T) -> Self {
+    Self(iter.into_iter().collect::<Vec<_>>().into_iter())
+  }
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'de, '_> {
+  type Error = Error;
+  fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+    let Some(element) = self.0.next() else {
+      return Ok(None);
+    };
+    match element {
+      Element::Argument(i, entry) => seed
+        .deserialize(ValueDe::entry(entry))
+        .map_err(|error| error.at(Segment::Argument(i), entry_span(entry))),
+      Element::Child(nodes, i) => seed
+        .deserialize(NodeDe {
+          node: &nodes[i],
+          typed: true,
+        })
+        .map_err(|error| error.at(child_segment(nodes, i), node_span(&nodes[i]))),
+      Element::Grouped(index, node) => seed
+        .deserialize(NodeDe { node, typed: true })
+        .map_err(|error| error.at(Segment::Node(node.name().to_owned(), index), node_span(node))),
+    }
+    .map(Some)
+  }
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.0.len())
+  }
+}
+
+impl<'de> de::Deserializer<'de> for Elements<'de, '_> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(self)
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf option unit unit_struct newtype_struct seq tuple
+    tuple_struct map struct enum identifier ignored_any
+  }
+}
+
+/// A map value
+enum Item<'de, 'text> {
+  Property(&'de Entry<'text>),
+  Argument(usize, &'de Entry<'text>),
+  Group(Group<'de, 'text>),
+  Elements(Elements<'de, 'text>),
+  Str(Option<&'de str>),
+}
+
+struct Items<'de, 'text> {
+  items: std::vec::IntoIter<(&'de str, Item<'de, 'text>)>,
+  value: Option<Item<'de, 'text>>,
+}
+
+impl<'de, 'text> Items<'de, 'text> {
+  fn new(items: Vec<(&'de str, Item<'de, 'text>)>) -> Self {
+    Self {
+      items: items.into_iter(),
+      value: None,
+    }
+  }
+}
+
+impl<'de> de::MapAccess<'de> for Items<'de, '_> {
+  type Error = Error;
+  fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+    let Some((key, value)) = self.items.next() else {
+      return Ok(None);
+    };
+    self.value = Some(value);
+    seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some)
+  }
+  fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+    match self.value.take().expect("value requested before key") {
+      Item::Property(entry) => seed
+        .deserialize(ValueDe::entry(entry))
+        .map_err(|error| error.at(Segment::Property(entry.key().unwrap().to_owned()), entry_span(entry))),
+      Item::Argument(i, entry) => seed
+        .deserialize(ValueDe::entry(entry))
+        .map_err(|error| error.at(Segment::Argument(i), entry_span(entry))),
+      Item::Group(group) => seed.deserialize(group),
+      Item::Elements(elements) => seed.deserialize(elements),
+      Item::Str(text) => seed.deserialize(CowDe(text.map(Cow::Borrowed))),
+    }
+  }
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.items.len())
+  }
+}
+
+/// Text that's optionally there, like a type annotation
+struct CowDe<'de>(Option<Cow<'de, str>>);
+
+impl<'de> de::Deserializer<'de> for CowDe<'de> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.0 {
+      Some(Cow::Borrowed(text)) => visitor.visit_borrowed_str(text),
+      Some(Cow::Owned(text)) => visitor.visit_string(text),
+      None => visitor.visit_none(),
+    }
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.0 {
+      Some(text) => visitor.visit_some(CowDe(Some(text))),
+      None => visitor.visit_none(),
+    }
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf unit unit_struct newtype_struct seq tuple
+    tuple_struct map struct enum identifier ignored_any
+  }
+}
+
+/// An enum variant chosen by name, with optional contents
+struct Enum<'de, D> {
+  variant: Cow<'de, str>,
+  content: Option<D>,
+  /// path of the contents, if it's somewhere else
+  context: Option<(Segment, Option<Span>)>,
+}
+
+impl<D> Enum<'_, D> {
+  fn wrap(context: Option<(Segment, Option<Span>)>) -> impl FnOnce(Error) -> Error {
+    move |error| match context {
+      Some((segment, span)) => error.at(segment, span),
+      None => error,
+    }
+  }
+}
+
+impl<'de, D: de::Deserializer<'de, Error = Error>> de::EnumAccess<'de> for Enum<'de, D> {
+  type Error = Error;
+  type Variant = Self;
+  fn variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<(T::Value, Self), Error> {
+    let variant = seed.deserialize(CowDe(Some(self.variant.clone())))?;
+    Ok((variant, self))
+  }
+}
+
+impl<'de, D: de::Deserializer<'de, Error = Error>> de::VariantAccess<'de> for Enum<'de, D> {
+  type Error = Error;
+  fn unit_variant(self) -> Result<(), Error> {
+    Ok(())
+  }
+  fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+    match self.content {
+      Some(content) => seed.deserialize(content).map_err(Self::wrap(self.context)),
+      None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant")),
+    }
+  }
+  fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+    match self.content {
+      Some(content) => content
+        .deserialize_tuple(len, visitor)
+        .map_err(Self::wrap(self.context)),
+      None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant")),
+    }
+  }
+  fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+    match self.content {
+      Some(content) => content
+        .deserialize_struct("", fields, visitor)
+        .map_err(Self::wrap(self.context)),
+      None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant")),
+    }
+  }
+}
+
+/// Deserialize straight from parser events, without building a document
+///
+/// Child lists are read lazily as they're deserialized, so memory use stays
+/// close to the size of the output. See the [module docs](self#streaming) for
+/// how the mapping differs from documents.
+/// Parse errors are reported even if they're after everything that was
+/// needed.
+pub fn from_parser<'de, T: Deserialize<'de>>(parser: Parser<'de>) -> Result<T, Error> {
+  let mut events = Events {
+    parser: parser.spanned(),
+    peeked: None,
+    depth: 0,
+  };
+  let value = T::deserialize(StreamDocument(&mut events))?;
+  while events.next()?.is_some() {}
+  Ok(value)
+}
+
+/// Parser events with one event of lookahead
+struct Events<'de> {
+  parser: SpannedParser<'de>,
+  peeked: Option<(Event<'de>, EventSpan)>,
+  /// number of open children blocks
+  depth: usize,
+}
+
+/// A node up to the start of its children
+struct Header<'de> {
+  r#type: Option<Cow<'de, str>>,
+  name: Cow<'de, str>,
+  span: Span,
+  entries: Vec<StreamEntry<'de>>,
+  /// has a non-empty children block, which is next in the events
+  children: bool,
+  /// [`Events::depth`] outside of the children block
+  depth: usize,
+}
+
+#[derive(Clone)]
+struct StreamEntry<'de> {
+  r#type: Option<Cow<'de, str>>,
+  key: Option<Cow<'de, str>>,
+  value: Value<'de>,
+  span: Span,
+}
+
+impl<'de> Events<'de> {
+  fn peek(&mut self) -> Result<Option<&Event<'de>>, Error> {
+    if self.peeked.is_none() {
+      self.peeked = self.parser.next().transpose()?;
+    }
+    Ok(self.peeked.as_ref().map(|(event, _)| event))
+  }
+  fn next(&mut self) -> Result<Option<(Event<'de>, EventSpan)>, Error> {
+    let event = match self.peeked.take() {
+      Some(event) => Some(event),
+      None => self.parser.next().transpose()?,
+    };
+    match event {
+      Some((Event::Begin, _)) => self.depth += 1,
+      Some((Event::End, _)) => self.depth -= 1,
+      _ => {}
+    }
+    Ok(event)
+  }
+  /// Skip the rest of a children block
+  fn skip_to(&mut self, depth: usize) -> Result<(), Error> {
+    while self.depth > depth && self.next()?.is_some() {}
+    Ok(())
+  }
+  /// Name of the next sibling node, if there is one
+  fn peek_name(&mut self) -> Result<Option<&str>, Error> {
+    Ok(match self.peek()? {
+      Some(Event::Node { name, .. }) => Some(name),
+      _ => None,
+    })
+  }
+  /// Read the next sibling node, or the end of the siblings
+  fn node(&mut self) -> Result<Option<Header<'de>>, Error> {
+    let (r#type, name, span) = match self.next()? {
+      Some((Event::Node { r#type, name }, span)) => (r#type, name, span.span()),
+      // end of the children block or document
+      _ => return Ok(None),
+    };
+    let mut entries = Vec::new();
+    while let Some(Event::Entry { .. }) = self.peek()? {
+      if let Some((Event::Entry { r#type, key, value }, span)) = self.next()? {
+        let span = span.span();
+        entries.push(StreamEntry {
+          r#type,
+          key,
+          value,
+          span,
+        });
+      }
+    }
+    let depth = self.depth;
+    let mut children = false;
+    if let Some(Event::Begin) = self.peek()? {
+      self.next()?;
+      // treat empty blocks like no block
+      if let Some(Event::End) = self.peek()? {
+        self.next()?;
+      } else {
+        children = true;
+      }
+    }
+    Ok(Some(Header {
+      r#type,
+      name,
+      span,
+      entries,
+      children,
+      depth,
+    }))
+  }
+  /// Deserialize a node, then skip whatever's left of it
+  fn with_node<T>(
+    &mut self,
+    header: Header<'de>,
+    segment: Segment,
+    inner: impl FnOnce(StreamNode<'_, 'de>) -> Result<T, Error>,
+  ) -> Result<T, Error> {
+    let (depth, span) = (header.depth, header.span);
+    let node = StreamNode {
+      events: self,
+      header,
+      typed: true,
+    };
+    let value = inner(node).map_err(|error| error.at(segment, Some(span)))?;
+    self.skip_to(depth)?;
+    Ok(value)
+  }
+}
+
+/// An entry value, with its type annotation
+struct StreamValue<'de> {
+  value: Value<'de>,
+  r#type: Option<Cow<'de, str>>,
+}
+
+impl<'de> From<StreamEntry<'de>> for StreamValue<'de> {
+  fn from(entry: StreamEntry<'de>) -> Self {
+    Self {
+      value: entry.value,
+      r#type: entry.r#type,
+    }
+  }
+}
+
+impl<'de> de::Deserializer<'de> for StreamValue<'de> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.value {
+      Value::String(Cow::Borrowed(value)) => visitor.visit_borrowed_str(value),
+      Value::String(Cow::Owned(value)) => visitor.visit_string(value),
+      value => visit_value(&value, visitor),
+    }
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.value {
+      Value::Null => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    }
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    match (self.r#type, self.value) {
+      (Some(variant), value) => visitor.visit_enum(Enum {
+        variant,
+        content: Some(StreamValue { value, r#type: None }),
+        context: None,
+      }),
+      (None, Value::String(variant)) => visitor.visit_enum(Enum::<StreamValue> {
+        variant,
+        content: None,
+        context: None,
+      }),
+      (None, value) => Err(de::Error::invalid_type(
+        unexpected(&value),
+        &"a string or type annotation",
+      )),
+    }
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+    identifier ignored_any
+  }
+}
+
+/// A node being read from the events
+struct StreamNode<'a, 'de> {
+  events: &'a mut Events<'de>,
+  header: Header<'de>,
+  typed: bool,
+}
+
+impl<'a, 'de> StreamNode<'a, 'de> {
+  fn is_single(&self) -> bool {
+    matches!(&*self.header.entries, [entry] if entry.key.is_none()) && !self.header.children
+  }
+  /// The only argument, for reading the node as a single value
+  fn single(mut self) -> Result<StreamEntry<'de>, Error> {
+    if self.is_single() {
+      return Ok(self.header.entries.pop().unwrap());
+    }
+    let arguments = self.header.entries.iter().filter(|entry| entry.key.is_none()).count();
+    Err(de::Error::custom(format_args!(
+      "expected a single argument, found {} arguments, {} properties, and {}",
+      arguments,
+      self.header.entries.len() - arguments,
+      if self.header.children {
+        "children"
+      } else {
+        "no children"
+      },
+    )))
+  }
+  fn has_properties(&self) -> bool {
+    self.header.entries.iter().any(|entry| entry.key.is_some())
+  }
+  /// Split off the arguments, keeping the properties with the last of each key
+  fn split_entries(&mut self) -> (Vec<StreamEntry<'de>>, Vec<StreamEntry<'de>>) {
+    let (arguments, mut properties): (Vec<_>, Vec<_>) =
+      self.header.entries.drain(..).partition(|entry| entry.key.is_none());
+    let mut seen = HashSet::new();
+    properties.reverse();
+    properties.retain(|entry| seen.insert(entry.key.clone()));
+    properties.reverse();
+    (arguments, properties)
+  }
+  fn elements(self, arguments: Vec<StreamEntry<'de>>) -> StreamElements<'a, 'de> {
+    StreamElements {
+      children: self.header.children,
+      events: self.events,
+      arguments: arguments.into_iter().enumerate(),
+      seen: HashMap::new(),
+    }
+  }
+  fn items(self, items: Vec<(Cow<'de, str>, StreamItem<'de>)>) -> StreamItems<'a, 'de> {
+    StreamItems {
+      children: self.header.children,
+      events: self.events,
+      items: items.into_iter(),
+      value: None,
+    }
+  }
+}
+
+fn property_items(properties: Vec<StreamEntry<'_>>) -> impl Iterator<Item = (Cow<'_, str>, StreamItem<'_>)> {
+  properties
+    .into_iter()
+    .map(|entry| (entry.key.clone().unwrap(), StreamItem::Property(entry)))
+}
+
+macro_rules! forward_to_stream_single {
+  ($($method:ident)*) => {$(
+    fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      StreamValue::from(self.single()?).$method(visitor)
+    }
+  )*};
+}
+
+impl<'de> de::Deserializer<'de> for StreamNode<'_, 'de> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    if self.is_single() {
+      StreamValue::from(self.single()?).deserialize_any(visitor)
+    } else if self.header.entries.is_empty() && !self.header.children {
+      visitor.visit_unit()
+    } else if !self.has_properties() && !self.header.children {
+      self.deserialize_seq(visitor)
+    } else {
+      self.deserialize_map(visitor)
+    }
+  }
+  forward_to_stream_single! {
+    deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+    deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+    deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+    deserialize_byte_buf deserialize_identifier
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match &*self.header.entries {
+      [StreamEntry {
+        key: None,
+        value: Value::Null,
+        ..
+      }] if !self.header.children => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    }
+  }
+  fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  fn deserialize_seq<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+    let (arguments, _) = self.split_entries();
+    visitor.visit_seq(self.elements(arguments))
+  }
+  fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_map<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+    let (arguments, properties) = self.split_entries();
+    let mut items = Vec::new();
+    if !arguments.is_empty() {
+      items.push((Cow::Borrowed(ARGUMENTS), StreamItem::Arguments(arguments)));
+    }
+    items.extend(property_items(properties));
+    visitor.visit_map(self.items(items))
+  }
+  fn deserialize_struct<V: Visitor<'de>>(
+    mut self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let (arguments, properties) = self.split_entries();
+    let mut items = Vec::new();
+    for &field in fields {
+      let item = match field {
+        ARGUMENTS => StreamItem::Arguments(arguments.clone()),
+        NAME => StreamItem::Str(Some(self.header.name.clone())),
+        TYPE => StreamItem::Str(self.header.r#type.clone()),
+        CHILDREN if self.header.children => {
+          self.header.children = false;
+          StreamItem::Children
+        }
+        CHILDREN => StreamItem::Arguments(Vec::new()),
+        _ => match field
+          .parse::<usize>()
+          .ok()
+          .and_then(|i| Some((i, arguments.get(i)?.clone())))
+        {
+          Some((i, entry)) => StreamItem::Argument(i, entry),
+          None => continue,
+        },
+      };
+      items.push((Cow::Borrowed(field), item));
+    }
+    items.extend(property_items(properties));
+    visitor.visit_map(self.items(items))
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    mut self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    if let (true, Some(variant)) = (self.typed, self.header.r#type.take()) {
+      return visitor.visit_enum(Enum {
+        variant,
+        content: Some(StreamNode { typed: false, ..self }),
+        context: None,
+      });
+    }
+    if self.is_single() && matches!(self.header.entries[0].value, Value::String(_)) {
+      let Value::String(variant) = self.single()?.value else {
+        unreachable!()
+      };
+      return visitor.visit_enum(Enum::<StreamNode> {
+        variant,
+        content: None,
+        context: None,
+      });
+    }
+    if !self.header.entries.is_empty() || !self.header.children {
+      return Err(de::Error::custom(
+        "expected a type annotation, a string argument, or a single child to choose an enum variant",
+      ));
+    }
+    // the parent skips any other children
+    let child = self.events.node()?.unwrap();
+    let context = Some((Segment::Node(child.name.to_string(), None), Some(child.span)));
+    visitor.visit_enum(Enum {
+      variant: child.name.clone(),
+      content: Some(StreamNode {
+        events: self.events,
+        header: child,
+        typed: true,
+      }),
+      context,
+    })
+  }
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+}
+
+/// Arguments followed by children, or top-level nodes
+struct StreamElements<'a, 'de> {
+  events: &'a mut Events<'de>,
+  arguments: std::iter::Enumerate<std::vec::IntoIter<StreamEntry<'de>>>,
+  children: bool,
+  /// number of children with each name so far
+  seen: HashMap<Cow<'de, str>, usize>,
+}
+
+impl<'de> de::SeqAccess<'de> for StreamElements<'_, 'de> {
+  type Error = Error;
+  fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+    if let Some((i, entry)) = self.arguments.next() {
+      let span = entry.span;
+      return seed
+        .deserialize(StreamValue::from(entry))
+        .map(Some)
+        .map_err(|error| error.at(Segment::Argument(i), Some(span)));
+    }
+    if !self.children {
+      return Ok(None);
+    }
+    let Some(header) = self.events.node()? else {
+      self.children = false;
+      return Ok(None);
+    };
+    let seen = self.seen.entry(header.name.clone()).or_default();
+    let segment = Segment::Node(header.name.to_string(), (*seen > 0).then_some(*seen));
+    *seen += 1;
+    self
+      .events
+      .with_node(header, segment, |node| seed.deserialize(node))
+      .map(Some)
+  }
+  fn size_hint(&self) -> Option<usize> {
+    match self.children {
+      true => None,
+      false => Some(self.arguments.len()),
+    }
+  }
+}
+
+impl<'de> de::Deserializer<'de> for StreamElements<'_, 'de> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(self)
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf option unit unit_struct newtype_struct seq tuple
+    tuple_struct map struct enum identifier ignored_any
+  }
+}
+
+/// A map value
+enum StreamItem<'de> {
+  Property(StreamEntry<'de>),
+  Argument(usize, StreamEntry<'de>),
+  Arguments(Vec<StreamEntry<'de>>),
+  /// all the children left
+  Children,
+  /// the first node of a group of nodes with the same name
+  Group(Header<'de>),
+  Str(Option<Cow<'de, str>>),
+}
+
+/// Buffered items followed by children grouped by name
+struct StreamItems<'a, 'de> {
+  events: &'a mut Events<'de>,
+  items: std::vec::IntoIter<(Cow<'de, str>, StreamItem<'de>)>,
+  children: bool,
+  value: Option<StreamItem<'de>>,
+}
+
+impl<'de> de::MapAccess<'de> for StreamItems<'_, 'de> {
+  type Error = Error;
+  fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+    let (key, value) = match self.items.next() {
+      Some(item) => item,
+      None if self.children => match self.events.node()? {
+        Some(header) => (header.name.clone(), StreamItem::Group(header)),
+        None => {
+          self.children = false;
+          return Ok(None);
+        }
+      },
+      None => return Ok(None),
+    };
+    self.value = Some(value);
+    seed.deserialize(CowDe(Some(key))).map(Some)
+  }
+  fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+    match self.value.take().expect("value requested before key") {
+      StreamItem::Property(mut entry) => {
+        let (key, span) = (entry.key.take().unwrap(), entry.span);
+        seed
+          .deserialize(StreamValue::from(entry))
+          .map_err(|error| error.at(Segment::Property(key.into_owned()), Some(span)))
+      }
+      StreamItem::Argument(i, entry) => {
+        let span = entry.span;
+        seed
+          .deserialize(StreamValue::from(entry))
+          .map_err(|error| error.at(Segment::Argument(i), Some(span)))
+      }
+      StreamItem::Arguments(arguments) => seed.deserialize(StreamElements {
+        events: self.events,
+        arguments: arguments.into_iter().enumerate(),
+        children: false,
+        seen: HashMap::new(),
+      }),
+      StreamItem::Children => seed.deserialize(StreamElements {
+        events: self.events,
+        arguments: Vec::new().into_iter().enumerate(),
+        children: true,
+        seen: HashMap::new(),
+      }),
+      StreamItem::Group(header) => seed.deserialize(StreamGroup {
+        events: self.events,
+        first: header,
+      }),
+      StreamItem::Str(text) => seed.deserialize(CowDe(text)),
+    }
+  }
+}
+
+/// Consecutive nodes with the same name
+struct StreamGroup<'a, 'de> {
+  events: &'a mut Events<'de>,
+  first: Header<'de>,
+}
+
+impl<'de> StreamGroup<'_, 'de> {
+  /// Deserialize as a single node
+  fn single<T>(self, inner: impl FnOnce(StreamNode<'_, 'de>) -> Result<T, Error>) -> Result<T, Error> {
+    let name = self.first.name.clone();
+    let span = self.first.span;
+    let segment = Segment::Node(name.to_string(), None);
+    let value = self.events.with_node(self.first, segment.clone(), inner)?;
+    if self.events.peek_name()? == Some(&name) {
+      let error: Error = de::Error::custom(format_args!("expected one `{name}` node, found more"));
+      return Err(error.at(segment, Some(span)));
+    }
+    Ok(value)
+  }
+  /// Whether the nodes are the elements of a sequence, instead of a single
+  /// node containing them
+  fn is_list(&mut self) -> Result<bool, Error> {
+    let first = &self.first;
+    Ok(if first.entries.iter().any(|entry| entry.key.is_some()) {
+      true
+    } else if first.children {
+      self.events.peek_name()? != Some("-")
+    } else {
+      self.events.peek_name()? == Some(&first.name)
+    })
+  }
+}
+
+macro_rules! forward_to_stream_node {
+  ($($method:ident($($arg:ident: $type:ty),*);)*) => {$(
+    fn $method<V: Visitor<'de>>(self, $($arg: $type,)* visitor: V) -> Result<V::Value, Error> {
+      self.single(|node| node.$method($($arg,)* visitor))
+    }
+  )*};
+}
+
+impl<'de> de::Deserializer<'de> for StreamGroup<'_, 'de> {
+  type Error = Error;
+  forward_to_stream_node! {
+    deserialize_any(); deserialize_bool(); deserialize_i8(); deserialize_i16(); deserialize_i32();
+    deserialize_i64(); deserialize_i128(); deserialize_u8(); deserialize_u16(); deserialize_u32();
+    deserialize_u64(); deserialize_u128(); deserialize_f32(); deserialize_f64(); deserialize_char();
+    deserialize_str(); deserialize_string(); deserialize_bytes(); deserialize_byte_buf();
+    deserialize_identifier(); deserialize_unit(); deserialize_unit_struct(name: &'static str);
+    deserialize_newtype_struct(name: &'static str); deserialize_map();
+    deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+    deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.events.peek_name()? == Some(&self.first.name) {
+      true => visitor.visit_some(self),
+      false => self.single(|node| node.deserialize_option(visitor)),
+    }
+  }
+  fn deserialize_seq<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+    if !self.is_list()? {
+      return self.single(|node| node.deserialize_seq(visitor));
+    }
+    visitor.visit_seq(StreamGroupElements {
+      events: self.events,
+      name: self.first.name.clone(),
+      first: Some(self.first),
+      index: 0,
+    })
+  }
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    let name = self.first.name.clone();
+    let mut next = Some(self.first);
+    while let Some(header) = next {
+      self.events.skip_to(header.depth)?;
+      next = match self.events.peek_name()? == Some(&name) {
+        true => self.events.node()?,
+        false => None,
+      };
+    }
+    visitor.visit_unit()
+  }
+  fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+}
+
+struct StreamGroupElements<'a, 'de> {
+  events: &'a mut Events<'de>,
+  name: Cow<'de, str>,
+  /// the first node, before it's been deserialized
+  first: Option<Header<'de>>,
+  index: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for StreamGroupElements<'_, 'de> {
+  type Error = Error;
+  fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+    let header = match self.first.take() {
+      Some(header) => header,
+      None if self.events.peek_name()? == Some(&self.name) => self.events.node()?.unwrap(),
+      None => return Ok(None),
+    };
+    let segment = Segment::Node(self.name.to_string(), (self.index > 0).then_some(self.index));
+    self.index += 1;
+    self
+      .events
+      .with_node(header, segment, |node| seed.deserialize(node))
+      .map(Some)
+  }
+}
+
+/// The top-level nodes
+struct StreamDocument<'a, 'de>(&'a mut Events<'de>);
+
+impl<'de> de::Deserializer<'de> for StreamDocument<'_, 'de> {
+  type Error = Error;
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_map(visitor)
+  }
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_some(self)
+  }
+  fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(StreamElements {
+      events: self.0,
+      arguments: Vec::new().into_iter().enumerate(),
+      children: true,
+      seen: HashMap::new(),
+    })
+  }
+  fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_seq(visitor)
+  }
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_map(StreamItems {
+      events: self.0,
+      items: Vec::new().into_iter(),
+      children: true,
+      value: None,
+    })
+  }
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    self.deserialize_map(visitor)
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    let Some(header) = self.0.node()? else {
+      return Err(de::Error::invalid_length(0, &"a single node for an enum"));
+    };
+    let value = visitor.visit_enum(Enum {
+      variant: header.name.clone(),
+      content: Some(StreamNode {
+        events: self.0,
+        header,
+        typed: true,
+      }),
+      context: None,
+    })?;
+    self.0.skip_to(0)?;
+    match self.0.peek()? {
+      Some(_) => Err(de::Error::custom("expected a single node for an enum, found more")),
+      None => Ok(value),
+    }
+  }
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf identifier
+  }
+}
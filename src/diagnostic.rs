@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Human-readable rendering of parse errors, start at [`Diagnostic`]
+
+use std::fmt;
+
+use crate::stream::{Error, ErrorKind};
+
+/// Is this character a kdl `newline`
+fn newline(ch: char) -> bool {
+  matches!(ch, '\u{A}'..='\u{D}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+/// A line and column position in source text
+///
+/// Lines are split on every kdl `newline`, with `\r\n` counting as one.
+/// All fields are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Location {
+  /// Line number
+  pub line: usize,
+  /// Column, counted in characters
+  pub column: usize,
+  /// Column, counted in UTF-8 bytes
+  pub column_utf8: usize,
+  /// Column, counted in UTF-16 code units
+  pub column_utf16: usize,
+}
+
+impl Location {
+  /// Find the location of a byte position in some text
+  ///
+  /// Positions past the end of the text are clamped to the end, and positions
+  /// inside of a character are moved to the start of that character.
+  pub fn of(text: &str, offset: usize) -> Self {
+    let (line, start) = line_start(text, offset);
+    let prefix = &text[start..floor_char_boundary(text, offset)];
+    Self {
+      line,
+      column: prefix.chars().count() + 1,
+      column_utf8: prefix.len() + 1,
+      column_utf16: prefix.encode_utf16().count() + 1,
+    }
+  }
+  /// Find the byte position of a line and character column,
+  /// the inverse of [`Location::of`]
+  ///
+  /// Returns `None` if the line doesn't exist, columns past the end of the
+  /// line are clamped to the end.
+  pub fn offset(text: &str, line: usize, column: usize) -> Option<usize> {
+    let mut start = 0;
+    for _ in 1..line {
+      start = next_line(text, start)?;
+    }
+    let end = line_end(text, start);
+    Some(
+      text[start..end]
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(end, |(at, _)| start + at),
+    )
+  }
+}
+
+impl fmt::Display for Location {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+  let mut offset = offset.min(text.len());
+  while !text.is_char_boundary(offset) {
+    offset -= 1;
+  }
+  offset
+}
+
+/// End of the line starting at `start`, excluding the newline
+fn line_end(text: &str, start: usize) -> usize {
+  text[start..].find(newline).map_or(text.len(), |at| start + at)
+}
+
+/// Start of the line after the one starting at `start`
+fn next_line(text: &str, start: usize) -> Option<usize> {
+  let end = line_end(text, start);
+  let mut chars = text[end..].chars();
+  let ch = chars.next()?;
+  let mut next = end + ch.len_utf8();
+  if ch == '\r' && chars.next() == Some('\n') {
+    next += 1;
+  }
+  Some(next)
+}
+
+/// Line number and start of the line containing `offset`
+fn line_start(text: &str, offset: usize) -> (usize, usize) {
+  let mut line = 1;
+  let mut start = 0;
+  while let Some(next) = next_line(text, start) {
+    if next > offset {
+      break;
+    }
+    line += 1;
+    start = next;
+  }
+  (line, start)
+}
+
+/// Longer explanation for an error kind, if there's more to say
+fn help(kind: ErrorKind) -> Option<&'static str> {
+  Some(match kind {
+    ErrorKind::ExpectedSpace => "arguments, properties, and children blocks must be separated by whitespace",
+    ErrorKind::ExpectedComment => "a `\\` can only be followed by whitespace and a comment before the newline",
+    ErrorKind::ExpectedNewline => "multi-line strings must start with a newline after the opening quotes",
+    ErrorKind::UnexpectedCloseBracket => "there's no children block open here",
+    ErrorKind::UnexpectedNewline => "use a multi-line `\"\"\"` string or a `\\n` escape",
+    ErrorKind::IntegerOverflow => "integers are limited to the range of a 128-bit signed integer here",
+    ErrorKind::BadKeyword => "valid keywords are `#true`, `#false`, `#null`, `#inf`, `#-inf`, and `#nan`",
+    ErrorKind::BadIdentifier => "keyword names like `true` or `null` need to be quoted when used as strings",
+    ErrorKind::BadIndent => "every line must start with the same whitespace as the closing line",
+    ErrorKind::MultipleChildren => "merge the blocks, or comment one out with `/-`",
+    ErrorKind::BannedChar => "this character is never allowed in a kdl document, even in strings",
+    ErrorKind::InvalidEncoding => "kdl documents must be utf-8, or utf-16 starting with a byte order mark",
+    ErrorKind::Utf16 => "transcode it to utf-8 with `decode`, or parse it with `Document::parse_bytes`",
+    _ => return None,
+  })
+}
+
+/// A parse error together with its source text, for displaying to users
+///
+/// The [`Display`](fmt::Display) output looks like:
+/// ```text
+/// error: expected whitespace
+///  --> config.kdl:1:14
+///   |
+/// 1 | node "string"1
+///   |              ^
+///   = help: arguments, properties, and children blocks must be separated by whitespace
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Diagnostic<'a> {
+  error: &'a Error,
+  text: &'a str,
+  file: Option<&'a str>,
+}
+
+impl<'a> Diagnostic<'a> {
+  /// Create a diagnostic for an error in some text
+  pub fn new(error: &'a Error, text: &'a str) -> Self {
+    Self {
+      error,
+      text,
+      file: None,
+    }
+  }
+  /// Set the file name shown in the diagnostic
+  pub fn with_file(self, file: &'a str) -> Self {
+    Self {
+      file: Some(file),
+      ..self
+    }
+  }
+  /// The error being displayed
+  pub fn error(&self) -> &'a Error {
+    self.error
+  }
+  /// Byte position the diagnostic points at,
+  /// errors at the end of the file point after the last character
+  pub fn offset(&self) -> usize {
+    floor_char_boundary(self.text, self.error.offset().unwrap_or(self.text.len()))
+  }
+  /// Line and column the diagnostic points at
+  pub fn location(&self) -> Location {
+    Location::of(self.text, self.offset())
+  }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let offset = self.offset();
+    let location = self.location();
+    let (_, start) = line_start(self.text, offset);
+    let line = &self.text[start..line_end(self.text, start)];
+    let gutter = location.line.to_string().len();
+    match self.error {
+      Error::BannedChar(ch, _) => writeln!(f, "error: {} {ch:?}", self.error.kind())?,
+      Error::Io(error) => writeln!(f, "error: {}: {error}", self.error.kind())?,
+      _ => writeln!(f, "error: {}", self.error.kind())?,
+    }
+    writeln!(f, "{:gutter$}--> {}:{location}", "", self.file.unwrap_or("<input>"))?;
+    writeln!(f, "{:gutter$} |", "")?;
+    writeln!(f, "{} | {}", location.line, VisibleLine(line))?;
+    write!(f, "{:gutter$} | ", "")?;
+    // keep tabs so the caret lines up in terminals, errors in a line break
+    // point just past the line
+    for ch in line[..(offset - start).min(line.len())].chars() {
+      f.write_str(if ch == '\t' { "\t" } else { " " })?;
+    }
+    f.write_str("^")?;
+    if let Some(help) = help(self.error.kind()) {
+      write!(f, "\n{:gutter$} = help: {help}", "")?;
+    }
+    Ok(())
+  }
+}
+
+/// A source line with control characters replaced, so they don't mess up the
+/// terminal (each one stays a single character wide)
+struct VisibleLine<'a>(&'a str);
+impl fmt::Display for VisibleLine<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for ch in self.0.chars() {
+      if ch != '\t' && ch.is_control() {
+        f.write_str("\u{FFFD}")?;
+      } else {
+        fmt::Display::fmt(&ch, f)?;
+      }
+    }
+    Ok(())
+  }
+}
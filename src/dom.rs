@@ -2,14 +2,18 @@
 //! document tree structures, start at [`Document`]
 
 use std::borrow::Cow;
-use std::cell::Cell;
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
 use std::fmt;
 use std::num::FpCategory;
 use std::ops::{Index, IndexMut};
 
-use crate::stream::{Error, Event, Parser};
+use crate::convert::{self, FromNode, FromValue};
+use crate::cursor::{DocumentCursor, DocumentCursorMut};
+use crate::path::{Key, Path};
+use crate::stream::{decode, EntrySpan, Error, Event, EventSpan, Literal, Parser, Span, Version, WriteError, Writer};
+use crate::visit::{self, Edit, Flow, Visit, VisitMut};
 use crate::{cow_static, IdentDisplay};
 
 fn maybe_debug<T: fmt::Debug>(value: Option<&T>) -> &dyn fmt::Debug {
@@ -19,6 +23,19 @@ fn maybe_debug<T: fmt::Debug>(value: Option<&T>) -> &dyn fmt::Debug {
   }
 }
 
+/// Source information that's ignored when comparing or hashing
+#[derive(Clone, Copy)]
+struct Meta<T>(Option<T>);
+impl<T> PartialEq for Meta<T> {
+  fn eq(&self, _: &Self) -> bool {
+    true
+  }
+}
+impl<T> Eq for Meta<T> {}
+impl<T> std::hash::Hash for Meta<T> {
+  fn hash<H: std::hash::Hasher>(&self, _: &mut H) {}
+}
+
 /// A `document` or `nodes` element, a container of [`Node`]
 #[derive(Default, Clone, PartialEq, Eq, Hash)]
 pub struct Document<'text> {
@@ -53,9 +70,274 @@ impl<'text> Document<'text> {
   {
     self.nodes.iter_mut().filter(move |node| node.name() == name)
   }
+  /// Get the value of an argument or property by path
+  pub fn get_path(&self, path: &Path) -> Option<&Value<'text>> {
+    let ((name, index), parents) = path.nodes.split_last()?;
+    let mut document = self;
+    for (name, index) in parents {
+      document = document.get(name).nth(*index)?.children.as_ref()?;
+    }
+    let node = document.get(name).nth(*index)?;
+    Some(&node.entry(&path.key)?.value)
+  }
+  /// Set the value of an argument or property by path, returning the old
+  /// value if there was one
+  ///
+  /// Missing nodes are created, and so are `#null` arguments before a
+  /// missing argument. Replaced values keep their type annotation. Does
+  /// nothing if the path has no nodes.
+  pub fn set_path(&mut self, path: &Path, value: Value<'text>) -> Option<Value<'text>> {
+    let ((name, index), parents) = path.nodes.split_last()?;
+    let mut document = self;
+    for (name, index) in parents {
+      document = document
+        .path_node(name, *index)
+        .children
+        .get_or_insert_with(Document::new);
+    }
+    let node = document.path_node(name, *index);
+    if let Some(entry) = node.entry_mut(&path.key) {
+      return Some(std::mem::replace(&mut entry.value, value));
+    }
+    match &path.key {
+      Key::Argument(index) => {
+        let arguments = node.entries.iter().filter(|entry| entry.key.is_none()).count();
+        node
+          .entries
+          .extend((arguments..*index).map(|_| Entry::new_value(Value::Null)));
+        node.entries.push(Entry::new_value(value));
+      }
+      Key::Property(name) => node.entries.push(Entry::new_prop(name.clone(), value)),
+    }
+    None
+  }
+  /// The node at an index among the ones with a name, creating it and any
+  /// before it if they're missing
+  fn path_node(&mut self, name: &str, index: usize) -> &mut Node<'text> {
+    for _ in self.get(name).count()..=index {
+      self.nodes.push(Node::new(name.to_owned()));
+    }
+    self.get_mut(name).nth(index).unwrap()
+  }
+  /// Remove an argument or property by path, returning its value
+  ///
+  /// Later arguments move down by one. Every copy of a repeated property is
+  /// removed, and the one that was in effect is returned.
+  pub fn remove_path(&mut self, path: &Path) -> Option<Value<'text>> {
+    let ((name, index), parents) = path.nodes.split_last()?;
+    let mut document = self;
+    for (name, index) in parents {
+      document = document.get_mut(name).nth(*index)?.children.as_mut()?;
+    }
+    let node = document.get_mut(name).nth(*index)?;
+    let position = match &path.key {
+      Key::Argument(index) => {
+        let mut arguments = node.entries.iter().enumerate().filter(|(_, entry)| entry.key.is_none());
+        arguments.nth(*index)?.0
+      }
+      Key::Property(name) => node.entries.iter().rposition(|entry| entry.key() == Some(name))?,
+    };
+    let removed = node.entries.remove(position);
+    if let Key::Property(name) = &path.key {
+      node.entries.retain(|entry| entry.key() != Some(name));
+    }
+    Some(removed.value)
+  }
+  /// Every argument and property in the document with its path, in order
+  ///
+  /// [`Document::unflatten`] builds the document back from them, without
+  /// type annotations or nodes that don't have any entries in them.
+  pub fn flatten(&self) -> Vec<(Path, Value<'text>)> {
+    let mut values = Vec::new();
+    self.flatten_into(&mut Vec::new(), &mut values);
+    values
+  }
+  fn flatten_into(&self, parents: &mut Vec<(String, usize)>, values: &mut Vec<(Path, Value<'text>)>) {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for node in &self.nodes {
+      let index = seen.entry(node.name()).or_default();
+      parents.push((node.name().to_owned(), *index));
+      *index += 1;
+      let mut arguments = 0;
+      for entry in &node.entries {
+        let key = match entry.key() {
+          Some(key) => Key::Property(key.to_owned()),
+          None => {
+            arguments += 1;
+            Key::Argument(arguments - 1)
+          }
+        };
+        let path = Path {
+          nodes: parents.clone(),
+          key,
+        };
+        values.push((path, entry.value.clone()));
+      }
+      if let Some(children) = &node.children {
+        children.flatten_into(parents, values);
+      }
+      parents.pop();
+    }
+  }
+  /// Build a document by setting every path to its value in order, see
+  /// [`Document::flatten`]
+  pub fn unflatten(values: impl IntoIterator<Item = (Path, Value<'text>)>) -> Self {
+    let mut document = Self::new();
+    for (path, value) in values {
+      document.set_path(&path, value);
+    }
+    document
+  }
+  /// A cursor at the first node, if there is one, see
+  /// [`cursor`](crate::cursor)
+  pub fn cursor(&self) -> Option<DocumentCursor<'_, 'text>> {
+    DocumentCursor::new(self, &[0])
+  }
+  /// A cursor at a node, if it exists
+  pub fn cursor_at(&self, path: &IndexPath) -> Option<DocumentCursor<'_, 'text>> {
+    DocumentCursor::new(self, &path.nodes)
+  }
+  /// A cursor that can change the document at the first node, if there is
+  /// one, see [`cursor`](crate::cursor)
+  pub fn cursor_mut(&mut self) -> Option<DocumentCursorMut<'_, 'text>> {
+    DocumentCursorMut::new(self, &[0])
+  }
+  /// A cursor that can change the document at a node, if it exists
+  pub fn cursor_mut_at(&mut self, path: &IndexPath) -> Option<DocumentCursorMut<'_, 'text>> {
+    DocumentCursorMut::new(self, &path.nodes)
+  }
+  /// Walk every node and entry in order, see the [`visit`] module
+  pub fn visit<'a>(&'a self, visitor: &mut impl Visit<'a, 'text>) {
+    visit::walk(self, visitor, &mut IndexPath::default());
+  }
+  /// Walk every node and entry in order, changing them as it goes, see
+  /// the [`visit`] module
+  pub fn visit_mut(&mut self, visitor: &mut impl VisitMut<'text>) {
+    visit::walk_mut(self, visitor, &mut IndexPath::default());
+  }
+  /// Keep only the nodes at any depth that pass a check, along with their
+  /// children
+  ///
+  /// Nodes are checked before their children, and the children of removed
+  /// nodes aren't checked.
+  pub fn retain_recursive(&mut self, keep: impl FnMut(&Node<'text>) -> bool) {
+    struct Retain<F>(F);
+    impl<'text, F: FnMut(&Node<'text>) -> bool> VisitMut<'text> for Retain<F> {
+      fn enter_node(&mut self, node: &mut Node<'text>, _: visit::Context) -> Edit<'text> {
+        match (self.0)(node) {
+          true => Edit::Continue,
+          false => Edit::Remove,
+        }
+      }
+    }
+    self.visit_mut(&mut Retain(keep));
+  }
+  /// Change the value of every argument and property at any depth
+  pub fn map_values(&mut self, map: impl FnMut(Value<'text>) -> Value<'text>) {
+    struct Map<F>(F);
+    impl<'text, F: FnMut(Value<'text>) -> Value<'text>> VisitMut<'text> for Map<F> {
+      fn entry(&mut self, entry: &mut Entry<'text>, _: visit::Context) -> Flow {
+        entry.value = (self.0)(std::mem::replace(&mut entry.value, Value::Null));
+        Flow::Continue
+      }
+    }
+    self.visit_mut(&mut Map(map));
+  }
+  /// Write the document as text in a specific version of the spec
+  ///
+  /// For [`Version::V2`] this is the same as the [`Display`](fmt::Display)
+  /// output, other versions can fail if a value can't be represented in them.
+  pub fn write_as(&self, f: &mut impl fmt::Write, version: Version) -> Result<(), WriteError> {
+    self.write_nodes(f, &mut Writer::new(version))
+  }
+  fn write_nodes(&self, f: &mut impl fmt::Write, writer: &mut Writer) -> Result<(), WriteError> {
+    for node in &self.nodes {
+      node.write(f, writer)?;
+    }
+    Ok(())
+  }
+  /// Find the innermost node containing a byte position in the source text,
+  /// and the entry at that position if there is one
+  ///
+  /// Only works on documents with spans, see [`Document::parse_spanned`].
+  pub fn node_at(&self, offset: usize) -> Option<IndexPath> {
+    let mut path = IndexPath::default();
+    let mut document = self;
+    while let Some((index, node)) = document
+      .nodes
+      .iter()
+      .enumerate()
+      .find(|(_, node)| node.span().is_some_and(|span| span.span.contains(offset)))
+    {
+      path.nodes.push(index);
+      path.entry = node
+        .entries
+        .iter()
+        .position(|entry| entry.span().is_some_and(|span| span.span().contains(offset)));
+      match &node.children {
+        Some(children) if path.entry.is_none() => document = children,
+        _ => break,
+      }
+    }
+    (!path.nodes.is_empty()).then_some(path)
+  }
+  /// Convert the only node with a name
+  ///
+  /// A missing node is an error unless `T` is an [`Option`], and so are
+  /// several nodes with the name. See [`convert`] for the conversions.
+  pub fn node<'a, T: FromNode<'a>>(&'a self, name: &str) -> Result<T, convert::Error> {
+    convert::only(&self.nodes, name)?
+      .or_else(T::from_missing)
+      .ok_or_else(|| convert::Error::new(format_args!("missing node `{name}`")))
+  }
   pub fn parse(text: &'text str) -> Result<Self, Error> {
     Ok(Parser::new(text).collect::<Result<Vec<_>, _>>()?.into_iter().collect())
   }
+  /// Parse a document from bytes, see [`decode`] for the accepted encodings
+  ///
+  /// UTF-8 input is borrowed, transcoded UTF-16 input is owned.
+  pub fn parse_bytes(bytes: &'text [u8]) -> Result<Self, Error> {
+    match decode(bytes)? {
+      Cow::Borrowed(text) => Self::parse(text),
+      Cow::Owned(text) => Ok(Document::parse(&text)?.into_owned()),
+    }
+  }
+  /// Parse a document, keeping the source positions of every node and entry
+  pub fn parse_spanned(text: &'text str) -> Result<Self, Error> {
+    Ok(
+      Parser::new(text)
+        .spanned()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .collect(),
+    )
+  }
+  /// Parse a document, keeping how every value was written, see
+  /// [`Parser::literals`]
+  ///
+  /// Values that aren't changed are written back out the same way, for
+  /// editing documents without reformatting every number and string.
+  pub fn parse_literals(text: &'text str) -> Result<Self, Error> {
+    Ok(
+      Parser::new(text)
+        .literals()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .collect(),
+    )
+  }
+  /// Parse as much of the text as possible, skipping over errors
+  ///
+  /// Returns a best-effort document along with every error encountered, see
+  /// [`Parser::recovering`] for what gets skipped.
+  pub fn parse_recovering(text: &'text str) -> (Self, Vec<Error>) {
+    let mut errors = Vec::new();
+    let document = Parser::new(text)
+      .recovering()
+      .filter_map(|event| event.map_err(|error| errors.push(error)).ok())
+      .collect();
+    (document, errors)
+  }
 }
 
 impl fmt::Debug for Document<'_> {
@@ -76,41 +358,142 @@ impl fmt::Display for Document<'_> {
     Ok(())
   }
 }
-/// Currently panic's if the iterator is invalid, oh well
-impl<'text> FromIterator<Event<'text>> for Document<'text> {
-  fn from_iter<T: IntoIterator<Item = Event<'text>>>(iter: T) -> Self {
-    let mut stack = vec![Document::new()];
-    for event in iter {
-      match event {
-        Event::Node { r#type, name } => {
-          let mut node = Node::new(name);
-          node.set_type_hint(r#type);
-          stack.last_mut().unwrap().nodes.push(node);
+/// Builds a document out of events, optionally with spans
+struct Builder<'text> {
+  stack: Vec<Document<'text>>,
+}
+
+impl<'text> Builder<'text> {
+  fn new() -> Self {
+    Self {
+      stack: vec![Document::new()],
+    }
+  }
+  fn last_node(&mut self) -> &mut Node<'text> {
+    self.stack.last_mut().unwrap().nodes.last_mut().unwrap()
+  }
+  fn push(&mut self, event: Event<'text>, span: Option<EventSpan>, literal: Option<Literal<'text>>) {
+    match event {
+      Event::Node { r#type, name } => {
+        let mut node = Node::new(name);
+        node.set_type_hint(r#type);
+        if let Some(EventSpan::Node { r#type, name }) = span {
+          node.set_span(Some(NodeSpan {
+            span: r#type.unwrap_or(name).join(name),
+            r#type,
+            name,
+            children: None,
+          }));
         }
-        Event::Entry { r#type, key, value } => {
-          let mut entry = Entry::new_value(value);
-          entry.set_key(key);
-          entry.set_type_hint(r#type);
-          stack.last_mut().unwrap().nodes.last_mut().unwrap().entries.push(entry);
+        self.stack.last_mut().unwrap().nodes.push(node);
+      }
+      Event::Entry { r#type, key, value } => {
+        let mut entry = Entry::new_value(value);
+        entry.set_key(key);
+        entry.set_type_hint(r#type);
+        entry.set_literal(literal);
+        let node = self.last_node();
+        if let (Some(EventSpan::Entry(span)), Some(node_span)) = (span, &mut node.span.0) {
+          node_span.span = node_span.span.join(span.span());
+          entry.set_span(Some(span));
         }
-        Event::Begin => stack.push(Document::new()),
-        Event::End => {
-          let children = stack.pop().unwrap();
-          stack.last_mut().unwrap().nodes.last_mut().unwrap().children = Some(children);
+        node.entries.push(entry);
+      }
+      Event::Begin => self.stack.push(Document::new()),
+      Event::End => {
+        let children = self.stack.pop().unwrap();
+        let node = self.last_node();
+        node.children = Some(children);
+        if let (Some(EventSpan::End { children, .. }), Some(node_span)) = (span, &mut node.span.0) {
+          node_span.span = node_span.span.join(children);
+          node_span.children = Some(children);
         }
       }
     }
-    let document = stack.pop().unwrap();
-    assert!(stack.is_empty(), "invalid iterator stream");
+  }
+  fn finish(mut self) -> Document<'text> {
+    let document = self.stack.pop().unwrap();
+    assert!(self.stack.is_empty(), "invalid iterator stream");
     document
   }
 }
 
+/// Currently panic's if the iterator is invalid, oh well
+impl<'text> FromIterator<Event<'text>> for Document<'text> {
+  fn from_iter<T: IntoIterator<Item = Event<'text>>>(iter: T) -> Self {
+    let mut builder = Builder::new();
+    for event in iter {
+      builder.push(event, None, None);
+    }
+    builder.finish()
+  }
+}
+/// Keeps the spans in the document, also panics if the iterator is invalid
+impl<'text> FromIterator<(Event<'text>, EventSpan)> for Document<'text> {
+  fn from_iter<T: IntoIterator<Item = (Event<'text>, EventSpan)>>(iter: T) -> Self {
+    let mut builder = Builder::new();
+    for (event, span) in iter {
+      builder.push(event, Some(span), None);
+    }
+    builder.finish()
+  }
+}
+/// Keeps how values were written in the document, also panics if the
+/// iterator is invalid
+impl<'text> FromIterator<(Event<'text>, Option<Literal<'text>>)> for Document<'text> {
+  fn from_iter<T: IntoIterator<Item = (Event<'text>, Option<Literal<'text>>)>>(iter: T) -> Self {
+    let mut builder = Builder::new();
+    for (event, literal) in iter {
+      builder.push(event, None, literal);
+    }
+    builder.finish()
+  }
+}
+
+/// Position of a node, and optionally one of its entries, by index
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct IndexPath {
+  /// Index of the node at each level, starting with [`Document::nodes`]
+  pub nodes: Vec<usize>,
+  /// Index into the node's [`Node::entries`]
+  pub entry: Option<usize>,
+}
+
+impl IndexPath {
+  /// Get the node this path points to
+  pub fn node<'a, 'text>(&self, document: &'a Document<'text>) -> Option<&'a Node<'text>> {
+    let (last, parents) = self.nodes.split_last()?;
+    let mut document = document;
+    for &index in parents {
+      document = document.nodes.get(index)?.children.as_ref()?;
+    }
+    document.nodes.get(*last)
+  }
+  /// Get the entry this path points to
+  pub fn entry<'a, 'text>(&self, document: &'a Document<'text>) -> Option<&'a Entry<'text>> {
+    self.node(document)?.entries.get(self.entry?)
+  }
+}
+
+/// Source positions of a [`Node`], see [`Document::parse_spanned`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeSpan {
+  /// The entire node, from the type hint to the last entry or `}`
+  pub span: Span,
+  /// The type hint, including parentheses
+  pub r#type: Option<Span>,
+  /// The name, including any quotes
+  pub name: Span,
+  /// The children block, from `{` to `}`
+  pub children: Option<Span>,
+}
+
 /// A `node` element
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Node<'text> {
   r#type: Option<Cow<'text, str>>,
   name: Cow<'text, str>,
+  span: Meta<NodeSpan>,
   /// The node's entries in order
   pub entries: Vec<Entry<'text>>,
   /// The node's child document
@@ -123,6 +506,7 @@ impl<'text> Node<'text> {
     Self {
       r#type: None,
       name: name.into(),
+      span: Meta(None),
       entries: Vec::new(),
       children: None,
     }
@@ -132,6 +516,7 @@ impl<'text> Node<'text> {
     Node {
       r#type: self.r#type.map(cow_static),
       name: cow_static(self.name),
+      span: self.span,
       entries: self.entries.into_iter().map(Entry::into_owned).collect(),
       children: self.children.map(Document::into_owned),
     }
@@ -152,6 +537,16 @@ impl<'text> Node<'text> {
   pub fn set_type_hint(&mut self, r#type: Option<impl Into<Cow<'text, str>>>) {
     self.r#type = r#type.map(Into::into);
   }
+  /// Get the node's source position, if it was parsed with spans
+  ///
+  /// Spans are ignored when comparing or hashing nodes.
+  pub fn span(&self) -> Option<&NodeSpan> {
+    self.span.0.as_ref()
+  }
+  /// Set the node's source position
+  pub fn set_span(&mut self, span: Option<NodeSpan>) {
+    self.span = Meta(span);
+  }
   /// Get a specific entry
   pub fn entry<'key>(&self, key: impl Into<EntryKey<'key>>) -> Option<&Entry<'text>> {
     key.into().seek(self.entries.iter(), |ent| ent.key.as_deref())
@@ -160,6 +555,33 @@ impl<'text> Node<'text> {
   pub fn entry_mut<'key>(&mut self, key: impl Into<EntryKey<'key>>) -> Option<&mut Entry<'text>> {
     key.into().seek(self.entries.iter_mut(), |ent| ent.key.as_deref())
   }
+  /// Convert a property, with the last one winning if it's repeated
+  ///
+  /// Missing properties are an error unless `T` is an [`Option`].
+  /// See [`convert`] for the conversions.
+  pub fn get<'a, T: FromValue<'a>>(&'a self, key: &str) -> Result<T, convert::Error> {
+    convert::property(self, key)?
+      .or_else(T::from_missing)
+      .ok_or_else(|| convert::missing(self, format_args!("property `{key}`")))
+  }
+  /// Convert an argument
+  ///
+  /// Missing arguments are an error unless `T` is an [`Option`].
+  /// See [`convert`] for the conversions.
+  pub fn arg<'a, T: FromValue<'a>>(&'a self, index: usize) -> Result<T, convert::Error> {
+    convert::argument(self, index)?
+      .or_else(T::from_missing)
+      .ok_or_else(|| convert::missing(self, format_args!("argument {index}")))
+  }
+  /// Convert the only child with a name
+  ///
+  /// A missing child is an error unless `T` is an [`Option`], and so are
+  /// several children with the name. See [`convert`] for the conversions.
+  pub fn child<'a, T: FromNode<'a>>(&'a self, name: &str) -> Result<T, convert::Error> {
+    convert::child(self, name)?
+      .or_else(T::from_missing)
+      .ok_or_else(|| convert::missing(self, format_args!("child `{name}`")))
+  }
   /// Normalize node to kdl spec:
   /// - Empty children block gets removed
   /// - Normalize child nodes
@@ -195,6 +617,18 @@ impl<'text> Node<'text> {
         .is_some_and(|key| std::ptr::eq(key.as_ptr(), marker.as_ptr()) && key.is_empty())
     });
   }
+  fn write(&self, f: &mut impl fmt::Write, writer: &mut Writer) -> Result<(), WriteError> {
+    writer.node(f, self.type_hint(), self.name())?;
+    for entry in &self.entries {
+      writer.entry(f, entry.key(), entry.type_hint(), &entry.value, entry.literal())?;
+    }
+    if let Some(children) = &self.children {
+      writer.begin(f)?;
+      children.write_nodes(f, writer)?;
+      writer.end(f)?;
+    }
+    Ok(())
+  }
 }
 
 impl fmt::Debug for Node<'_> {
@@ -209,41 +643,8 @@ impl fmt::Debug for Node<'_> {
 }
 impl fmt::Display for Node<'_> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    if let Some(r#type) = &self.r#type {
-      write!(f, "({})", IdentDisplay(r#type))?;
-    }
-    fmt::Display::fmt(&IdentDisplay(&self.name), f)?;
-    for entry in &self.entries {
-      write!(f, " {entry}")?;
-    }
-    if let Some(children) = &self.children {
-      // make rust fmt do indents for me
-      struct Children<'this>(&'this Document<'this>, Cell<bool>);
-      impl fmt::Debug for Children<'_> {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-          fmt::Display::fmt(self.0, f)?;
-          // really stupid hack to have debug_set not print the trailing comma
-          // (while not ignoring real errors!)
-          self.1.set(true);
-          Err(fmt::Error)
-        }
-      }
-      struct Block<'this>(&'this Document<'this>);
-      impl fmt::Debug for Block<'_> {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-          let children = Children(self.0, Cell::new(false));
-          let result = f.debug_set().entry(&children).finish();
-          if children.1.get() {
-            Ok(())
-          } else {
-            result
-          }
-        }
-      }
-      f.write_str(" ")?;
-      write!(f, "{:#?}\n}}", Block(children))?;
-    }
-    Ok(())
+    // every value can be written in v2
+    self.write(f, &mut Writer::display()).map_err(|_| fmt::Error)
   }
 }
 impl<'key, 'text, T: Into<EntryKey<'key>>> Index<T> for Node<'text> {
@@ -269,6 +670,8 @@ impl<'key, 'text, T: Into<EntryKey<'key>>> IndexMut<T> for Node<'text> {
 pub struct Entry<'text> {
   key: Option<Cow<'text, str>>,
   r#type: Option<Cow<'text, str>>,
+  span: Meta<EntrySpan>,
+  literal: Meta<Literal<'text>>,
   /// The value of this property
   pub value: Value<'text>,
 }
@@ -279,6 +682,8 @@ impl<'text> Entry<'text> {
     Self {
       key: None,
       r#type: None,
+      span: Meta(None),
+      literal: Meta(None),
       value,
     }
   }
@@ -287,6 +692,8 @@ impl<'text> Entry<'text> {
     Self {
       key: Some(name.into()),
       r#type: None,
+      span: Meta(None),
+      literal: Meta(None),
       value,
     }
   }
@@ -295,6 +702,8 @@ impl<'text> Entry<'text> {
     Entry {
       key: self.key.map(cow_static),
       r#type: self.r#type.map(cow_static),
+      span: self.span,
+      literal: Meta(self.literal.0.map(Literal::into_owned)),
       value: self.value.into_owned(),
     }
   }
@@ -314,6 +723,28 @@ impl<'text> Entry<'text> {
   pub fn set_type_hint(&mut self, r#type: Option<impl Into<Cow<'text, str>>>) {
     self.r#type = r#type.map(Into::into);
   }
+  /// Get the entry's source position, if it was parsed with spans
+  ///
+  /// Spans are ignored when comparing or hashing entries.
+  pub fn span(&self) -> Option<&EntrySpan> {
+    self.span.0.as_ref()
+  }
+  /// Set the entry's source position
+  pub fn set_span(&mut self, span: Option<EntrySpan>) {
+    self.span = Meta(span);
+  }
+  /// Get how the value was written, if it was parsed with literals, see
+  /// [`Document::parse_literals`]
+  ///
+  /// Literals are ignored when comparing or hashing entries, and only
+  /// written out while they still match the value.
+  pub fn literal(&self) -> Option<&Literal<'text>> {
+    self.literal.0.as_ref()
+  }
+  /// Set how the value is written
+  pub fn set_literal(&mut self, literal: Option<Literal<'text>>) {
+    self.literal = Meta(literal);
+  }
 }
 
 impl fmt::Debug for Entry<'_> {
@@ -333,7 +764,10 @@ impl fmt::Display for Entry<'_> {
     if let Some(r#type) = &self.r#type {
       write!(f, "({})", IdentDisplay(r#type))?;
     }
-    fmt::Display::fmt(&self.value, f)
+    match self.literal() {
+      Some(literal) if literal.represents(&self.value, Version::V2) => f.write_str(&literal.text),
+      _ => fmt::Display::fmt(&self.value, f),
+    }
   }
 }
 impl<'text, K: Into<Cow<'text, str>>, V: Into<Value<'text>>> From<(K, V)> for Entry<'text> {
@@ -388,6 +822,12 @@ pub enum Value<'text> {
   String(Cow<'text, str>),
   /// An integer value
   Integer(i128),
+  /// An integer outside the range of `i128`, as decimal digits without
+  /// leading zeroes and with a `-` if it's negative
+  ///
+  /// The parser only produces these for integers that don't fit in an
+  /// [`Value::Integer`], see [`Parser::with_strict_integers`].
+  BigInteger(Cow<'text, str>),
   /// A floating-point number value
   Float(f64),
   /// A boolean value
@@ -402,12 +842,139 @@ impl Value<'_> {
     match self {
       Self::String(value) => Value::String(cow_static(value)),
       Self::Integer(value) => Value::Integer(value),
+      Self::BigInteger(value) => Value::BigInteger(cow_static(value)),
       Self::Float(value) => Value::Float(value),
       Self::Bool(value) => Value::Bool(value),
       Self::Null => Value::Null,
     }
   }
-  // TODO: maybe some helper methods?
+  /// The text, if it's a string
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Self::String(value) => Some(value),
+      _ => None,
+    }
+  }
+  /// The integer, if it's an integer that fits
+  pub fn as_i64(&self) -> Option<i64> {
+    self.as_i128().and_then(|value| value.try_into().ok())
+  }
+  /// The integer, if it's an integer that fits
+  pub fn as_u64(&self) -> Option<u64> {
+    self.as_i128().and_then(|value| value.try_into().ok())
+  }
+  /// The integer, if it's an integer that fits
+  pub fn as_i128(&self) -> Option<i128> {
+    match *self {
+      Self::Integer(value) => Some(value),
+      _ => None,
+    }
+  }
+  /// The integer, if it's an integer that fits
+  pub fn as_u128(&self) -> Option<u128> {
+    match self {
+      Self::Integer(value) => (*value).try_into().ok(),
+      Self::BigInteger(value) => value.parse().ok(),
+      _ => None,
+    }
+  }
+  /// The number, if it's a float or an integer (which may lose precision)
+  pub fn as_f64(&self) -> Option<f64> {
+    match self {
+      Self::Float(value) => Some(*value),
+      Self::Integer(value) => Some(*value as f64),
+      Self::BigInteger(value) => value.parse().ok(),
+      _ => None,
+    }
+  }
+  /// The boolean, if it's a boolean
+  pub fn as_bool(&self) -> Option<bool> {
+    match *self {
+      Self::Bool(value) => Some(value),
+      _ => None,
+    }
+  }
+  /// Whether it's `#null`
+  pub fn is_null(&self) -> bool {
+    matches!(self, Self::Null)
+  }
+  /// Name of the kind of value, for messages: `string`, `integer`, `float`,
+  /// `bool`, or `null`
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Self::String(_) => "string",
+      Self::Integer(_) | Self::BigInteger(_) => "integer",
+      Self::Float(_) => "float",
+      Self::Bool(_) => "bool",
+      Self::Null => "null",
+    }
+  }
+  /// Rank for ordering different kinds of values
+  fn rank(&self) -> u8 {
+    match self {
+      Self::Null => 0,
+      Self::Bool(_) => 1,
+      Self::Integer(_) | Self::BigInteger(_) | Self::Float(_) => 2,
+      Self::String(_) => 3,
+    }
+  }
+}
+
+/// Compare an integer and a float exactly, with the integer first if
+/// they're the same number
+fn cmp_integer_float(l: i128, r: f64) -> Ordering {
+  // 2^127, the first float past i128::MAX
+  const LIMIT: f64 = 170141183460469231731687303715884105728.0;
+  if r.is_nan() || r >= LIMIT {
+    return Ordering::Less;
+  }
+  if r < -LIMIT {
+    return Ordering::Greater;
+  }
+  let whole = r.trunc();
+  // the cast is exact, since it's a whole number in range
+  let fraction = if r < whole { Ordering::Greater } else { Ordering::Less };
+  l.cmp(&(whole as i128)).then(fraction)
+}
+
+/// Compare the digits of two integers like [`Value::BigInteger`]
+fn cmp_digits(l: &str, r: &str) -> Ordering {
+  match (l.strip_prefix('-'), r.strip_prefix('-')) {
+    (None, None) => (l.len(), l).cmp(&(r.len(), r)),
+    (Some(l), Some(r)) => (r.len(), r).cmp(&(l.len(), l)),
+    (Some(_), None) => Ordering::Less,
+    (None, Some(_)) => Ordering::Greater,
+  }
+}
+
+/// [`cmp_integer_float`] for a [`Value::BigInteger`]
+fn cmp_big_float(l: &str, r: f64) -> Ordering {
+  if r.is_nan() || r == f64::INFINITY {
+    return Ordering::Less;
+  }
+  if r == f64::NEG_INFINITY {
+    return Ordering::Greater;
+  }
+  let whole = r.trunc();
+  // floats print their exact value, except for the sign of zero
+  let digits = if whole == 0.0 {
+    "0".to_owned()
+  } else {
+    format!("{whole:.0}")
+  };
+  let fraction = if r < whole { Ordering::Greater } else { Ordering::Less };
+  cmp_digits(l, &digits).then(fraction)
+}
+
+/// Compare floats like [`norm_float`]: zeroes are equal, and NaN is equal to
+/// itself and after everything else
+fn cmp_float(l: f64, r: f64) -> Ordering {
+  match (l.is_nan(), r.is_nan()) {
+    (true, true) => Ordering::Equal,
+    (true, false) => Ordering::Greater,
+    (false, true) => Ordering::Less,
+    (false, false) => l.partial_cmp(&r).unwrap(),
+  }
 }
 
 impl fmt::Debug for Value<'_> {
@@ -415,6 +982,7 @@ impl fmt::Debug for Value<'_> {
     match self {
       Self::String(value) => fmt::Debug::fmt(&**value, f),
       Self::Integer(value) => fmt::Debug::fmt(value, f),
+      Self::BigInteger(value) => f.write_str(value),
       Self::Float(value) => fmt::Debug::fmt(value, f),
       Self::Bool(true) => f.write_str("#true"),
       Self::Bool(false) => f.write_str("#false"),
@@ -427,6 +995,7 @@ impl fmt::Display for Value<'_> {
     match self {
       Value::String(value) => fmt::Display::fmt(&IdentDisplay(value), f),
       Value::Integer(value) => fmt::Display::fmt(value, f),
+      Value::BigInteger(value) => f.write_str(value),
       Value::Float(value) => match value.classify() {
         FpCategory::Nan => f.write_str("#nan"),
         FpCategory::Infinite => f.write_str(if value.is_sign_negative() { "#-inf" } else { "#inf" }),
@@ -446,6 +1015,7 @@ impl PartialEq for Value<'_> {
     match (self, other) {
       (Self::String(l), Self::String(r)) => l == r,
       (Self::Integer(l), Self::Integer(r)) => l == r,
+      (Self::BigInteger(l), Self::BigInteger(r)) => l == r,
       (Self::Float(l), Self::Float(r)) => norm_float(*l) == norm_float(*r),
       (Self::Bool(l), Self::Bool(r)) => l == r,
       (Self::Null, Self::Null) => true,
@@ -454,6 +1024,36 @@ impl PartialEq for Value<'_> {
   }
 }
 impl Eq for Value<'_> {}
+/// Total ordering, agreeing with equality:
+/// `#null` < booleans < numbers < strings
+///
+/// Integers and floats are compared by their numeric value, with integers
+/// first when they're the same number (`1 < 1.0`), and `#nan` after every
+/// other number.
+impl PartialOrd for Value<'_> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl Ord for Value<'_> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    match (self, other) {
+      (Self::String(l), Self::String(r)) => l.cmp(r),
+      (Self::Integer(l), Self::Integer(r)) => l.cmp(r),
+      (Self::Float(l), Self::Float(r)) => cmp_float(*l, *r),
+      (Self::Integer(l), Self::Float(r)) => cmp_integer_float(*l, *r),
+      (Self::Float(l), Self::Integer(r)) => cmp_integer_float(*r, *l).reverse(),
+      (Self::BigInteger(l), Self::BigInteger(r)) => cmp_digits(l, r),
+      // integers first, in case a big one could've been a normal one
+      (Self::Integer(l), Self::BigInteger(r)) => cmp_digits(&l.to_string(), r).then(Ordering::Less),
+      (Self::BigInteger(l), Self::Integer(r)) => cmp_digits(l, &r.to_string()).then(Ordering::Greater),
+      (Self::BigInteger(l), Self::Float(r)) => cmp_big_float(l, *r),
+      (Self::Float(l), Self::BigInteger(r)) => cmp_big_float(r, *l).reverse(),
+      (Self::Bool(l), Self::Bool(r)) => l.cmp(r),
+      _ => self.rank().cmp(&other.rank()),
+    }
+  }
+}
 impl std::hash::Hash for Value<'_> {
   fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
     match self {
@@ -476,6 +1076,10 @@ impl std::hash::Hash for Value<'_> {
       Value::Null => {
         state.write_u8(4);
       }
+      Value::BigInteger(value) => {
+        state.write_u8(5);
+        value.hash(state);
+      }
     }
   }
 }
@@ -509,6 +1113,25 @@ impl<'text> From<()> for Value<'text> {
     Self::Null
   }
 }
+macro_rules! try_from_value {
+  ($($type:ty)*) => {$(
+    impl TryFrom<&Value<'_>> for $type {
+      type Error = convert::Error;
+      fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        <$type>::from_value(value)
+      }
+    }
+    impl TryFrom<Value<'_>> for $type {
+      type Error = convert::Error;
+      fn try_from(value: Value) -> Result<Self, Self::Error> {
+        <$type>::from_value(&value)
+      }
+    }
+  )*};
+}
+
+try_from_value! { i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize f32 f64 }
+
 impl<'text, T: Into<Value<'text>>> From<Option<T>> for Value<'text> {
   fn from(value: Option<T>) -> Self {
     match value {
@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Errors that know where in a document they happened
+
+use std::fmt;
+
+use crate::dom::{Entry, Node};
+use crate::stream::{self, Span};
+
+/// A conversion error, with where in the document it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+  message: String,
+  // innermost first
+  path: Vec<Segment>,
+  pub(crate) span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+  /// a node, with its index among same-named siblings if there are several
+  Node(String, Option<usize>),
+  Property(String),
+  Argument(usize),
+}
+
+impl Error {
+  /// Create an error with a message, for the current node or value
+  pub fn new(message: impl fmt::Display) -> Self {
+    Self {
+      message: message.to_string(),
+      path: Vec::new(),
+      span: None,
+    }
+  }
+  /// The error message, without the path
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+  /// Path to the node or entry that failed, like `server > listen[1].port`
+  ///
+  /// Nodes are separated by `>`, with their index among siblings of the same
+  /// name if there are several. Properties and arguments come after a `.`.
+  pub fn path(&self) -> String {
+    let mut path = String::new();
+    for segment in self.path.iter().rev() {
+      match segment {
+        Segment::Node(name, index) => {
+          if !path.is_empty() {
+            path.push_str(" > ");
+          }
+          path.push_str(name);
+          if let Some(index) = index {
+            path.push_str(&format!("[{index}]"));
+          }
+        }
+        Segment::Property(key) => {
+          if !path.is_empty() {
+            path.push('.');
+          }
+          path.push_str(key);
+        }
+        Segment::Argument(index) => {
+          if !path.is_empty() {
+            path.push('.');
+          }
+          path.push_str(&index.to_string());
+        }
+      }
+    }
+    path
+  }
+  /// Source position of the innermost node or entry with a span, see
+  /// [`Document::parse_spanned`](crate::dom::Document::parse_spanned)
+  pub fn span(&self) -> Option<Span> {
+    self.span
+  }
+  pub(crate) fn at(mut self, segment: Segment, span: Option<Span>) -> Self {
+    self.path.push(segment);
+    self.span = self.span.or(span);
+    self
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.path.is_empty() {
+      f.write_str(&self.message)
+    } else {
+      write!(f, "{}: {}", self.path(), self.message)
+    }
+  }
+}
+impl std::error::Error for Error {}
+impl From<stream::Error> for Error {
+  fn from(value: stream::Error) -> Self {
+    Self {
+      message: value.kind().to_string(),
+      path: Vec::new(),
+      span: value.offset().map(|at| Span { start: at, end: at }),
+    }
+  }
+}
+
+pub(crate) fn node_span(node: &Node) -> Option<Span> {
+  node.span().map(|span| span.span)
+}
+pub(crate) fn entry_span(entry: &Entry) -> Option<Span> {
+  entry.span().map(|span| span.span())
+}
+
+/// Path segment for `nodes[index]`, with an index if its name isn't unique
+pub(crate) fn child_segment(nodes: &[Node], index: usize) -> Segment {
+  let name = nodes[index].name();
+  let mut same = nodes.iter().enumerate().filter(|(_, node)| node.name() == name);
+  let position = same.clone().position(|(i, _)| i == index);
+  let index = if same.nth(1).is_some() { position } else { None };
+  Segment::Node(name.to_owned(), index)
+}
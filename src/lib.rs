@@ -17,10 +17,26 @@
 //! trees, etc.) where formatting information is entirely redundant and just
 //! wasteful of parsing time and memory.
 //!
-//! This parser is based on `just-kdl` crate, with opaque number type replaced with `f64` and `i128`.
+//! This parser is based on `just-kdl` crate, with opaque number type replaced with `f64` and `i128`
+//! (and decimal text for larger integers).
 //!
 //! Additionally, this implementation has a few other benefits:
 //! - Full v2.0.0 compliance
+//! - Can also parse KDL v1, see [`stream::Version`]
+//! - Can parse input as it arrives, see [`stream::ReadParser`]
+//! - Can keep how values were written for editing documents, see
+//!   [`stream::Parser::literals`]
+//! - Optional [serde](https://serde.rs) support with the `serde` feature
+//! - Conversion to and from Rust types, derivable with the `derive` feature,
+//!   see [`convert`]
+//! - Checking and decoding the spec's reserved type annotations, like
+//!   `(u8)` or `(date-time)`, and handlers for your own, see [`annotation`]
+//! - Getting and setting values by paths like `server.listen.port`, see
+//!   [`path`]
+//! - Walking and transforming documents with visitors, see [`visit`], or
+//!   moving around them with cursors, see [`cursor`]
+//! - Finding nodes with the KDL Query Language, in documents or straight from
+//!   the parser, see [`query`]
 //! - Significantly fewer dependencies!
 //!
 //! ## Benchmarks
@@ -48,11 +64,26 @@
 use std::borrow::Cow;
 use std::fmt;
 
+pub mod annotation;
+pub mod convert;
+pub mod cursor;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod diagnostic;
 pub mod dom;
+mod error;
+pub mod path;
+pub mod query;
+#[cfg(feature = "serde")]
+pub mod ser;
 pub mod stream;
+pub mod visit;
 
 #[cfg(test)]
 mod tests;
+// derived impls in tests refer to `::kdlite`
+#[cfg(all(test, feature = "derive"))]
+extern crate self as kdlite;
 
 fn cow_static<T: ?Sized + ToOwned>(value: Cow<'_, T>) -> Cow<'static, T> {
   Cow::Owned(value.into_owned())
@@ -95,3 +126,63 @@ impl fmt::Display for IdentDisplay<'_> {
     }
   }
 }
+
+/// [`IdentDisplay`] with KDL v1 rules, using raw strings instead of escaping
+/// quotes and backslashes where possible
+struct IdentDisplayV1<'text>(&'text str);
+impl fmt::Display for IdentDisplayV1<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let text = self.0;
+    let is_number_like = {
+      let text = text.strip_prefix(['+', '-']).unwrap_or(text);
+      let text = text.strip_prefix('.').unwrap_or(text);
+      matches!(text.chars().next(), Some('0'..='9'))
+    };
+    // `r` followed by `#` or `"` would start a raw string
+    let is_raw_like = text.starts_with("r#") || text.starts_with("r\"");
+    // everything v1 allows in strings that shouldn't be written literally
+    let needs_escape = |ch: char| {
+      ch.is_control()
+        || matches!(ch, '\u{85}' | '\u{200E}'..='\u{200F}' | '\u{2028}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}')
+    };
+    if !(text.is_empty()
+      || is_number_like
+      || is_raw_like
+      || matches!(text, "true" | "false" | "null")
+      || text.contains(|ch: char| {
+        needs_escape(ch)
+          || ch.is_whitespace()
+          || matches!(
+            ch,
+            '\\' | '/' | '(' | ')' | '{' | '}' | '<' | '>' | ';' | '[' | ']' | '=' | ',' | '"'
+          )
+      }))
+    {
+      fmt::Display::fmt(&text, f)
+    } else if text.contains(['\\', '"']) && !text.contains(needs_escape) {
+      // just enough hashes to not end the string early
+      let mut hashes = 0;
+      while text.contains(&format!("\"{}", "#".repeat(hashes))) {
+        hashes += 1;
+      }
+      let hashes = "#".repeat(hashes);
+      write!(f, "r{hashes}\"{text}\"{hashes}")
+    } else {
+      f.write_str("\"")?;
+      for ch in text.chars() {
+        match ch {
+          '"' => f.write_str("\\\""),
+          '\\' => f.write_str("\\\\"),
+          '\n' => f.write_str("\\n"),
+          '\r' => f.write_str("\\r"),
+          '\t' => f.write_str("\\t"),
+          '\u{8}' => f.write_str("\\b"),
+          '\u{C}' => f.write_str("\\f"),
+          ch if needs_escape(ch) => write!(f, "\\u{{{:x}}}", ch as u32),
+          ch => fmt::Display::fmt(&ch, f),
+        }?;
+      }
+      f.write_str("\"")
+    }
+  }
+}
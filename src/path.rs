@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Paths to values by node names, like `server.listen.port`
+//!
+//! A [`Path`] is a list of nodes, each by name and optionally by index among
+//! siblings with that name, followed by a property or argument of the last
+//! node:
+//! - `server.listen.port`: property `port` of the first `listen` in the
+//!   first `server`
+//! - `upstream[2].args[0]`: the first argument of the third `upstream`
+//!
+//! Names with `.`, `[`, `]`, quotes, or spaces in them are quoted like KDL
+//! strings: `"example.com".args[0]`.
+//!
+//! Use them with [`Document::get_path`], [`Document::set_path`], and
+//! [`Document::remove_path`], or list every value in a document with
+//! [`Document::flatten`]:
+//!
+//! ```
+//! use kdlite::dom::{Document, Value};
+//! use kdlite::path::Path;
+//!
+//! let mut document = Document::parse("server { listen port=80 }").unwrap();
+//! let port: Path = "server.listen.port".parse().unwrap();
+//! assert_eq!(document.get_path(&port), Some(&Value::Integer(80)));
+//!
+//! document.set_path(&"server.tls.args[0]".parse().unwrap(), Value::Bool(true));
+//! assert_eq!(document.to_string(), "server {\n    listen port=80\n    tls #true\n}");
+//! ```
+//!
+//! [`Document::get_path`]: crate::dom::Document::get_path
+//! [`Document::set_path`]: crate::dom::Document::set_path
+//! [`Document::remove_path`]: crate::dom::Document::remove_path
+//! [`Document::flatten`]: crate::dom::Document::flatten
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::dom::EntryKey;
+use crate::stream::piece;
+
+/// An invalid path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+  message: Cow<'static, str>,
+  offset: usize,
+}
+
+impl Error {
+  fn new(message: &'static str, offset: usize) -> Self {
+    Self {
+      message: Cow::Borrowed(message),
+      offset,
+    }
+  }
+  /// Byte position of the error in the path
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at byte {}", self.message, self.offset)
+  }
+}
+impl std::error::Error for Error {}
+
+/// A path to a property or argument, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Path {
+  /// Nodes from the top of the document, by name and index among siblings
+  /// with that name
+  pub nodes: Vec<(String, usize)>,
+  /// The entry in the last node
+  pub key: Key,
+}
+
+/// The entry a [`Path`] ends with
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+  /// An argument by index, `args[0]`
+  Argument(usize),
+  /// A property by name
+  Property(String),
+}
+
+impl Path {
+  /// Create a path from node names, each the first one with its name
+  pub fn new<'a>(nodes: impl IntoIterator<Item = &'a str>, key: Key) -> Self {
+    Self {
+      nodes: nodes.into_iter().map(|name| (name.to_owned(), 0)).collect(),
+      key,
+    }
+  }
+
+  /// Parse a path, see the [module docs](self) for the syntax
+  pub fn parse(text: &str) -> Result<Self, Error> {
+    let mut segments = Vec::new();
+    let mut at = 0;
+    loop {
+      let (end, name, quoted) = if text[at..].starts_with('"') {
+        let (end, name) = piece::string(text, at).map_err(|error| Error {
+          message: Cow::Owned(error.kind().to_string()),
+          offset: error.offset().unwrap_or(text.len()),
+        })?;
+        (end, name.into_owned(), true)
+      } else {
+        let end = text[at..].find(['.', '[', ']']).map_or(text.len(), |end| at + end);
+        if end == at {
+          return Err(Error::new("expected a name", at));
+        }
+        (end, text[at..end].to_owned(), false)
+      };
+      at = end;
+      let mut index = None;
+      if text[at..].starts_with('[') {
+        let end = text[at..].find(']').map_or(text.len(), |end| at + end);
+        match text[at + 1..end].parse() {
+          Ok(number) if end < text.len() => index = Some(number),
+          _ => return Err(Error::new("expected an index", at + 1)),
+        }
+        at = end + 1;
+      }
+      segments.push((at, name, quoted, index));
+      if at == text.len() {
+        break;
+      }
+      if !text[at..].starts_with('.') {
+        return Err(Error::new("expected `.`", at));
+      }
+      at += 1;
+    }
+    let (end, name, quoted, index) = segments.pop().unwrap();
+    if segments.is_empty() {
+      return Err(Error::new("expected a node before the entry", end));
+    }
+    let key = match (name, index) {
+      (name, Some(index)) if name == "args" && !quoted => Key::Argument(index),
+      (_, Some(_)) => return Err(Error::new("only `args` can have an index at the end", end)),
+      (name, None) => Key::Property(name),
+    };
+    Ok(Self {
+      nodes: segments
+        .into_iter()
+        .map(|(_, name, _, index)| (name, index.unwrap_or(0)))
+        .collect(),
+      key,
+    })
+  }
+}
+
+impl FromStr for Path {
+  type Err = Error;
+  fn from_str(text: &str) -> Result<Self, Error> {
+    Self::parse(text)
+  }
+}
+
+impl<'a> From<&'a Key> for EntryKey<'a> {
+  fn from(value: &'a Key) -> Self {
+    match value {
+      Key::Argument(index) => Self::Pos(*index),
+      Key::Property(name) => Self::Name(name),
+    }
+  }
+}
+
+/// A name in a path, quoted if it has to be
+struct Name<'a>(&'a str);
+impl fmt::Display for Name<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let text = self.0;
+    if text.is_empty()
+      || text.contains(|ch: char| matches!(ch, '.' | '[' | ']' | '"' | '\\') || ch.is_whitespace() || ch.is_control())
+    {
+      // rust escapes are also valid in KDL strings
+      write!(f, "{text:?}")
+    } else {
+      f.write_str(text)
+    }
+  }
+}
+
+impl fmt::Display for Path {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for (name, index) in &self.nodes {
+      write!(f, "{}", Name(name))?;
+      if *index > 0 {
+        write!(f, "[{index}]")?;
+      }
+      f.write_str(".")?;
+    }
+    match &self.key {
+      Key::Argument(index) => write!(f, "args[{index}]"),
+      Key::Property(name) => write!(f, "{}", Name(name)),
+    }
+  }
+}
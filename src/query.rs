@@ -0,0 +1,879 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! The [KDL Query Language], for finding nodes anywhere in a document
+//!
+//! A [`Query`] is parsed once and can then [`select`](Query::select) nodes
+//! from any number of documents, in document order:
+//!
+//! ```
+//! use kdlite::dom::Document;
+//! use kdlite::query::Query;
+//!
+//! let document = Document::parse(
+//!   r#"
+//!   upstream "api" { server "10.0.0.1" weight=5; server "10.0.0.2" weight=20 }
+//!   upstream "static" { server "10.0.0.3" weight=20 }
+//!   "#,
+//! )
+//! .unwrap();
+//! let query = Query::parse(r#"upstream[val() = "api"] > server[weight >= 10]"#).unwrap();
+//! let servers = query.select(&document);
+//! assert_eq!(servers.len(), 1);
+//! assert_eq!(servers[0].arg::<&str>(0).unwrap(), "10.0.0.2");
+//! ```
+//!
+//! ## Syntax
+//!
+//! A query is one or more selectors separated by `||`, and selects the nodes
+//! that any of them match. A selector is a list of filters joined by:
+//! - `a > b`: `b` is a child of `a`
+//! - `a >> b`: `b` is a descendant of `a`
+//! - `a + b`: `b` is the sibling right after `a`
+//! - `a ~ b`: `b` is any sibling after `a`
+//!
+//! Without a combinator before it, the first filter matches nodes at any
+//! depth, unless it's `top()`, which stands for the document itself:
+//! `top() > a` only matches `a` at the top level, and `top()` alone matches
+//! every top level node.
+//!
+//! A filter is an optional type annotation, then an optional node name, then
+//! any number of matchers in brackets, which all have to match:
+//! - `(type)` has that type annotation, and `()` has any annotation
+//! - `name` has that name
+//! - `[]` matches any node
+//! - `[accessor]` checks that something exists:
+//!   - `val(n)` or `val()`: argument `n`, or the first one
+//!   - `prop(key)` or just `key`: a property
+//!   - `name()`: the node name
+//!   - `tag()`: the node type annotation
+//! - `[accessor op value]` compares it to a value, where `op` is:
+//!   - `=` or `!=`, for any value, or a type annotation like `(ipv4)` to
+//!     compare the annotation of an argument or property instead
+//!   - `<`, `<=`, `>`, `>=`, for numbers
+//!   - `^=`, `$=`, `*=`, for strings that start with, end with, or contain
+//!     the value
+//!
+//! Names, strings, and values are written like in KDL (2.0.0). Comparisons
+//! with things that don't exist, or that have the wrong type, never match.
+//!
+//! ## Streaming
+//!
+//! [`Query::extract`] runs a query on parser events instead, to pull a few
+//! nodes out of a large document without building the rest of it.
+//!
+//! [KDL Query Language]: https://github.com/kdl-org/kdl/blob/2.0.0/QUERY-SPEC.md
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::dom::{Document, EntryKey, IndexPath, Node, Value};
+use crate::stream::{self, piece, Event};
+
+/// An invalid query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+  message: Cow<'static, str>,
+  offset: usize,
+}
+
+impl Error {
+  fn new(message: &'static str, offset: usize) -> Self {
+    Self {
+      message: Cow::Borrowed(message),
+      offset,
+    }
+  }
+  /// Byte position of the error in the query
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at byte {}", self.message, self.offset)
+  }
+}
+impl std::error::Error for Error {}
+
+/// A compiled query, see the [module docs](self) for the syntax
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+  selectors: Vec<Selector>,
+}
+
+/// `filter (combinator filter)*`
+#[derive(Debug, Clone, PartialEq)]
+struct Selector {
+  filters: Vec<Filter>,
+  /// `combinators[i]` is between `filters[i]` and `filters[i + 1]`
+  combinators: Vec<Combinator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+  /// `>`
+  Child,
+  /// `>>`
+  Descendant,
+  /// `+`
+  Next,
+  /// `~`
+  After,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+  /// `top()`
+  Top,
+  Nodes {
+    /// `Some(None)` for `()`
+    r#type: Option<Option<String>>,
+    name: Option<String>,
+    matchers: Vec<Matcher>,
+  },
+}
+
+/// `[accessor (op operand)?]`
+#[derive(Debug, Clone, PartialEq)]
+struct Matcher {
+  accessor: Accessor,
+  comparison: Option<(Op, Operand)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Accessor {
+  /// `[]`
+  Any,
+  Val(usize),
+  Prop(String),
+  Name,
+  Tag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  StartsWith,
+  EndsWith,
+  Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+  Type(String),
+  Value(Value<'static>),
+}
+
+/// A node, by its siblings and its index among them
+type Level<'a, 'text> = (&'a [Node<'text>], usize);
+
+impl Query {
+  /// Parse a query
+  pub fn parse(text: &str) -> Result<Self, Error> {
+    let mut parser = QueryParser { text, at: 0 };
+    let mut selectors = vec![parser.selector()?];
+    loop {
+      parser.space()?;
+      if parser.at == text.len() {
+        return Ok(Self { selectors });
+      }
+      if !parser.eat("||") {
+        return Err(Error::new("expected `||` or a combinator", parser.at));
+      }
+      parser.space()?;
+      selectors.push(parser.selector()?);
+    }
+  }
+
+  /// Every node the query matches, in document order
+  pub fn select<'a, 'text>(&self, document: &'a Document<'text>) -> Vec<&'a Node<'text>> {
+    let mut found = Vec::new();
+    let mut levels = vec![self.document_prefixes()];
+    self.walk(
+      &mut levels,
+      &mut Vec::new(),
+      &document.nodes,
+      &mut |_, (nodes, index)| found.push(&nodes[index]),
+    );
+    found
+  }
+
+  /// The positions of every node the query matches, in document order
+  ///
+  /// Unlike [`select`](Self::select), the positions don't borrow the
+  /// document, so they can be used to change it.
+  pub fn select_paths(&self, document: &Document) -> Vec<IndexPath> {
+    let mut found = Vec::new();
+    let mut levels = vec![self.document_prefixes()];
+    self.walk(
+      &mut levels,
+      &mut Vec::new(),
+      &document.nodes,
+      &mut |parents, (_, index)| {
+        let mut nodes: Vec<usize> = parents.iter().map(|&(_, index)| index).collect();
+        nodes.push(index);
+        found.push(IndexPath { nodes, entry: None })
+      },
+    );
+    found
+  }
+
+  /// Check if the query matches a node at a position
+  pub fn matches(&self, document: &Document, path: &IndexPath) -> bool {
+    let mut level = self.document_prefixes();
+    let mut nodes = &document.nodes[..];
+    let mut matched = Vec::new();
+    for (depth, &index) in path.nodes.iter().enumerate() {
+      if index >= nodes.len() {
+        return false;
+      }
+      // siblings before the node can matter for `+` and `~`
+      for (sibling, node) in nodes[..=index].iter().enumerate() {
+        let selected = self.match_node(node, &level, depth == 0, &mut matched);
+        if sibling == index && depth + 1 == path.nodes.len() {
+          return selected;
+        }
+        level.push_sibling(&matched);
+      }
+      let mut children = Prefixes::default();
+      children.enter(&level, &matched);
+      level = children;
+      match &nodes[index].children {
+        Some(children) => nodes = &children.nodes,
+        None => return false,
+      }
+    }
+    false
+  }
+
+  /// Visit the nodes of a block with `levels[parents.len()]` set up for
+  /// them, reporting the ones that match
+  fn walk<'a, 'text>(
+    &self,
+    levels: &mut Vec<Prefixes>,
+    parents: &mut Vec<Level<'a, 'text>>,
+    nodes: &'a [Node<'text>],
+    found: &mut impl FnMut(&[Level<'a, 'text>], Level<'a, 'text>),
+  ) {
+    let depth = parents.len();
+    let mut matched = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+      if self.match_node(node, &levels[depth], depth == 0, &mut matched) {
+        found(parents, (nodes, index));
+      }
+      levels[depth].push_sibling(&matched);
+      if let Some(children) = &node.children {
+        if levels.len() == depth + 1 {
+          levels.push(Prefixes::default());
+        }
+        let (outer, inner) = levels.split_at_mut(depth + 1);
+        inner[0].enter(&outer[depth], &matched);
+        parents.push((nodes, index));
+        self.walk(levels, parents, &children.nodes, found);
+        parents.pop();
+      }
+    }
+  }
+}
+
+impl FromStr for Query {
+  type Err = Error;
+  fn from_str(text: &str) -> Result<Self, Error> {
+    Self::parse(text)
+  }
+}
+
+/// Which selector prefixes match at one level of open children blocks
+///
+/// Each has a flag for every `filters[..=k]` of every selector, see
+/// [`Query::prefixes`].
+#[derive(Default)]
+struct Prefixes {
+  /// matched by the node that owns the block, or the document itself
+  matched: Vec<bool>,
+  /// matched by that node or any of its ancestors
+  inherited: Vec<bool>,
+  /// matched by the last child so far
+  previous: Vec<bool>,
+  /// matched by any child so far
+  before: Vec<bool>,
+}
+
+impl Prefixes {
+  fn reset(&mut self, size: usize) {
+    for flags in [
+      &mut self.matched,
+      &mut self.inherited,
+      &mut self.previous,
+      &mut self.before,
+    ] {
+      flags.clear();
+      flags.resize(size, false);
+    }
+  }
+
+  /// Record the prefixes matched by the next child
+  fn push_sibling(&mut self, matched: &[bool]) {
+    self.previous.copy_from_slice(matched);
+    for (before, &matched) in self.before.iter_mut().zip(matched) {
+      *before |= matched;
+    }
+  }
+
+  /// Start the children block of a node that matched some prefixes
+  fn enter(&mut self, parent: &Prefixes, matched: &[bool]) {
+    self.reset(matched.len());
+    self.matched.copy_from_slice(matched);
+    for ((inherited, &matched), &outer) in self.inherited.iter_mut().zip(matched).zip(&parent.inherited) {
+      *inherited = matched || outer;
+    }
+  }
+}
+
+impl Query {
+  /// Number of selector prefixes
+  fn prefixes(&self) -> usize {
+    self.selectors.iter().map(|selector| selector.filters.len()).sum()
+  }
+
+  /// The prefixes for the top level, where only `top()` has matched
+  fn document_prefixes(&self) -> Prefixes {
+    let mut document = Prefixes::default();
+    document.reset(self.prefixes());
+    let mut offset = 0;
+    for selector in &self.selectors {
+      if selector.filters.len() > 1 && selector.filters[0] == Filter::Top {
+        document.matched[offset] = true;
+        document.inherited[offset] = true;
+      }
+      offset += selector.filters.len();
+    }
+    document
+  }
+
+  /// Find the prefixes a node matches, given the ones of its block,
+  /// returning whether a whole selector matches
+  ///
+  /// Each prefix only looks at the flags of the one before it, so this is
+  /// linear in the size of the query.
+  fn match_node(&self, node: &impl Header, parent: &Prefixes, top_level: bool, matched: &mut Vec<bool>) -> bool {
+    matched.clear();
+    let mut selected = false;
+    for selector in &self.selectors {
+      let offset = matched.len();
+      for (k, filter) in selector.filters.iter().enumerate() {
+        let matches = if *filter == Filter::Top {
+          selector.filters.len() == 1 && top_level
+        } else if !filter.matches(node) {
+          false
+        } else if k == 0 {
+          true
+        } else {
+          let previous = offset + k - 1;
+          match selector.combinators[k - 1] {
+            Combinator::Child => parent.matched[previous],
+            Combinator::Descendant => parent.inherited[previous],
+            Combinator::Next => parent.previous[previous],
+            Combinator::After => parent.before[previous],
+          }
+        };
+        matched.push(matches);
+      }
+      selected |= matched[matched.len() - 1];
+    }
+    selected
+  }
+
+  /// Keep only the events for nodes the query matches, with everything in
+  /// them, skipping the rest
+  ///
+  /// Filters only see a node up to its children, so nodes inside a matching
+  /// node are part of it rather than matched again. Discarded nodes are
+  /// never built, and after the first few nodes nothing is allocated for
+  /// them. Parse errors are passed along.
+  ///
+  /// ```
+  /// use kdlite::query::Query;
+  /// use kdlite::stream::Parser;
+  ///
+  /// let text = r#"plugins { plugin name="log"; plugin name="auth" { key "k" } }"#;
+  /// let query = Query::parse(r#"plugins > plugin[name="auth"]"#).unwrap();
+  /// let nodes = query.extract(Parser::new(text)).nodes().collect::<Result<Vec<_>, _>>().unwrap();
+  /// assert_eq!(nodes.len(), 1);
+  /// assert_eq!(nodes[0].child::<&str>("key").unwrap(), "k");
+  /// ```
+  pub fn extract<'text, I>(&self, events: I) -> Extract<'_, 'text, I::IntoIter>
+  where
+    I: IntoIterator<Item = Result<Event<'text>, stream::Error>>,
+  {
+    Extract {
+      query: self,
+      events: events.into_iter(),
+      header: Vec::new(),
+      ready: VecDeque::new(),
+      levels: vec![self.document_prefixes()],
+      depth: 0,
+      inside: 0,
+      matched: Vec::new(),
+      selected: false,
+    }
+  }
+}
+
+/// An [`Iterator`] of the events for nodes a query matches, see
+/// [`Query::extract`]
+pub struct Extract<'q, 'text, I> {
+  query: &'q Query,
+  events: I,
+  /// the node being read, up to its children
+  header: Vec<Event<'text>>,
+  ready: VecDeque<Event<'text>>,
+  /// one for the document and each open children block, reused after
+  /// they close
+  levels: Vec<Prefixes>,
+  /// number of open children blocks outside of matching nodes
+  depth: usize,
+  /// number of open children blocks inside a matching node
+  inside: usize,
+  /// prefixes matched by the last complete header
+  matched: Vec<bool>,
+  selected: bool,
+}
+
+impl<'q, 'text, I: Iterator<Item = Result<Event<'text>, stream::Error>>> Extract<'q, 'text, I> {
+  /// Build each matching node, instead of giving its events
+  pub fn nodes(self) -> ExtractNodes<'q, 'text, I> {
+    ExtractNodes {
+      events: self,
+      peeked: None,
+    }
+  }
+
+  /// Decide on the header that was read, when it's complete
+  fn finish_header(&mut self) {
+    if self.header.is_empty() {
+      self.selected = false;
+      return;
+    }
+    let header = EventHeader(&self.header);
+    let parent = &mut self.levels[self.depth];
+    self.selected = self
+      .query
+      .match_node(&header, parent, self.depth == 0, &mut self.matched);
+    parent.push_sibling(&self.matched);
+    if self.selected {
+      self.ready.extend(self.header.drain(..));
+    } else {
+      self.header.clear();
+    }
+  }
+
+  /// Go into the children of the last header
+  fn begin(&mut self) {
+    self.depth += 1;
+    if self.levels.len() == self.depth {
+      self.levels.push(Prefixes::default());
+    }
+    let (parents, levels) = self.levels.split_at_mut(self.depth);
+    levels[0].enter(&parents[self.depth - 1], &self.matched);
+  }
+}
+
+impl<'text, I: Iterator<Item = Result<Event<'text>, stream::Error>>> Iterator for Extract<'_, 'text, I> {
+  type Item = Result<Event<'text>, stream::Error>;
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(event) = self.ready.pop_front() {
+        return Some(Ok(event));
+      }
+      let event = match self.events.next() {
+        Some(Ok(event)) => event,
+        Some(Err(error)) => return Some(Err(error)),
+        None => {
+          self.finish_header();
+          return self.ready.pop_front().map(Ok);
+        }
+      };
+      if self.inside > 0 {
+        match event {
+          Event::Begin => self.inside += 1,
+          Event::End => self.inside -= 1,
+          _ => {}
+        }
+        return Some(Ok(event));
+      }
+      match event {
+        Event::Node { .. } => {
+          self.finish_header();
+          self.header.push(event);
+        }
+        Event::Entry { .. } => self.header.push(event),
+        Event::Begin => {
+          self.finish_header();
+          if self.selected {
+            self.inside = 1;
+            self.ready.push_back(event);
+          } else {
+            self.begin();
+          }
+        }
+        Event::End => {
+          self.finish_header();
+          self.depth = self.depth.saturating_sub(1);
+        }
+      }
+    }
+  }
+}
+
+/// An [`Iterator`] of the nodes a query matches, see [`Extract::nodes`]
+pub struct ExtractNodes<'q, 'text, I> {
+  events: Extract<'q, 'text, I>,
+  /// the start of the next node
+  peeked: Option<Event<'text>>,
+}
+
+impl<'text, I: Iterator<Item = Result<Event<'text>, stream::Error>>> Iterator for ExtractNodes<'_, 'text, I> {
+  type Item = Result<Node<'text>, stream::Error>;
+  fn next(&mut self) -> Option<Self::Item> {
+    let mut events = Vec::new();
+    events.extend(self.peeked.take());
+    let mut depth = 0;
+    loop {
+      let event = match self.events.next() {
+        Some(Ok(event)) => event,
+        Some(Err(error)) => return Some(Err(error)),
+        None => break,
+      };
+      match event {
+        Event::Node { .. } if depth == 0 && !events.is_empty() => {
+          self.peeked = Some(event);
+          break;
+        }
+        Event::Begin => depth += 1,
+        Event::End => depth -= 1,
+        _ => {}
+      }
+      events.push(event);
+    }
+    Document::from_iter(events).nodes.pop().map(Ok)
+  }
+}
+
+/// What filters look at in a node, so they can run on parser events too
+trait Header {
+  fn name(&self) -> &str;
+  fn type_hint(&self) -> Option<&str>;
+  /// An argument or property, with its type annotation
+  fn lookup(&self, key: EntryKey) -> Option<(&Value<'_>, Option<&str>)>;
+}
+
+impl Header for Node<'_> {
+  fn name(&self) -> &str {
+    Node::name(self)
+  }
+  fn type_hint(&self) -> Option<&str> {
+    Node::type_hint(self)
+  }
+  fn lookup(&self, key: EntryKey) -> Option<(&Value<'_>, Option<&str>)> {
+    self.entry(key).map(|entry| (&entry.value, entry.type_hint()))
+  }
+}
+
+/// A `Node` event followed by its `Entry` events
+struct EventHeader<'a, 'text>(&'a [Event<'text>]);
+
+impl Header for EventHeader<'_, '_> {
+  fn name(&self) -> &str {
+    match self.0.first() {
+      Some(Event::Node { name, .. }) => name,
+      _ => "",
+    }
+  }
+  fn type_hint(&self) -> Option<&str> {
+    match self.0.first() {
+      Some(Event::Node { r#type, .. }) => r#type.as_deref(),
+      _ => None,
+    }
+  }
+  fn lookup(&self, key: EntryKey) -> Option<(&Value<'_>, Option<&str>)> {
+    let mut entries = self.0.iter().filter_map(|event| match event {
+      Event::Entry { key, r#type, value, .. } => Some((key.as_deref(), value, r#type.as_deref())),
+      _ => None,
+    });
+    let found = match key {
+      EntryKey::Pos(index) => entries.filter(|(key, ..)| key.is_none()).nth(index),
+      // right-most property overrides value
+      EntryKey::Name(name) => entries.rfind(|(key, ..)| *key == Some(name)),
+    };
+    found.map(|(_, value, r#type)| (value, r#type))
+  }
+}
+
+impl Filter {
+  fn matches(&self, node: &impl Header) -> bool {
+    let Filter::Nodes { r#type, name, matchers } = self else {
+      return false;
+    };
+    let type_matches = match r#type {
+      None => true,
+      Some(None) => node.type_hint().is_some(),
+      Some(Some(r#type)) => node.type_hint() == Some(r#type.as_str()),
+    };
+    type_matches
+      && name.as_ref().is_none_or(|name| node.name() == name)
+      && matchers.iter().all(|matcher| matcher.matches(node))
+  }
+}
+
+impl Matcher {
+  fn matches(&self, node: &impl Header) -> bool {
+    // the value and its type annotation
+    let (value, r#type) = match &self.accessor {
+      Accessor::Any => return true,
+      Accessor::Val(index) => match node.lookup(EntryKey::Pos(*index)) {
+        Some((value, r#type)) => (Cow::Borrowed(value), r#type),
+        None => return false,
+      },
+      Accessor::Prop(key) => match node.lookup(EntryKey::Name(key)) {
+        Some((value, r#type)) => (Cow::Borrowed(value), r#type),
+        None => return false,
+      },
+      Accessor::Name => (Cow::Owned(Value::from(node.name())), None),
+      Accessor::Tag => match node.type_hint() {
+        Some(r#type) => (Cow::Owned(Value::from(r#type)), None),
+        None => return false,
+      },
+    };
+    let Some((op, operand)) = &self.comparison else {
+      return true;
+    };
+    let operand = match operand {
+      Operand::Type(expected) => {
+        return match op {
+          Op::Eq => r#type == Some(expected.as_str()),
+          Op::Ne => r#type != Some(expected.as_str()),
+          _ => false,
+        }
+      }
+      Operand::Value(operand) => operand,
+    };
+    let numbers = is_number(&value) && is_number(operand);
+    match (op, value.as_str(), operand.as_str()) {
+      (Op::Eq, ..) => value.as_ref().cmp(operand) == Ordering::Equal,
+      (Op::Ne, ..) => value.as_ref().cmp(operand) != Ordering::Equal,
+      (Op::Lt, ..) => numbers && value.as_ref() < operand,
+      (Op::Le, ..) => numbers && value.as_ref() <= operand,
+      (Op::Gt, ..) => numbers && value.as_ref() > operand,
+      (Op::Ge, ..) => numbers && value.as_ref() >= operand,
+      (Op::StartsWith, Some(text), Some(part)) => text.starts_with(part),
+      (Op::EndsWith, Some(text), Some(part)) => text.ends_with(part),
+      (Op::Contains, Some(text), Some(part)) => text.contains(part),
+      _ => false,
+    }
+  }
+}
+
+fn is_number(value: &Value) -> bool {
+  matches!(value, Value::Integer(_) | Value::BigInteger(_) | Value::Float(_))
+}
+
+struct QueryParser<'a> {
+  text: &'a str,
+  at: usize,
+}
+
+impl QueryParser<'_> {
+  fn rest(&self) -> &str {
+    &self.text[self.at..]
+  }
+  fn eat(&mut self, token: &str) -> bool {
+    let found = self.rest().starts_with(token);
+    if found {
+      self.at += token.len();
+    }
+    found
+  }
+  fn expect(&mut self, token: &str, message: &'static str) -> Result<(), Error> {
+    match self.eat(token) {
+      true => Ok(()),
+      false => Err(Error::new(message, self.at)),
+    }
+  }
+  fn space(&mut self) -> Result<(), Error> {
+    self.at = piece::space(self.text, self.at).map_err(|error| self.error(error))?;
+    Ok(())
+  }
+  fn error(&self, error: stream::Error) -> Error {
+    Error {
+      message: Cow::Owned(error.kind().to_string()),
+      offset: error.offset().unwrap_or(self.text.len()),
+    }
+  }
+  fn string(&mut self) -> Result<String, Error> {
+    let (at, string) = piece::string(self.text, self.at).map_err(|error| self.error(error))?;
+    self.at = at;
+    Ok(string.into_owned())
+  }
+  /// `"(" string? ")"`, after the `(`
+  fn type_hint(&mut self) -> Result<Option<String>, Error> {
+    self.space()?;
+    if self.eat(")") {
+      return Ok(None);
+    }
+    let r#type = self.string()?;
+    self.space()?;
+    self.expect(")", "expected `)`")?;
+    Ok(Some(r#type))
+  }
+
+  /// `filter (space* combinator space* filter)*`
+  fn selector(&mut self) -> Result<Selector, Error> {
+    let mut selector = Selector {
+      filters: vec![self.filter()?],
+      combinators: Vec::new(),
+    };
+    loop {
+      let before = self.at;
+      self.space()?;
+      let combinator = if self.eat(">>") {
+        Combinator::Descendant
+      } else if self.eat(">") {
+        Combinator::Child
+      } else if self.eat("+") {
+        Combinator::Next
+      } else if self.eat("~") {
+        Combinator::After
+      } else {
+        self.at = before;
+        return Ok(selector);
+      };
+      self.space()?;
+      let at = self.at;
+      let filter = self.filter()?;
+      if filter == Filter::Top {
+        return Err(Error::new("`top()` has to come first", at));
+      }
+      selector.combinators.push(combinator);
+      selector.filters.push(filter);
+    }
+  }
+
+  /// `top()` or `type? name? matcher*`, with at least one of them
+  fn filter(&mut self) -> Result<Filter, Error> {
+    if self.eat("top(") {
+      self.space()?;
+      self.expect(")", "expected `)`")?;
+      return Ok(Filter::Top);
+    }
+    let start = self.at;
+    let r#type = match self.eat("(") {
+      true => Some(self.type_hint()?),
+      false => None,
+    };
+    let name = match self.rest().chars().next() {
+      Some(ch) if ch != '[' && ch != '|' && !ch.is_whitespace() => Some(self.string()?),
+      _ => None,
+    };
+    let mut matchers = Vec::new();
+    while self.eat("[") {
+      matchers.push(self.matcher()?);
+    }
+    if self.at == start {
+      return Err(Error::new("expected a filter", start));
+    }
+    Ok(Filter::Nodes { r#type, name, matchers })
+  }
+
+  /// `accessor (op operand)? "]"`, after the `[`
+  fn matcher(&mut self) -> Result<Matcher, Error> {
+    self.space()?;
+    if self.eat("]") {
+      return Ok(Matcher {
+        accessor: Accessor::Any,
+        comparison: None,
+      });
+    }
+    let accessor = self.accessor()?;
+    self.space()?;
+    let mut comparison = None;
+    if !self.eat("]") {
+      let op = self.op()?;
+      self.space()?;
+      let operand = match self.eat("(") {
+        true => match self.type_hint()? {
+          Some(r#type) => Operand::Type(r#type),
+          None => return Err(Error::new("expected a type annotation", self.at - 1)),
+        },
+        false => {
+          let (at, value) = piece::value(self.text, self.at).map_err(|error| self.error(error))?;
+          self.at = at;
+          Operand::Value(value.into_owned())
+        }
+      };
+      comparison = Some((op, operand));
+      self.space()?;
+      self.expect("]", "expected `]`")?;
+    }
+    Ok(Matcher { accessor, comparison })
+  }
+
+  fn accessor(&mut self) -> Result<Accessor, Error> {
+    let accessor = if self.eat("val(") {
+      self.space()?;
+      let mut index = 0;
+      if !self.rest().starts_with(')') {
+        let at = self.at;
+        let (end, value) = piece::value(self.text, self.at).map_err(|error| self.error(error))?;
+        index = value.as_u64().ok_or(Error::new("expected an argument index", at))? as usize;
+        self.at = end;
+        self.space()?;
+      }
+      Accessor::Val(index)
+    } else if self.eat("prop(") {
+      self.space()?;
+      let key = self.string()?;
+      self.space()?;
+      Accessor::Prop(key)
+    } else if self.eat("name(") {
+      self.space()?;
+      Accessor::Name
+    } else if self.eat("tag(") {
+      self.space()?;
+      Accessor::Tag
+    } else {
+      return Ok(Accessor::Prop(self.string()?));
+    };
+    self.expect(")", "expected `)`")?;
+    Ok(accessor)
+  }
+
+  fn op(&mut self) -> Result<Op, Error> {
+    const OPS: [(&str, Op); 9] = [
+      (">=", Op::Ge),
+      ("<=", Op::Le),
+      ("!=", Op::Ne),
+      ("^=", Op::StartsWith),
+      ("$=", Op::EndsWith),
+      ("*=", Op::Contains),
+      ("=", Op::Eq),
+      (">", Op::Gt),
+      ("<", Op::Lt),
+    ];
+    match OPS.iter().find(|(token, _)| self.rest().starts_with(token)) {
+      Some(&(token, op)) => {
+        self.at += token.len();
+        Ok(op)
+      }
+      None => Err(Error::new("expected `]` or a comparison", self.at)),
+    }
+  }
+}
@@ -0,0 +1,696 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! [serde] serialization into a document or KDL text, requires the `serde`
+//! feature
+//!
+//! Start with [`to_string`] or [`to_document`]. The output reads back with
+//! [`de`](crate::de), using the same field names.
+//!
+//! ## Mapping
+//!
+//! The top level is a [`Document`]: a struct or map becomes one node per
+//! field, a sequence becomes nodes named `-`, and an enum variant becomes a
+//! node named after the variant.
+//!
+//! Inside a struct or map, fields are placed by what they hold:
+//! - Single values become properties: `server port=80`. At the top level,
+//!   where there are no properties, they become nodes instead: `port 80`.
+//! - Sequences of single values become one child with those arguments:
+//!   `tags a b c`.
+//! - Other sequences become one child per element, all with the field name:
+//!   `listen port=80; listen port=53`. A sequence with one element that
+//!   wouldn't read back as a sequence is wrapped in a child named `-`
+//!   instead, see the [`de`](crate::de#mapping) mapping.
+//! - Structs and maps become a child, with their fields placed the same way.
+//! - `None` is left out.
+//!
+//! The [`de`](crate::de) struct field names also work here: `"0"`, `"1"`, …
+//! for arguments in field order, [`ARGUMENTS`] for a sequence of arguments,
+//! [`NAME`] and [`TYPE`] to set the node name and type annotation, and
+//! [`CHILDREN`] for a sequence of children (named `-` unless they set their
+//! own [`NAME`]).
+//!
+//! To pick a placement for a single field, use [`property`] or [`child`] with
+//! `#[serde(with = "...")]`:
+//!
+//! ```
+//! # use serde::Serialize;
+//! #[derive(Serialize)]
+//! struct Server {
+//!   #[serde(rename = "0")]
+//!   host: &'static str,
+//!   #[serde(with = "kdlite::ser::child")]
+//!   port: u16,
+//!   timeout: u32,
+//! }
+//! #[derive(Serialize)]
+//! struct Config {
+//!   server: Server,
+//! }
+//! let server = Server { host: "localhost", port: 80, timeout: 30 };
+//! let text = kdlite::ser::to_string(&Config { server }).unwrap();
+//! assert_eq!(text, "server localhost timeout=30 {\n    port 80\n}");
+//! ```
+//!
+//! Unit enum variants are strings. Other variants set the type annotation of
+//! the node or entry they're in: `(tcp)listen port=80` or `(udp)53`, or
+//! become a single child named after the variant if there already is one.
+//!
+//! [`ARGUMENTS`]: crate::de::ARGUMENTS
+//! [`NAME`]: crate::de::NAME
+//! [`TYPE`]: crate::de::TYPE
+//! [`CHILDREN`]: crate::de::CHILDREN
+
+use std::fmt;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::de::{ARGUMENTS, CHILDREN, NAME, TYPE};
+use crate::dom::{Document, Entry, Node, Value};
+
+// newtype struct names used by `property` and `child`
+const PROPERTY: &str = "$kdlite::property";
+const CHILD: &str = "$kdlite::child";
+
+/// A serialization error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+  message: String,
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+impl std::error::Error for Error {}
+impl ser::Error for Error {
+  fn custom<T: fmt::Display>(msg: T) -> Self {
+    Self {
+      message: msg.to_string(),
+    }
+  }
+}
+
+fn error(message: impl fmt::Display) -> Error {
+  ser::Error::custom(message)
+}
+
+/// Serialize into KDL text
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+  to_document(value).map(|document| document.to_string())
+}
+
+/// Serialize into a document
+pub fn to_document<T: Serialize + ?Sized>(value: &T) -> Result<Document<'static>, Error> {
+  let nodes = match value.serialize(Serializer)? {
+    Shape::Map(fields) => {
+      let mut node = NodeBuilder::document();
+      for (key, shape) in fields {
+        node.field(key, shape)?;
+      }
+      node.children
+    }
+    Shape::Seq(items) => items
+      .into_iter()
+      .map(|item| into_node("-".into(), item))
+      .collect::<Result<_, _>>()?,
+    Shape::Variant(variant, content) => vec![into_node(variant, *content)?],
+    Shape::Skip => Vec::new(),
+    Shape::Scalar(_) | Shape::Property(_) | Shape::Child(_) => {
+      return Err(error(
+        "expected a struct, map, sequence, or enum variant at the top level",
+      ))
+    }
+  };
+  Ok(Document { nodes })
+}
+
+/// Serialize a field as a property, for `#[serde(with = "kdlite::ser::property")]`
+///
+/// The field has to be a single value, or an enum variant holding one.
+/// Deserializing is unchanged.
+pub mod property {
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+  /// Serialize as a property
+  pub fn serialize<T: Serialize + ?Sized, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_newtype_struct(super::PROPERTY, value)
+  }
+  /// Deserialize as usual
+  pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    T::deserialize(deserializer)
+  }
+}
+
+/// Serialize a field as child nodes, for `#[serde(with = "kdlite::ser::child")]`
+///
+/// Deserializing is unchanged.
+pub mod child {
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+  /// Serialize as child nodes
+  pub fn serialize<T: Serialize + ?Sized, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_newtype_struct(super::CHILD, value)
+  }
+  /// Deserialize as usual
+  pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    T::deserialize(deserializer)
+  }
+}
+
+/// A serialized value, before knowing where it goes
+enum Shape {
+  /// `None`
+  Skip,
+  Scalar(Value<'static>),
+  Seq(Vec<Shape>),
+  Map(Vec<(String, Shape)>),
+  Variant(String, Box<Shape>),
+  Property(Box<Shape>),
+  Child(Box<Shape>),
+}
+
+impl Shape {
+  /// As an entry, if it's a single value
+  fn entry(&self) -> Option<Entry<'static>> {
+    match self {
+      Self::Scalar(value) => Some(Entry::new_value(value.clone())),
+      Self::Variant(variant, content) => {
+        let mut entry = content.entry().filter(|entry| entry.type_hint().is_none())?;
+        entry.set_type_hint(Some(variant.clone()));
+        Some(entry)
+      }
+      Self::Property(inner) | Self::Child(inner) => inner.entry(),
+      Self::Skip | Self::Seq(_) | Self::Map(_) => None,
+    }
+  }
+  /// As an argument, with `None` as `#null`
+  fn argument(&self) -> Option<Entry<'static>> {
+    match self {
+      Self::Skip => Some(Entry::new_value(Value::Null)),
+      _ => self.entry(),
+    }
+  }
+}
+
+/// Convert to a node with a name
+fn into_node(name: String, shape: Shape) -> Result<Node<'static>, Error> {
+  let mut node = Node::new(name);
+  match shape {
+    Shape::Skip => node.entries.push(Entry::new_value(Value::Null)),
+    Shape::Scalar(value) => node.entries.push(Entry::new_value(value)),
+    Shape::Seq(items) => {
+      if let Some(arguments) = items.iter().map(Shape::argument).collect() {
+        node.entries = arguments;
+      } else {
+        let children = items.into_iter().map(|item| into_node("-".into(), item));
+        node.children = Some(Document {
+          nodes: children.collect::<Result<_, _>>()?,
+        });
+      }
+    }
+    Shape::Map(fields) => {
+      let mut builder = NodeBuilder::new(node);
+      for (key, shape) in fields {
+        builder.field(key, shape)?;
+      }
+      node = builder.finish();
+    }
+    Shape::Variant(variant, content) => {
+      let mut content = into_node(name_of(&node), *content)?;
+      if content.type_hint().is_none() {
+        content.set_type_hint(Some(variant));
+        node = content;
+      } else {
+        content.set_name(variant);
+        node.children = Some(Document { nodes: vec![content] });
+      }
+    }
+    Shape::Property(inner) | Shape::Child(inner) => return into_node(name_of(&node), *inner),
+  }
+  Ok(node)
+}
+
+fn name_of(node: &Node) -> String {
+  node.name().to_owned()
+}
+
+/// Whether a group of one node reads back as a sequence of that node, like
+/// in [`de`](crate::de#mapping)
+fn is_list(node: &Node) -> bool {
+  node.entries.iter().any(|entry| entry.key().is_some())
+    || node
+      .children
+      .iter()
+      .flat_map(|children| &children.nodes)
+      .any(|child| child.name() != "-")
+}
+
+/// Convert to nodes for a field
+fn group(key: String, shape: Shape) -> Result<Vec<Node<'static>>, Error> {
+  let items = match shape {
+    Shape::Seq(items) if items.iter().any(|item| item.argument().is_none()) => items,
+    Shape::Property(inner) | Shape::Child(inner) => return group(key, *inner),
+    shape => return Ok(vec![into_node(key, shape)?]),
+  };
+  match items.len() {
+    1 => {
+      let node = into_node(key.clone(), items.into_iter().next().unwrap())?;
+      if is_list(&node) {
+        return Ok(vec![node]);
+      }
+      let mut wrapper = Node::new(key);
+      let mut node = node;
+      node.set_name("-");
+      wrapper.children = Some(Document { nodes: vec![node] });
+      Ok(vec![wrapper])
+    }
+    _ => items.into_iter().map(|item| into_node(key.clone(), item)).collect(),
+  }
+}
+
+/// A node being filled in from struct or map fields
+struct NodeBuilder {
+  node: Node<'static>,
+  children: Vec<Node<'static>>,
+  /// top level, with no entries
+  document: bool,
+}
+
+impl NodeBuilder {
+  fn new(node: Node<'static>) -> Self {
+    Self {
+      node,
+      children: Vec::new(),
+      document: false,
+    }
+  }
+  fn document() -> Self {
+    Self {
+      node: Node::new("-"),
+      children: Vec::new(),
+      document: true,
+    }
+  }
+  fn finish(mut self) -> Node<'static> {
+    if !self.children.is_empty() {
+      self.node.children = Some(Document { nodes: self.children });
+    }
+    self.node
+  }
+  fn text(key: &str, shape: Shape) -> Result<String, Error> {
+    match shape {
+      Shape::Scalar(Value::String(text)) => Ok(text.into_owned()),
+      _ => Err(error(format_args!("`{key}` must be a string"))),
+    }
+  }
+  fn field(&mut self, key: String, shape: Shape) -> Result<(), Error> {
+    let special = matches!(&*key, ARGUMENTS | NAME | TYPE | CHILDREN) || key.parse::<usize>().is_ok();
+    if self.document && (special || matches!(shape, Shape::Property(_))) {
+      return Err(error(format_args!(
+        "`{key}` can't be at the top level, which only has nodes"
+      )));
+    }
+    match shape {
+      Shape::Skip => {}
+      Shape::Property(inner) | Shape::Child(inner) if matches!(*inner, Shape::Skip) => {}
+      Shape::Child(inner) => self.children.extend(group(key, *inner)?),
+      Shape::Property(inner) => match inner.entry() {
+        Some(entry) => self.property(key, entry),
+        None => {
+          return Err(error(format_args!(
+            "`{key}` can't be a property, it isn't a single value"
+          )))
+        }
+      },
+      shape if key == ARGUMENTS => {
+        let Shape::Seq(items) = shape else {
+          return Err(error(format_args!("`{ARGUMENTS}` must be a sequence")));
+        };
+        for item in items {
+          let entry = item
+            .argument()
+            .ok_or_else(|| error(format_args!("`{ARGUMENTS}` can only have single values")))?;
+          self.node.entries.push(entry);
+        }
+      }
+      shape if key == NAME => self.node.set_name(Self::text(&key, shape)?),
+      shape if key == TYPE => self.node.set_type_hint(Some(Self::text(&key, shape)?)),
+      shape if key == CHILDREN => {
+        let Shape::Seq(items) = shape else {
+          return Err(error(format_args!("`{CHILDREN}` must be a sequence")));
+        };
+        for item in items {
+          self.children.push(into_node("-".into(), item)?);
+        }
+      }
+      shape if special => {
+        let entry = shape
+          .argument()
+          .ok_or_else(|| error(format_args!("argument `{key}` isn't a single value")))?;
+        self.node.entries.push(entry);
+      }
+      shape => match shape.entry() {
+        Some(entry) if !self.document => self.property(key, entry),
+        _ => self.children.extend(group(key, shape)?),
+      },
+    }
+    Ok(())
+  }
+  fn property(&mut self, key: String, mut entry: Entry<'static>) {
+    entry.set_key(Some(key));
+    self.node.entries.push(entry);
+  }
+}
+
+/// Serializes into a [`Shape`]
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+  type Ok = Shape;
+  type Error = Error;
+  type SerializeSeq = SeqShape;
+  type SerializeTuple = SeqShape;
+  type SerializeTupleStruct = SeqShape;
+  type SerializeTupleVariant = SeqShape;
+  type SerializeMap = MapShape;
+  type SerializeStruct = MapShape;
+  type SerializeStructVariant = MapShape;
+  fn serialize_bool(self, v: bool) -> Result<Shape, Error> {
+    Ok(Shape::Scalar(Value::Bool(v)))
+  }
+  fn serialize_i8(self, v: i8) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_i16(self, v: i16) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_i32(self, v: i32) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_i64(self, v: i64) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_i128(self, v: i128) -> Result<Shape, Error> {
+    Ok(Shape::Scalar(Value::Integer(v)))
+  }
+  fn serialize_u8(self, v: u8) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_u16(self, v: u16) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_u32(self, v: u32) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_u64(self, v: u64) -> Result<Shape, Error> {
+    self.serialize_i128(v.into())
+  }
+  fn serialize_u128(self, v: u128) -> Result<Shape, Error> {
+    match i128::try_from(v) {
+      Ok(v) => self.serialize_i128(v),
+      Err(_) => Ok(Shape::Scalar(Value::BigInteger(v.to_string().into()))),
+    }
+  }
+  fn serialize_f32(self, v: f32) -> Result<Shape, Error> {
+    self.serialize_f64(v.into())
+  }
+  fn serialize_f64(self, v: f64) -> Result<Shape, Error> {
+    Ok(Shape::Scalar(Value::Float(v)))
+  }
+  fn serialize_char(self, v: char) -> Result<Shape, Error> {
+    self.serialize_str(v.encode_utf8(&mut [0; 4]))
+  }
+  fn serialize_str(self, v: &str) -> Result<Shape, Error> {
+    Ok(Shape::Scalar(Value::String(v.to_owned().into())))
+  }
+  fn serialize_bytes(self, v: &[u8]) -> Result<Shape, Error> {
+    Ok(Shape::Seq(
+      v.iter()
+        .map(|&byte| Shape::Scalar(Value::Integer(byte.into())))
+        .collect(),
+    ))
+  }
+  fn serialize_none(self) -> Result<Shape, Error> {
+    Ok(Shape::Skip)
+  }
+  fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Shape, Error> {
+    value.serialize(self)
+  }
+  fn serialize_unit(self) -> Result<Shape, Error> {
+    Ok(Shape::Scalar(Value::Null))
+  }
+  fn serialize_unit_struct(self, _name: &'static str) -> Result<Shape, Error> {
+    self.serialize_unit()
+  }
+  fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Shape, Error> {
+    self.serialize_str(variant)
+  }
+  fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<Shape, Error> {
+    let shape = value.serialize(self)?;
+    Ok(match name {
+      PROPERTY => Shape::Property(Box::new(shape)),
+      CHILD => Shape::Child(Box::new(shape)),
+      _ => shape,
+    })
+  }
+  fn serialize_newtype_variant<T: Serialize + ?Sized>(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    value: &T,
+  ) -> Result<Shape, Error> {
+    Ok(Shape::Variant(variant.to_owned(), Box::new(value.serialize(self)?)))
+  }
+  fn serialize_seq(self, len: Option<usize>) -> Result<SeqShape, Error> {
+    Ok(SeqShape {
+      variant: None,
+      items: Vec::with_capacity(len.unwrap_or(0)),
+    })
+  }
+  fn serialize_tuple(self, len: usize) -> Result<SeqShape, Error> {
+    self.serialize_seq(Some(len))
+  }
+  fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqShape, Error> {
+    self.serialize_seq(Some(len))
+  }
+  fn serialize_tuple_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    len: usize,
+  ) -> Result<SeqShape, Error> {
+    Ok(SeqShape {
+      variant: Some(variant),
+      items: Vec::with_capacity(len),
+    })
+  }
+  fn serialize_map(self, len: Option<usize>) -> Result<MapShape, Error> {
+    Ok(MapShape {
+      variant: None,
+      fields: Vec::with_capacity(len.unwrap_or(0)),
+      key: None,
+    })
+  }
+  fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapShape, Error> {
+    self.serialize_map(Some(len))
+  }
+  fn serialize_struct_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    len: usize,
+  ) -> Result<MapShape, Error> {
+    Ok(MapShape {
+      variant: Some(variant),
+      fields: Vec::with_capacity(len),
+      key: None,
+    })
+  }
+}
+
+/// Wrap in an enum variant, if there is one
+fn variant(variant: Option<&str>, shape: Shape) -> Shape {
+  match variant {
+    Some(variant) => Shape::Variant(variant.to_owned(), Box::new(shape)),
+    None => shape,
+  }
+}
+
+struct SeqShape {
+  variant: Option<&'static str>,
+  items: Vec<Shape>,
+}
+
+impl ser::SerializeSeq for SeqShape {
+  type Ok = Shape;
+  type Error = Error;
+  fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    self.items.push(value.serialize(Serializer)?);
+    Ok(())
+  }
+  fn end(self) -> Result<Shape, Error> {
+    Ok(variant(self.variant, Shape::Seq(self.items)))
+  }
+}
+
+macro_rules! forward_to_seq {
+  ($($trait:ident::$method:ident)*) => {$(
+    impl ser::$trait for SeqShape {
+      type Ok = Shape;
+      type Error = Error;
+      fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+      }
+      fn end(self) -> Result<Shape, Error> {
+        ser::SerializeSeq::end(self)
+      }
+    }
+  )*};
+}
+
+forward_to_seq! {
+  SerializeTuple::serialize_element
+  SerializeTupleStruct::serialize_field
+  SerializeTupleVariant::serialize_field
+}
+
+struct MapShape {
+  variant: Option<&'static str>,
+  fields: Vec<(String, Shape)>,
+  key: Option<String>,
+}
+
+impl ser::SerializeMap for MapShape {
+  type Ok = Shape;
+  type Error = Error;
+  fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+    self.key = Some(key.serialize(KeySerializer)?);
+    Ok(())
+  }
+  fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    let key = self.key.take().expect("value serialized before key");
+    self.fields.push((key, value.serialize(Serializer)?));
+    Ok(())
+  }
+  fn end(self) -> Result<Shape, Error> {
+    Ok(variant(self.variant, Shape::Map(self.fields)))
+  }
+}
+
+impl ser::SerializeStruct for MapShape {
+  type Ok = Shape;
+  type Error = Error;
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+    self.fields.push((key.to_owned(), value.serialize(Serializer)?));
+    Ok(())
+  }
+  fn end(self) -> Result<Shape, Error> {
+    ser::SerializeMap::end(self)
+  }
+}
+
+impl ser::SerializeStructVariant for MapShape {
+  type Ok = Shape;
+  type Error = Error;
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+    ser::SerializeStruct::serialize_field(self, key, value)
+  }
+  fn end(self) -> Result<Shape, Error> {
+    ser::SerializeMap::end(self)
+  }
+}
+
+/// Serializes map keys, which have to be strings or numbers
+struct KeySerializer;
+
+macro_rules! key_to_string {
+  ($($method:ident($type:ty))*) => {$(
+    fn $method(self, v: $type) -> Result<String, Error> {
+      Ok(v.to_string())
+    }
+  )*};
+}
+
+macro_rules! key_unsupported {
+  ($($method:ident($($arg:ty),*))*) => {$(
+    fn $method(self, $(_: $arg),*) -> Result<String, Error> {
+      Err(error("map keys must be strings or numbers"))
+    }
+  )*};
+}
+
+impl ser::Serializer for KeySerializer {
+  type Ok = String;
+  type Error = Error;
+  type SerializeSeq = Impossible<String, Error>;
+  type SerializeTuple = Impossible<String, Error>;
+  type SerializeTupleStruct = Impossible<String, Error>;
+  type SerializeTupleVariant = Impossible<String, Error>;
+  type SerializeMap = Impossible<String, Error>;
+  type SerializeStruct = Impossible<String, Error>;
+  type SerializeStructVariant = Impossible<String, Error>;
+  key_to_string! {
+    serialize_bool(bool) serialize_i8(i8) serialize_i16(i16) serialize_i32(i32) serialize_i64(i64)
+    serialize_i128(i128) serialize_u8(u8) serialize_u16(u16) serialize_u32(u32) serialize_u64(u64)
+    serialize_u128(u128) serialize_char(char) serialize_str(&str)
+  }
+  key_unsupported! {
+    serialize_f32(f32) serialize_f64(f64) serialize_bytes(&[u8]) serialize_none() serialize_unit()
+    serialize_unit_struct(&'static str)
+  }
+  fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, Error> {
+    value.serialize(self)
+  }
+  fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<String, Error> {
+    Ok(variant.to_owned())
+  }
+  fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<String, Error> {
+    value.serialize(self)
+  }
+  fn serialize_newtype_variant<T: Serialize + ?Sized>(
+    self,
+    _name: &'static str,
+    _index: u32,
+    _variant: &'static str,
+    _value: &T,
+  ) -> Result<String, Error> {
+    self.serialize_unit()
+  }
+  fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+    Err(error("map keys must be strings or numbers"))
+  }
+  fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+    Err(error("map keys must be strings or numbers"))
+  }
+  fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Error> {
+    Err(error("map keys must be strings or numbers"))
+  }
+  fn serialize_tuple_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    _variant: &'static str,
+    _len: usize,
+  ) -> Result<Self::SerializeTupleVariant, Error> {
+    Err(error("map keys must be strings or numbers"))
+  }
+  fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+    Err(error("map keys must be strings or numbers"))
+  }
+  fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Error> {
+    Err(error("map keys must be strings or numbers"))
+  }
+  fn serialize_struct_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    _variant: &'static str,
+    _len: usize,
+  ) -> Result<Self::SerializeStructVariant, Error> {
+    Err(error("map keys must be strings or numbers"))
+  }
+}
@@ -1,22 +1,23 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 //! The actual parser
 //!
-//! While this is technically a streaming parser, it operates on a complete
-//! `&str` data, and requires that data to be borrowed for the duration of the
-//! returned events.
-
-// TODO: spec-breaking configs:
-// - v2_0_1: draft spec syntax differences
-// - really_raw: allow arbitrary bytes in raw strings, including newlines in
-//   single-line strings (which remain unprocessed)
-// TODO: some alternative input api: peek/consume utf-8 stream?
+//! While this is technically a streaming parser, [`Parser`] operates on a
+//! complete `&str` data, and requires that data to be borrowed for the
+//! duration of the returned events.
+//! For input that doesn't fit in memory, [`ChunkParser`] and [`ReadParser`]
+//! take it in pieces and return owned events instead.
+
 // TODO: fuzzing!
 
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::fmt;
+use std::io;
+use std::ops::Range;
 
+use crate::diagnostic::Diagnostic;
 use crate::dom::Value;
-use crate::{cow_static, IdentDisplay};
+use crate::{cow_static, IdentDisplay, IdentDisplayV1};
 
 /// A parsing error
 /// `usize` arguments are byte positions in the source text
@@ -41,6 +42,9 @@ pub enum Error {
   UnexpectedNewline(usize),
   /// A number is invalid
   InvalidNumber(usize),
+  /// An integer is outside the range of `i128`, see
+  /// [`Parser::with_strict_integers`]
+  IntegerOverflow(usize),
   /// A keyword has an invalid name
   BadKeyword(usize),
   /// An identifier with an invalid name
@@ -55,15 +59,176 @@ pub enum Error {
   UnexpectedEof,
   /// An always-invalid character at this position
   BannedChar(char, usize),
+  /// Reading the input failed, see [`ReadParser`]
+  Io(io::Error),
+  /// The input isn't valid utf-8 (or utf-16, see [`decode`]) at this position
+  InvalidEncoding(usize),
+  /// The input starts with a utf-16 byte order mark, but has to be
+  /// transcoded with [`decode`] first, see [`Parser::from_bytes`]
+  Utf16,
+}
+
+impl Error {
+  /// The kind of error, without any position information
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      Self::ExpectedSpace(_) => ErrorKind::ExpectedSpace,
+      Self::ExpectedCloseParen(_) => ErrorKind::ExpectedCloseParen,
+      Self::ExpectedComment(_) => ErrorKind::ExpectedComment,
+      Self::ExpectedNewline(_) => ErrorKind::ExpectedNewline,
+      Self::ExpectedString(_) => ErrorKind::ExpectedString,
+      Self::ExpectedValue(_) => ErrorKind::ExpectedValue,
+      Self::UnexpectedCloseBracket(_) => ErrorKind::UnexpectedCloseBracket,
+      Self::UnexpectedNewline(_) => ErrorKind::UnexpectedNewline,
+      Self::InvalidNumber(_) => ErrorKind::InvalidNumber,
+      Self::IntegerOverflow(_) => ErrorKind::IntegerOverflow,
+      Self::BadKeyword(_) => ErrorKind::BadKeyword,
+      Self::BadIdentifier(_) => ErrorKind::BadIdentifier,
+      Self::BadEscape(_) => ErrorKind::BadEscape,
+      Self::BadIndent(_) => ErrorKind::BadIndent,
+      Self::MultipleChildren(_) => ErrorKind::MultipleChildren,
+      Self::UnexpectedEof => ErrorKind::UnexpectedEof,
+      Self::BannedChar(..) => ErrorKind::BannedChar,
+      Self::Io(_) => ErrorKind::Io,
+      Self::InvalidEncoding(_) => ErrorKind::InvalidEncoding,
+      Self::Utf16 => ErrorKind::Utf16,
+    }
+  }
+  /// Byte position of the error in the source text, `None` if the error
+  /// happened at the end of the text, while reading it, or is about all of it
+  pub fn offset(&self) -> Option<usize> {
+    match *self {
+      Self::ExpectedSpace(at)
+      | Self::ExpectedCloseParen(at)
+      | Self::ExpectedComment(at)
+      | Self::ExpectedNewline(at)
+      | Self::ExpectedString(at)
+      | Self::ExpectedValue(at)
+      | Self::UnexpectedCloseBracket(at)
+      | Self::UnexpectedNewline(at)
+      | Self::InvalidNumber(at)
+      | Self::IntegerOverflow(at)
+      | Self::BadKeyword(at)
+      | Self::BadIdentifier(at)
+      | Self::BadEscape(at)
+      | Self::BadIndent(at)
+      | Self::MultipleChildren(at)
+      | Self::BannedChar(_, at)
+      | Self::InvalidEncoding(at) => Some(at),
+      Self::UnexpectedEof | Self::Io(_) | Self::Utf16 => None,
+    }
+  }
+  /// Pair the error with its source text, to display it with line, column,
+  /// and a snippet of the offending line
+  pub fn diagnostic<'a>(&'a self, text: &'a str) -> Diagnostic<'a> {
+    Diagnostic::new(self, text)
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::BannedChar(ch, _) => write!(f, "{} {:?}", self.kind(), ch)?,
+      Self::Io(error) => write!(f, "{}: {}", self.kind(), error)?,
+      _ => fmt::Display::fmt(&self.kind(), f)?,
+    }
+    match self.offset() {
+      Some(at) => write!(f, " at byte {at}"),
+      None => Ok(()),
+    }
+  }
+}
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Io(error) => Some(error),
+      _ => None,
+    }
+  }
+}
+
+/// The kind of an [`Error`], for handling errors without matching every variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+  /// See [`Error::ExpectedSpace`]
+  ExpectedSpace,
+  /// See [`Error::ExpectedCloseParen`]
+  ExpectedCloseParen,
+  /// See [`Error::ExpectedComment`]
+  ExpectedComment,
+  /// See [`Error::ExpectedNewline`]
+  ExpectedNewline,
+  /// See [`Error::ExpectedString`]
+  ExpectedString,
+  /// See [`Error::ExpectedValue`]
+  ExpectedValue,
+  /// See [`Error::UnexpectedCloseBracket`]
+  UnexpectedCloseBracket,
+  /// See [`Error::UnexpectedNewline`]
+  UnexpectedNewline,
+  /// See [`Error::InvalidNumber`]
+  InvalidNumber,
+  /// See [`Error::IntegerOverflow`]
+  IntegerOverflow,
+  /// See [`Error::BadKeyword`]
+  BadKeyword,
+  /// See [`Error::BadIdentifier`]
+  BadIdentifier,
+  /// See [`Error::BadEscape`]
+  BadEscape,
+  /// See [`Error::BadIndent`]
+  BadIndent,
+  /// See [`Error::MultipleChildren`]
+  MultipleChildren,
+  /// See [`Error::UnexpectedEof`]
+  UnexpectedEof,
+  /// See [`Error::BannedChar`]
+  BannedChar,
+  /// See [`Error::Io`]
+  Io,
+  /// See [`Error::InvalidEncoding`]
+  InvalidEncoding,
+  /// See [`Error::Utf16`]
+  Utf16,
+}
+
+impl fmt::Display for ErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      Self::ExpectedSpace => "expected whitespace",
+      Self::ExpectedCloseParen => "expected `)`",
+      Self::ExpectedComment => "expected a comment or newline after line continuation",
+      Self::ExpectedNewline => "expected a newline",
+      Self::ExpectedString => "expected a string",
+      Self::ExpectedValue => "expected a value",
+      Self::UnexpectedCloseBracket => "unexpected `}`",
+      Self::UnexpectedNewline => "unexpected newline in single-line string",
+      Self::InvalidNumber => "invalid number",
+      Self::IntegerOverflow => "integer too large",
+      Self::BadKeyword => "invalid keyword",
+      Self::BadIdentifier => "invalid identifier",
+      Self::BadEscape => "invalid escape sequence",
+      Self::BadIndent => "indentation doesn't match the closing line of the multi-line string",
+      Self::MultipleChildren => "node has multiple children blocks",
+      Self::UnexpectedEof => "unexpected end of file",
+      Self::BannedChar => "disallowed character",
+      Self::Io => "failed to read input",
+      Self::InvalidEncoding => "invalid text encoding",
+      Self::Utf16 => "utf-16 text has to be decoded first",
+    })
+  }
 }
 
 type PResult<T> = Result<T, Error>;
+type Spanned<T> = (Span, T);
 
 /// A inner representation of a number
-#[derive(Clone, Copy)]
 enum NumberInner {
   F64(f64),
   I128(i128),
+  /// canonical decimal digits, see [`Value::BigInteger`]
+  Big(String),
 }
 
 /// a parsing event
@@ -110,6 +275,198 @@ impl Event<'_> {
     }
   }
 }
+/// An event without a literal, for [`write_stream`]
+impl<'text> From<Event<'text>> for (Event<'text>, Option<Literal<'text>>) {
+  fn from(value: Event<'text>) -> Self {
+    (value, None)
+  }
+}
+
+/// A range of bytes in the source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+  /// Position of the first byte
+  pub start: usize,
+  /// Position after the last byte
+  pub end: usize,
+}
+
+impl Span {
+  fn new(start: Pos, end: Pos) -> Self {
+    Self {
+      start: start.0,
+      end: end.0,
+    }
+  }
+  /// Does this span contain a byte position
+  pub fn contains(&self, offset: usize) -> bool {
+    (self.start..self.end).contains(&offset)
+  }
+  /// The smallest span containing both spans
+  pub fn join(self, other: Span) -> Span {
+    Span {
+      start: self.start.min(other.start),
+      end: self.end.max(other.end),
+    }
+  }
+}
+
+impl From<Span> for Range<usize> {
+  fn from(value: Span) -> Self {
+    value.start..value.end
+  }
+}
+
+/// Source positions of an [`Event::Entry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntrySpan {
+  /// The key, if it exists
+  pub key: Option<Span>,
+  /// The type hint, including parentheses
+  pub r#type: Option<Span>,
+  /// The value, including any quotes
+  pub value: Span,
+}
+
+impl EntrySpan {
+  /// Span of the entire entry
+  pub fn span(&self) -> Span {
+    let start = self.key.or(self.r#type).unwrap_or(self.value);
+    start.join(self.value)
+  }
+}
+
+/// How a value was written in the source text, see [`Parser::literals`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Literal<'text> {
+  /// The source text of the value, including any sign, quotes, or `#`
+  pub text: Cow<'text, str>,
+  /// The kind of literal
+  pub kind: LiteralKind,
+}
+
+impl Literal<'_> {
+  /// Convert into an owned value
+  pub fn into_owned(self) -> Literal<'static> {
+    Literal {
+      text: cow_static(self.text),
+      kind: self.kind,
+    }
+  }
+  /// Does the text still read as `value` in a version of the spec,
+  /// so it can be written instead of the value
+  pub(crate) fn represents(&self, value: &Value, version: Version) -> bool {
+    let syntax = Syntax {
+      version,
+      ..Syntax::default()
+    };
+    let grammar = Grammar::new(&self.text, syntax);
+    matches!(grammar.value(Pos(0)), Ok((end, parsed)) if end.0 == grammar.end().0 && parsed == *value)
+  }
+}
+
+/// The kind of a [`Literal`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LiteralKind {
+  /// A string without quotes, like `text`
+  Identifier,
+  /// A quoted string, like `"text"`, or `"""` if it's multi-line
+  Quoted {
+    /// Written with `"""`
+    multi_line: bool,
+  },
+  /// A raw string, like `#"text"#` (or `r#"text"#` in v1)
+  Raw {
+    /// Number of `#` around the string
+    hashes: usize,
+    /// Written with `"""`
+    multi_line: bool,
+  },
+  /// An integer, like `0xFF`
+  Integer {
+    /// The base, 2, 8, 10, or 16
+    radix: u32,
+  },
+  /// A float, like `1.50` or `1e3`
+  Float,
+  /// A keyword, like `#true`
+  Keyword,
+}
+
+impl LiteralKind {
+  /// Classify the source text of a value
+  fn new(text: &str, value: &Value) -> Self {
+    match value {
+      Value::String(_) => {
+        // v1 raw strings start with an `r`, which can't otherwise be
+        // followed by `#` or `"`
+        let raw = text
+          .strip_prefix('r')
+          .filter(|raw| raw.starts_with(['#', '"']))
+          .unwrap_or(text);
+        let unhashed = raw.trim_start_matches('#');
+        let multi_line = unhashed.starts_with("\"\"\"");
+        if !unhashed.starts_with('"') {
+          Self::Identifier
+        } else if raw.len() != text.len() || unhashed.len() != raw.len() {
+          Self::Raw {
+            hashes: raw.len() - unhashed.len(),
+            multi_line,
+          }
+        } else {
+          Self::Quoted { multi_line }
+        }
+      }
+      Value::Integer(_) | Value::BigInteger(_) => {
+        let text = text.strip_prefix(['+', '-']).unwrap_or(text);
+        let radix = match text.get(..2) {
+          Some("0b") => 2,
+          Some("0o") => 8,
+          Some("0x") => 16,
+          _ => 10,
+        };
+        Self::Integer { radix }
+      }
+      Value::Float(_) if !text.starts_with('#') => Self::Float,
+      Value::Float(_) | Value::Bool(_) | Value::Null => Self::Keyword,
+    }
+  }
+}
+
+/// Source positions of an [`Event`], see [`Parser::spanned`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventSpan {
+  /// Positions for [`Event::Node`]
+  Node {
+    /// The type hint, including parentheses
+    r#type: Option<Span>,
+    /// The name, including any quotes
+    name: Span,
+  },
+  /// Positions for [`Event::Entry`]
+  Entry(EntrySpan),
+  /// The `{` of [`Event::Begin`]
+  Begin(Span),
+  /// Positions for [`Event::End`]
+  End {
+    /// The `}`, empty at the end of the text if it's missing
+    brace: Span,
+    /// The entire children block, from `{` to `}`
+    children: Span,
+  },
+}
+
+impl EventSpan {
+  /// Span of the entire event
+  pub fn span(&self) -> Span {
+    match *self {
+      Self::Node { r#type, name } => r#type.unwrap_or(name).join(name),
+      Self::Entry(entry) => entry.span(),
+      Self::Begin(brace) | Self::End { brace, .. } => brace,
+    }
+  }
+}
 
 #[derive(Debug)]
 enum InnerEvent<'text> {
@@ -117,20 +474,26 @@ enum InnerEvent<'text> {
     sd: bool,
     r#type: Option<Cow<'text, str>>,
     name: Cow<'text, str>,
+    span: EventSpan,
   },
   PropValue {
     sd: bool,
     r#type: Option<Cow<'text, str>>,
     key: Option<Cow<'text, str>>,
     value: Value<'text>,
+    span: EventSpan,
   },
   Begin {
     sd: bool,
+    span: EventSpan,
+  },
+  End {
+    brace: Span,
   },
-  End,
   Done,
 }
 
+#[derive(Clone)]
 enum ParserState {
   /// right after init
   BeginDocument,
@@ -140,6 +503,8 @@ enum ParserState {
   NodeProps,
   /// only children left
   NodeChildren,
+  /// recovered at the end of the text, close remaining children
+  Unclosed,
   /// die
   Done,
 }
@@ -171,13 +536,56 @@ impl Pos {
   }
 }
 
-struct Grammar<'text>(&'text str);
+/// Grammar settings, see [`Parser::with_version`],
+/// [`Parser::with_really_raw`], [`Parser::with_strict_integers`], and
+/// [`Parser::literals`]
+#[derive(Clone, Copy, Default)]
+struct Syntax {
+  version: Version,
+  really_raw: bool,
+  strict_integers: bool,
+  literals: bool,
+}
+
+struct Grammar<'text> {
+  text: &'text str,
+  /// position of the start of `text` in the entire input
+  base: usize,
+  syntax: Syntax,
+  /// more input may follow `text`
+  partial: bool,
+  /// set when a partial grammar looks close enough to the end of `text`
+  /// that more input could change the result
+  starved: Cell<bool>,
+}
 
 // in this impl: anything in `backticks` (except that)
 // represents a kdl grammar item or expression
 impl<'text> Grammar<'text> {
-  fn tail(&self, at: Pos) -> &str {
-    &self.0[at.0..]
+  /// Longest lookahead any rule does without going through [`Grammar::tail`]
+  /// again (`"""` or a single character)
+  const LOOKAHEAD: usize = 4;
+  fn new(text: &'text str, syntax: Syntax) -> Self {
+    Self {
+      text,
+      base: 0,
+      syntax,
+      partial: false,
+      starved: Cell::new(false),
+    }
+  }
+  fn end(&self) -> Pos {
+    Pos(self.base + self.text.len())
+  }
+  fn tail(&self, at: Pos) -> &'text str {
+    let tail = &self.text[at.0 - self.base..];
+    if self.partial && tail.len() < Self::LOOKAHEAD {
+      self.starved.set(true);
+    }
+    tail
+  }
+  fn slice(&self, start: Pos, end: Pos) -> &'text str {
+    &self.text[start.0 - self.base..end.0 - self.base]
   }
   // TODO: i realize now this could be written a lot better as a
   // "(&Self, Pos) -> (char, Pos)", kinda like every other parser
@@ -195,25 +603,37 @@ impl<'text> Grammar<'text> {
     matches!(ch, '\u{0}'..='\u{8}' | '\u{E}'..='\u{1F}' | '\u{7F}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}')
   }
   // `identifier-char`
-  fn ident(ch: char) -> bool {
+  fn ident(&self, ch: char) -> bool {
     !(Self::banned(ch)
-      || Self::space(ch)
-      || Self::newline(ch)
-      || matches!(
-        ch,
-        '\\' | '/' | '(' | ')' | '{' | '}' | ';' | '[' | ']' | '"' | '#' | '='
-      ))
+      || self.space(ch)
+      || self.newline(ch)
+      || match self.syntax.version {
+        Version::V1 => matches!(
+          ch,
+          '\\' | '/' | '(' | ')' | '{' | '}' | '<' | '>' | ';' | '[' | ']' | '=' | ',' | '"'
+        ),
+        Version::V2 | Version::V2_0_1 => matches!(
+          ch,
+          '\\' | '/' | '(' | ')' | '{' | '}' | ';' | '[' | ']' | '"' | '#' | '='
+        ),
+      })
   }
   // `unicode-space`
-  fn space(ch: char) -> bool {
+  fn space(&self, ch: char) -> bool {
     matches!(
       ch,
       '\u{9}' | '\u{20}' | '\u{A0}' | '\u{1680}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}'
-    )
+    ) || (ch == '\u{B}' && self.syntax.version == Version::V2_0_1)
   }
   // `newline`
-  fn newline(ch: char) -> bool {
-    matches!(ch, '\u{A}'..='\u{D}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+  fn newline(&self, ch: char) -> bool {
+    match self.syntax.version {
+      // no vertical tab
+      Version::V1 | Version::V2_0_1 => {
+        matches!(ch, '\u{A}' | '\u{C}' | '\u{D}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+      }
+      Version::V2 => matches!(ch, '\u{A}'..='\u{D}' | '\u{85}' | '\u{2028}' | '\u{2029}'),
+    }
   }
   fn number_like(text: &str) -> bool {
     let text = text.strip_prefix(['+', '-']).unwrap_or(text);
@@ -269,8 +689,12 @@ impl<'text> Grammar<'text> {
       }
     }
     let radix = radix as u32;
+    let digits = buffer.strip_prefix('-').unwrap_or(&buffer);
     if let Ok(value) = i128::from_str_radix(&buffer, radix) {
       Some(NumberInner::I128(value))
+    } else if !digits.is_empty() && digits.chars().all(|ch| ch.is_digit(radix)) {
+      // only digits, so it's just too big
+      Some(NumberInner::Big(big_integer(negative, digits, radix)))
     } else if radix == 10 {
       if buffer.ends_with('.') {
         None
@@ -289,7 +713,7 @@ impl<'text> Grammar<'text> {
     loop {
       match self.top_char(at) {
         Some(ch) if Self::banned(ch) => return Err(Error::BannedChar(ch, at.0)),
-        Some(ch) if Self::newline(ch) => return Ok(at.offset_char(ch)),
+        Some(ch) if self.newline(ch) => return Ok(at.offset_char(ch)),
         None => return Ok(at),
         Some(ch) => at = at.offset_char(ch),
       }
@@ -327,7 +751,7 @@ impl<'text> Grammar<'text> {
     // valid: `unicode-space` `multi-line-comment`
     loop {
       match self.top_char(at) {
-        Some(ch) if Self::space(ch) => {
+        Some(ch) if self.space(ch) => {
           at = at.offset_char(ch);
         }
         Some('/') if self.top_char(at.offset_char('/')) == Some('*') => {
@@ -339,7 +763,7 @@ impl<'text> Grammar<'text> {
     // valid: `single-line-comment` `newline` `eof`
     match self.top_char(at) {
       Some('/') if self.top_char(at.offset_char('/')) == Some('/') => self.single_line_comment(at.offset_str("//")),
-      Some(ch) if Self::newline(ch) => Ok(at.offset_char(ch)),
+      Some(ch) if self.newline(ch) => Ok(at.offset_char(ch)),
       None => Ok(at),
       _ => Err(Error::ExpectedComment(at.0)),
     }
@@ -357,7 +781,7 @@ impl<'text> Grammar<'text> {
           Some('*') => at = self.multi_line_comment(at.offset_str("/*"))?,
           _ => break,
         },
-        Some(ch) if Self::newline(ch) || Self::space(ch) => {
+        Some(ch) if self.newline(ch) || self.space(ch) => {
           at = at.offset_char(ch);
         }
         _ => break,
@@ -377,7 +801,7 @@ impl<'text> Grammar<'text> {
         Some('/') if self.top_char(at.offset_char('/')) == Some('*') => {
           at = self.multi_line_comment(at.offset_str("/*"))?;
         }
-        Some(ch) if Self::space(ch) => {
+        Some(ch) if self.space(ch) => {
           at = at.offset_char(ch);
         }
         _ => break,
@@ -401,12 +825,12 @@ impl<'text> Grammar<'text> {
   fn identifier_string(&self, at: Pos) -> (Pos, &'text str) {
     let mut end = at;
     while let Some(ch) = self.top_char(end) {
-      if !Self::ident(ch) {
+      if !self.ident(ch) {
         break;
       }
       end = end.offset_char(ch);
     }
-    (end, &self.0[at.0..end.0])
+    (end, self.slice(at, end))
   }
   /// string escape after \
   fn escape(&self, at: Pos) -> PResult<(Pos, Option<char>)> {
@@ -418,7 +842,8 @@ impl<'text> Grammar<'text> {
       '"' => (at.offset_char('"'), Some('"')),
       'b' => (at.offset_char('b'), Some('\x08')),
       'f' => (at.offset_char('f'), Some('\x0C')),
-      's' => (at.offset_char('s'), Some(' ')),
+      '/' if self.syntax.version == Version::V1 => (at.offset_char('/'), Some('/')),
+      's' if self.syntax.version != Version::V1 => (at.offset_char('s'), Some(' ')),
       'u' => {
         let start = at.offset_char('u');
         let Some('{') = self.top_char(start) else {
@@ -433,17 +858,17 @@ impl<'text> Grammar<'text> {
             _ => return Err(Error::BadEscape(at.0)),
           }
         }
-        let number = u32::from_str_radix(&self.0[start.0..end.0], 16).map_err(|_| Error::BadEscape(at.0))?;
+        let number = u32::from_str_radix(self.slice(start, end), 16).map_err(|_| Error::BadEscape(at.0))?;
         if self.top_char(end) != Some('}') {
           return Err(Error::BadEscape(at.0));
         }
         let char = char::from_u32(number).ok_or(Error::BadEscape(at.0))?;
         (end.offset_char('}'), Some(char))
       }
-      ch if Self::space(ch) || Self::newline(ch) => {
+      ch if self.syntax.version != Version::V1 && (self.space(ch) || self.newline(ch)) => {
         let mut at = at.offset_char(ch);
         while let Some(next) = self.top_char(at) {
-          if !Self::space(next) && !Self::newline(next) {
+          if !self.space(next) && !self.newline(next) {
             break;
           }
           at = at.offset_char(next);
@@ -454,18 +879,21 @@ impl<'text> Grammar<'text> {
     };
     Ok((at, ch))
   }
-  fn string_end(&self, pos: Pos, multi: bool, raw: usize) -> Option<Pos> {
+  fn string_end(&self, pos: Pos, multi: bool, raw: Option<usize>) -> Option<Pos> {
+    let raw = raw.unwrap_or(0);
     self
       .tail(pos)
       .strip_prefix(if multi { "\"\"\"" } else { "\"" })
       .and_then(|tail| {
-        tail.as_bytes()[..raw]
+        tail
+          .as_bytes()
+          .get(..raw)?
           .iter()
           .all(|&ch| ch == b'#')
           .then(|| pos.offset_bytes(if multi { raw + 3 } else { raw + 1 }))
       })
   }
-  fn dedent_multiline(&self, first: Pos, mut lines: Vec<Pos>, raw: usize) -> PResult<String> {
+  fn dedent_multiline(&self, first: Pos, mut lines: Vec<Pos>, raw: Option<usize>) -> PResult<String> {
     // an interesting thing to note is that whitespace escapes
     // can never be within an indent, as they'll consume all the indent afterwards
     // this also means that all indents are byte-for-byte exact
@@ -479,7 +907,7 @@ impl<'text> Grammar<'text> {
       let mut end = start;
       loop {
         match self.top_char(end) {
-          Some('\\') if raw == 0 => {
+          Some('\\') if raw.is_none() => {
             let (next, ch) = self.escape(end.offset_char('\\'))?;
             if ch.is_some() {
               return Err(Error::ExpectedSpace(end.0));
@@ -492,7 +920,7 @@ impl<'text> Grammar<'text> {
             break;
           }
           Some(ch) => {
-            if Self::space(ch) {
+            if self.space(ch) {
               end = end.offset_char(ch);
             } else {
               return Err(Error::ExpectedSpace(end.0));
@@ -510,9 +938,9 @@ impl<'text> Grammar<'text> {
         let mut at = start;
         loop {
           let top = self.top_char(at).ok_or(Error::UnexpectedEof)?;
-          if Self::newline(top) {
+          if self.newline(top) {
             return Ok(String::new());
-          } else if !Self::space(top) {
+          } else if !self.space(top) {
             break;
           }
           at = at.offset_char(top);
@@ -522,13 +950,13 @@ impl<'text> Grammar<'text> {
           let mut text = String::new();
           loop {
             match self.top_char(at) {
-              Some('\\') if raw == 0 => {
+              Some('\\') if raw.is_none() => {
                 let (next, ch) = self.escape(at.offset_char('\\'))?;
                 at = next;
                 text.extend(ch);
               }
               Some(ch) => {
-                if Self::newline(ch) {
+                if self.newline(ch) {
                   break Ok(text);
                 } else {
                   text.push(ch);
@@ -546,15 +974,19 @@ impl<'text> Grammar<'text> {
       .map(|lines| dbg!(lines).join("\n"))
   }
   /// {single, multi}-line {raw, escaped} string, starting after the first "
-  fn quoted_string(&self, start: Pos, raw: usize) -> PResult<(Pos, Cow<'text, str>)> {
-    if self.tail(start).starts_with("\"\"") {
+  /// `raw` is the number of `#`, or `None` for escaped strings
+  fn quoted_string(&self, start: Pos, raw: Option<usize>) -> PResult<(Pos, Cow<'text, str>)> {
+    // v1 doesn't have multi-line strings, but allows newlines in any string
+    let v1 = self.syntax.version == Version::V1;
+    let really_raw = self.syntax.really_raw && raw.is_some();
+    if !v1 && self.tail(start).starts_with("\"\"") {
       // multi-line: `newline (line newline)* indent* """`
       // line: `indent* text*`
       let mut at = start.offset_str("\"\"");
       let mut lines = Vec::<Pos>::new();
       loop {
         match self.top_char(at) {
-          Some('\\') if raw == 0 => {
+          Some('\\') if raw.is_none() => {
             let (next, _) = self.escape(at.offset_char('\\'))?;
             at = next;
           }
@@ -566,14 +998,14 @@ impl<'text> Grammar<'text> {
             at = at.offset_str("\"\"\"");
           }
           Some(ch) => {
-            if Self::newline(ch) {
+            if self.newline(ch) {
               let mut next = at.offset_char(ch);
               if ch == '\r' && self.top_char(next) == Some('\n') {
                 at = next;
                 next = at.offset_char('\n');
               }
               lines.push(next);
-            } else if Self::banned(ch) {
+            } else if Self::banned(ch) && !really_raw {
               return Err(Error::BannedChar(ch, at.0));
             } else if lines.is_empty() {
               return Err(Error::ExpectedNewline(at.0));
@@ -590,8 +1022,8 @@ impl<'text> Grammar<'text> {
       let mut at = start;
       loop {
         match self.top_char(at) {
-          Some('\\') if raw == 0 => {
-            let text = text.get_or_insert_with(|| self.0[start.0..at.0].to_owned());
+          Some('\\') if raw.is_none() => {
+            let text = text.get_or_insert_with(|| self.slice(start, at).to_owned());
             let (next, ch) = self.escape(at.offset_char('\\'))?;
             at = next;
             text.extend(ch);
@@ -600,7 +1032,7 @@ impl<'text> Grammar<'text> {
             if let Some(next) = self.string_end(at, false, raw) {
               break Ok((
                 next,
-                text.map_or_else(|| Cow::Borrowed(&self.0[start.0..at.0]), Cow::Owned),
+                text.map_or_else(|| Cow::Borrowed(self.slice(start, at)), Cow::Owned),
               ));
             }
             // more text!
@@ -610,9 +1042,9 @@ impl<'text> Grammar<'text> {
             at = at.offset_char('"');
           }
           Some(ch) => {
-            if Self::newline(ch) {
+            if self.newline(ch) && !(v1 || really_raw) {
               return Err(Error::UnexpectedNewline(at.0));
-            } else if Self::banned(ch) {
+            } else if Self::banned(ch) && !really_raw {
               return Err(Error::BannedChar(ch, at.0));
             }
             if let Some(text) = &mut text {
@@ -625,18 +1057,74 @@ impl<'text> Grammar<'text> {
       }
     }
   }
+  /// v1 `raw-string` start `r#*"`, returning the position after the quote and
+  /// the number of `#`
+  fn raw_string_v1(&self, at: Pos) -> Option<(Pos, usize)> {
+    if self.syntax.version != Version::V1 {
+      return None;
+    }
+    let tail = self.tail(at).strip_prefix('r')?;
+    let quote = tail.trim_start_matches('#');
+    let raw = tail.len() - quote.len();
+    quote
+      .starts_with('"')
+      .then(|| (at.offset_char('r').offset_bytes(raw).offset_char('"'), raw))
+  }
+  /// End of a quoted or raw string starting at `at`, if there is one
+  ///
+  /// This is for skipping strings after an error, so the string can be
+  /// invalid. Escapes are still skipped as a whole, and single-line strings
+  /// stop at a newline or the end of the text if they aren't closed.
+  fn skip_string(&self, at: Pos) -> Option<Pos> {
+    let v1 = self.syntax.version == Version::V1;
+    let (start, raw) = match self.raw_string_v1(at) {
+      Some((start, raw)) => (start, Some(raw)),
+      None => {
+        let quote = self.tail(at).trim_start_matches('#');
+        let raw = self.tail(at).len() - quote.len();
+        if !quote.starts_with('"') || (raw > 0 && v1) {
+          return None;
+        }
+        (at.offset_bytes(raw).offset_char('"'), (raw > 0).then_some(raw))
+      }
+    };
+    let multi = !v1 && self.tail(start).starts_with("\"\"");
+    let mut at = if multi { start.offset_str("\"\"") } else { start };
+    loop {
+      match self.top_char(at) {
+        Some('\\') if raw.is_none() => {
+          let next = at.offset_char('\\');
+          at = match self.escape(next) {
+            Ok((end, _)) => end,
+            Err(_) => next,
+          };
+        }
+        Some('"') => match self.string_end(at, multi, raw) {
+          Some(end) => return Some(end),
+          None => at = at.offset_char('"'),
+        },
+        Some(ch) if self.newline(ch) && !multi && !v1 => return Some(at),
+        Some(ch) => at = at.offset_char(ch),
+        None => return Some(at),
+      }
+    }
+  }
   /// `string | number | keyword`, may be invalid
   fn semi_value(&self, at: Pos) -> PResult<(Pos, SemiValue<'text>)> {
+    if let Some((start, raw)) = self.raw_string_v1(at) {
+      let (at, text) = self.quoted_string(start, Some(raw))?;
+      return Ok((at, SemiValue::String(text)));
+    }
     match self.top_char(at) {
       Some('"') => {
-        let (at, text) = self.quoted_string(at.offset_char('"'), 0)?;
+        let (at, text) = self.quoted_string(at.offset_char('"'), None)?;
         Ok((at, SemiValue::String(text)))
       }
-      Some('#') => {
+      Some('#') if self.syntax.version != Version::V1 => {
         let start = at;
         let mut at = at.offset_char('#');
         match self.top_char(at) {
-          Some(ch) if Self::ident(ch) => {
+          Some(ch) if self.ident(ch) => {
             let (at, text) = self.identifier_string(at);
             Ok((at, SemiValue::Keyword(text)))
           }
@@ -649,17 +1137,23 @@ impl<'text> Grammar<'text> {
             if self.top_char(at) != Some('"') {
               return Err(Error::ExpectedString(start.0));
             }
-            let (at, text) = self.quoted_string(at.offset_char('"'), raw)?;
+            let (at, text) = self.quoted_string(at.offset_char('"'), Some(raw))?;
             Ok((at, SemiValue::String(text)))
           }
         }
       }
-      Some(ch) if Self::ident(ch) => {
+      Some(ch) if self.ident(ch) => {
         let (next, text) = self.identifier_string(at);
         Ok((
           next,
           if Self::number_like(text) {
             SemiValue::Number(text)
+          } else if self.syntax.version == Version::V1 {
+            // v1 keywords are bare
+            match text {
+              "true" | "false" | "null" => SemiValue::Keyword(text),
+              _ => SemiValue::String(Cow::Borrowed(text)),
+            }
           } else if matches!(text, "inf" | "-inf" | "nan" | "true" | "false" | "null") {
             return Err(Error::BadIdentifier(at.0));
           } else {
@@ -689,6 +1183,10 @@ impl<'text> Grammar<'text> {
         SemiValue::Number(text) => match parse_number(text).ok_or(Error::InvalidNumber(at.0))? {
           NumberInner::F64(num) => Value::Float(num),
           NumberInner::I128(num) => Value::Integer(num),
+          NumberInner::Big(_) if self.syntax.strict_integers => return Err(Error::IntegerOverflow(at.0)),
+          // borrow when it's already written canonically
+          NumberInner::Big(num) if num == text => Value::BigInteger(Cow::Borrowed(text)),
+          NumberInner::Big(num) => Value::BigInteger(Cow::Owned(num)),
         },
         SemiValue::Keyword("null") => Value::Null,
         SemiValue::Keyword("true") => Value::Bool(true),
@@ -701,13 +1199,15 @@ impl<'text> Grammar<'text> {
     ))
   }
   /// `type?`
-  fn type_hint(&self, at: Pos) -> PResult<(Pos, Option<Cow<'text, str>>)> {
+  fn type_hint(&self, at: Pos) -> PResult<(Pos, Option<Spanned<Cow<'text, str>>>)> {
     if self.top_char(at) == Some('(') {
+      let start = at;
       let at = self.node_space(at.offset_char('('), false)?;
       let (at, text) = self.string(at)?;
       let at = self.node_space(at, false)?;
       if self.top_char(at) == Some(')') {
-        Ok((at.offset_char(')'), Some(text)))
+        let at = at.offset_char(')');
+        Ok((at, Some((Span::new(start, at), text))))
       } else {
         Err(Error::ExpectedCloseParen(at.0))
       }
@@ -718,20 +1218,59 @@ impl<'text> Grammar<'text> {
   /// `line-space* eob` or `line-space* slashdash? type? node-space* string`
   fn start_node(&self, at: Pos, root: bool) -> PResult<(Pos, InnerEvent<'text>)> {
     let at = self.line_space(at)?;
-    if root {
-      if self.top_char(at).is_none() {
-        return Ok((at, InnerEvent::Done));
+    match self.top_char(at) {
+      None if root => return Ok((at, InnerEvent::Done)),
+      None => return Err(Error::UnexpectedEof),
+      Some('}') if root => return Err(Error::UnexpectedCloseBracket(at.0)),
+      Some('}') => {
+        let next = at.offset_char('}');
+        return Ok((
+          next,
+          InnerEvent::End {
+            brace: Span::new(at, next),
+          },
+        ));
       }
-    } else if self.top_char(at) == Some('}') {
-      return Ok((at.offset_char('}'), InnerEvent::End));
+      _ => {}
     }
     let next = self.slash_dash(at)?;
     let sd = next.is_some();
     let at = next.unwrap_or(at);
     let (at, r#type) = self.type_hint(at)?;
     let at = self.node_space(at, false)?;
+    let start = at;
     let (at, name) = self.string(at)?;
-    Ok((at, InnerEvent::Node { sd, r#type, name }))
+    let span = EventSpan::Node {
+      r#type: r#type.as_ref().map(|(span, _)| *span),
+      name: Span::new(start, at),
+    };
+    let r#type = r#type.map(|(_, text)| text);
+    Ok((at, InnerEvent::Node { sd, r#type, name, span }))
+  }
+  /// `/- kdl-version <n>` as the first node, which parses the same in every
+  /// version
+  fn version_marker(&self) -> Option<Version> {
+    let (at, InnerEvent::Node { sd: true, name, .. }) = self.begin_document(Pos(0)).ok()? else {
+      return None;
+    };
+    if name != "kdl-version" {
+      return None;
+    }
+    match self.node_item(at, true, true).ok()?.1 {
+      InnerEvent::PropValue {
+        sd: false,
+        key: None,
+        value: Value::Integer(1),
+        ..
+      } => Some(Version::V1),
+      InnerEvent::PropValue {
+        sd: false,
+        key: None,
+        value: Value::Integer(2),
+        ..
+      } => Some(Version::V2),
+      _ => None,
+    }
   }
   fn begin_document(&self, at: Pos) -> PResult<(Pos, InnerEvent<'text>)> {
     let at = self.bom(at).unwrap_or(at);
@@ -745,28 +1284,50 @@ impl<'text> Grammar<'text> {
   fn node_item(&self, at: Pos, root: bool, props: bool) -> PResult<(Pos, InnerEvent<'text>)> {
     let first = at;
     let at = self.node_space(at, false)?;
-    if root && self.top_char(at).is_none() {
-      return Ok((at, InnerEvent::Done));
-    }
     match self.top_char(at) {
-      Some('}') if !root => return Ok((at.offset_char('}'), InnerEvent::End)),
+      None if root => return Ok((at, InnerEvent::Done)),
+      None => return Err(Error::UnexpectedEof),
+      Some('}') if root => return Err(Error::UnexpectedCloseBracket(at.0)),
+      Some('}') => {
+        let next = at.offset_char('}');
+        return Ok((
+          next,
+          InnerEvent::End {
+            brace: Span::new(at, next),
+          },
+        ));
+      }
       // node-terminator
       Some(';') => return self.start_node(at.offset_char(';'), root),
       Some('/') if self.top_char(at.offset_char('/')) == Some('/') => {
         let at = self.single_line_comment(at.offset_str("//"))?;
         return self.start_node(at, root);
       }
-      Some(ch) if Self::newline(ch) => return self.start_node(at.offset_char(ch), root),
+      Some(ch) if self.newline(ch) => return self.start_node(at.offset_char(ch), root),
       _ => {}
     }
-    if at.0 == first.0 {
+    // v1 doesn't need a space before the children block,
+    // v2.0.1 before the children block or a slashdash
+    let tight = match self.syntax.version {
+      Version::V1 => self.top_char(at) == Some('{'),
+      Version::V2 => false,
+      Version::V2_0_1 => self.top_char(at) == Some('{') || self.tail(at).starts_with("/-"),
+    };
+    if at.0 == first.0 && !tight {
       return Err(Error::ExpectedSpace(at.0));
     }
     let next = self.slash_dash(at)?;
     let sd = next.is_some();
     let at = next.unwrap_or(at);
     if self.top_char(at) == Some('{') {
-      Ok((at.offset_char('{'), InnerEvent::Begin { sd }))
+      let next = at.offset_char('{');
+      Ok((
+        next,
+        InnerEvent::Begin {
+          sd,
+          span: EventSpan::Begin(Span::new(at, next)),
+        },
+      ))
     } else if props {
       // prop/value sucks to parse, the two valid options here are:
       // - type? node-space* value
@@ -777,8 +1338,9 @@ impl<'text> Grammar<'text> {
       // - string node-space* = node-space* type? node-space* value
       // that third one comes as a tail-check of the second, only consume the space if
       // it's used
-      if let (at, Some(r#type)) = self.type_hint(at)? {
+      if let (at, Some((type_span, r#type))) = self.type_hint(at)? {
         let at = self.node_space(at, false)?;
+        let start = at;
         let (at, value) = self.value(at)?;
         Ok((
           at,
@@ -787,11 +1349,18 @@ impl<'text> Grammar<'text> {
             r#type: Some(r#type),
             key: None,
             value,
+            span: EventSpan::Entry(EntrySpan {
+              key: None,
+              r#type: Some(type_span),
+              value: Span::new(start, at),
+            }),
           },
         ))
       } else {
         // this is a different at binding than type_hint, but it's the same value
+        let start = at;
         let (at, value) = self.value(at)?;
+        let value_span = Span::new(start, at);
         // try for a property
         let value = match value {
           Value::String(key) => {
@@ -800,12 +1369,18 @@ impl<'text> Grammar<'text> {
               let at = self.node_space(at.offset_char('='), false)?;
               let (at, r#type) = self.type_hint(at)?;
               let at = self.node_space(at, false)?;
+              let start = at;
               let (at, real) = self.value(at)?;
               return Ok((
                 at,
                 InnerEvent::PropValue {
                   sd,
-                  r#type,
+                  span: EventSpan::Entry(EntrySpan {
+                    key: Some(value_span),
+                    r#type: r#type.as_ref().map(|(span, _)| *span),
+                    value: Span::new(start, at),
+                  }),
+                  r#type: r#type.map(|(_, text)| text),
                   key: Some(key),
                   value: real,
                 },
@@ -823,6 +1398,11 @@ impl<'text> Grammar<'text> {
             r#type: None,
             key: None,
             value,
+            span: EventSpan::Entry(EntrySpan {
+              key: None,
+              r#type: None,
+              value: value_span,
+            }),
           },
         ))
       }
@@ -834,12 +1414,140 @@ impl<'text> Grammar<'text> {
 
 /// Actual number parsing implementation based on the streaming combinators
 fn parse_number(text: &str) -> Option<NumberInner> {
-  Grammar(text).all_number(Pos(0))
+  Grammar::new(text, Syntax::default()).all_number(Pos(0))
+}
+
+/// Write out digits in some radix as canonical decimal digits, without
+/// leading zeroes
+fn big_integer(negative: bool, digits: &str, radix: u32) -> String {
+  const BASE: u64 = 1_000_000_000;
+  // little-endian base 10^9 limbs
+  let mut limbs: Vec<u64> = Vec::new();
+  for ch in digits.chars() {
+    let mut carry = u64::from(ch.to_digit(radix).unwrap());
+    for limb in &mut limbs {
+      let next = *limb * u64::from(radix) + carry;
+      *limb = next % BASE;
+      carry = next / BASE;
+    }
+    if carry > 0 {
+      limbs.push(carry);
+    }
+  }
+  let mut text = if negative { "-".to_owned() } else { String::new() };
+  let mut limbs = limbs.iter().rev();
+  // never empty, since a zero would've fit in an `i128`
+  text += &limbs.next().unwrap().to_string();
+  for limb in limbs {
+    text += &format!("{limb:09}");
+  }
+  text
+}
+
+/// Pieces of the grammar for languages built on KDL values, like
+/// [queries](crate::query), at byte positions in their text
+pub(crate) mod piece {
+  use super::*;
+
+  /// `node-space*`, returning the position after it
+  pub(crate) fn space(text: &str, at: usize) -> PResult<usize> {
+    Ok(Grammar::new(text, Syntax::default()).node_space(Pos(at), false)?.0)
+  }
+  /// `string`, returning the position after it
+  pub(crate) fn string(text: &str, at: usize) -> PResult<(usize, Cow<'_, str>)> {
+    let (end, string) = Grammar::new(text, Syntax::default()).string(Pos(at))?;
+    Ok((end.0, string))
+  }
+  /// `value`, returning the position after it
+  pub(crate) fn value(text: &str, at: usize) -> PResult<(usize, Value<'_>)> {
+    let (end, value) = Grammar::new(text, Syntax::default()).value(Pos(at))?;
+    Ok((end.0, value))
+  }
+}
+
+/// Turn bytes into text for parsing, checking their encoding
+///
+/// Bytes starting with a UTF-16 byte order mark (little or big endian) are
+/// transcoded, keeping the byte order mark as U+FEFF which the parser skips
+/// like the UTF-8 one. Anything else has to be UTF-8 and is borrowed as-is.
+/// Errors are positions in `bytes`, but positions from parsing transcoded
+/// text are in the new UTF-8 text.
+pub fn decode(bytes: &[u8]) -> PResult<Cow<'_, str>> {
+  let Some(big_endian) = utf16_bom(bytes) else {
+    return match std::str::from_utf8(bytes) {
+      Ok(text) => Ok(Cow::Borrowed(text)),
+      Err(error) => Err(Error::InvalidEncoding(error.valid_up_to())),
+    };
+  };
+  let units = bytes.chunks(2).map(|pair| match *pair {
+    [a, b] if big_endian => Ok(u16::from_be_bytes([a, b])),
+    [a, b] => Ok(u16::from_le_bytes([a, b])),
+    // odd length
+    _ => Err(()),
+  });
+  let mut text = String::with_capacity(bytes.len());
+  let mut at = 0;
+  for ch in char::decode_utf16(units.map_while(Result::ok)) {
+    let ch = ch.map_err(|_| Error::InvalidEncoding(at))?;
+    at += ch.len_utf16() * 2;
+    text.push(ch);
+  }
+  if at < bytes.len() {
+    return Err(Error::InvalidEncoding(at));
+  }
+  Ok(Cow::Owned(text))
+}
+
+/// Whether bytes start with a UTF-16 byte order mark, and if it's big endian
+fn utf16_bom(bytes: &[u8]) -> Option<bool> {
+  match bytes {
+    [0xFF, 0xFE, ..] => Some(false),
+    [0xFE, 0xFF, ..] => Some(true),
+    _ => None,
+  }
+}
+
+/// A version of the KDL spec
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Version {
+  /// [KDL 1.0.0](https://github.com/kdl-org/kdl/blob/1.0.0/SPEC.md)
+  V1,
+  /// [KDL 2.0.0](https://github.com/kdl-org/kdl/blob/2.0.0/SPEC.md)
+  #[default]
+  V2,
+  /// The draft of the next KDL revision, as tested by the
+  /// [upstream test suite](https://github.com/kdl-org/kdl/tree/main/tests),
+  /// which differs from 2.0.0 in that:
+  /// - U+000B (vertical tab) is whitespace instead of a newline
+  /// - whitespace is optional before a children block or a `/-`
+  V2_0_1,
 }
 
 /// A streaming parser, is an [`Iterator`] of [`Event`]
+///
+/// By default the parser stops after the first error, see
+/// [`Parser::recovering`] to keep going.
 pub struct Parser<'text> {
   grammar: Grammar<'text>,
+  machine: Machine,
+}
+
+/// An event with everything the parsers can yield along with it
+type Parsed<'text> = (Event<'text>, EventSpan, Option<Literal<'text>>);
+
+/// One event of [`Machine::step`]
+enum Step<'text> {
+  Event(Event<'text>, EventSpan, Option<Literal<'text>>),
+  /// filtered out, like the entries of a slashdashed node
+  Skipped,
+  Done,
+}
+
+/// Parser state that doesn't borrow the text, so [`ReadParser`] can swap out
+/// its buffer between events
+#[derive(Clone)]
+struct Machine {
   cursor: Pos,
   state: ParserState,
   // used in sd-filtering
@@ -847,43 +1555,168 @@ pub struct Parser<'text> {
   // number of levels deep
   // used to determine if a } is still needed
   nest: usize,
+  // inside the entries or children of a slashdashed node
+  skip_node: bool,
+  // number of levels deep inside slashdashed children
+  skip_depth: usize,
+  // resync after errors instead of stopping
+  recover: bool,
+  // start of every open children block, for spans
+  blocks: Vec<usize>,
 }
 
 impl<'text> Parser<'text> {
   /// Create a new parser from a text string
   pub fn new(text: &'text str) -> Self {
     Self {
-      grammar: Grammar(text),
+      grammar: Grammar::new(text, Syntax::default()),
+      machine: Machine::new(),
+    }
+  }
+  /// Parse a specific version of the spec, instead of the default
+  /// [`Version::V2`]
+  ///
+  /// All versions produce the same events, v1 keywords are parsed into the
+  /// same values as their v2 equivalents (`true` is `#true`, etc).
+  pub fn with_version(mut self, version: Version) -> Self {
+    self.grammar.syntax.version = version;
+    self
+  }
+  /// Guess which version of the spec the text is written in
+  ///
+  /// A `/- kdl-version 1` or `/- kdl-version 2` marker as the first node is
+  /// used if present, otherwise the text is checked against v2 and falls back
+  /// to v1 if it only parses as v1.
+  /// Checking requires parsing the entire text an extra time.
+  pub fn detect_version(self) -> Self {
+    let text = self.grammar.text;
+    let syntax = self.grammar.syntax;
+    let valid = |version| {
+      let mut parser = Parser::new(text);
+      parser.grammar.syntax = syntax;
+      parser.with_version(version).all(|event| event.is_ok())
+    };
+    let version = match Grammar::new(text, syntax).version_marker() {
+      Some(version) => version,
+      None if !valid(Version::V2) && valid(Version::V1) => Version::V1,
+      None => Version::V2,
+    };
+    self.with_version(version)
+  }
+  /// The version of the spec being parsed
+  pub fn version(&self) -> Version {
+    self.grammar.syntax.version
+  }
+  /// Allow any text in raw strings, breaking the spec
+  ///
+  /// Normally disallowed characters are accepted in raw strings, and
+  /// single-line raw strings can contain newlines, which are kept as-is.
+  /// This is useful for embedding arbitrary text, like templates, with
+  /// `#"…"#`. Escaped strings are unaffected.
+  pub fn with_really_raw(mut self, really_raw: bool) -> Self {
+    self.grammar.syntax.really_raw = really_raw;
+    self
+  }
+  /// Error on integers outside the range of `i128`, instead of keeping them
+  /// as [`Value::BigInteger`]
+  pub fn with_strict_integers(mut self, strict: bool) -> Self {
+    self.grammar.syntax.strict_integers = strict;
+    self
+  }
+  /// Also yield the source positions of every event
+  pub fn spanned(self) -> SpannedParser<'text> {
+    SpannedParser(self)
+  }
+  /// Also yield how the value of every entry was written
+  ///
+  /// Writing the events back out with [`write_stream`] or a
+  /// [`Document`](crate::dom::Document) reuses the original text of values
+  /// that weren't changed, so `0xFF` doesn't become `255`, `1.50` stays
+  /// `1.50`, and strings keep their quotes.
+  pub fn literals(mut self) -> Literals<Self> {
+    self.grammar.syntax.literals = true;
+    Literals(self)
+  }
+  /// Current byte position in the source text,
+  /// right after the last event that was parsed
+  pub fn position(&self) -> usize {
+    self.machine.cursor.0
+  }
+  /// Create a new parser from utf-8 bytes, which may start with a byte order
+  /// mark
+  ///
+  /// UTF-16 has to be transcoded into a new buffer first with [`decode`],
+  /// bytes starting with a UTF-16 byte order mark are an [`Error::Utf16`].
+  pub fn from_bytes(bytes: &'text [u8]) -> PResult<Self> {
+    if utf16_bom(bytes).is_some() {
+      return Err(Error::Utf16);
+    }
+    match std::str::from_utf8(bytes) {
+      Ok(text) => Ok(Self::new(text)),
+      Err(error) => Err(Error::InvalidEncoding(error.valid_up_to())),
+    }
+  }
+  /// Keep parsing after an error instead of stopping
+  ///
+  /// After each error is yielded, the parser skips ahead to the next node
+  /// terminator (`;` or newline) or `}` that isn't in a string or comment,
+  /// and continues from there, so every
+  /// error in the document gets reported. Anything between the error and that
+  /// point is dropped, including nodes in a children block that was started
+  /// there, and children blocks left open at the end of the text are closed.
+  /// The events always stay balanced, so they can still be collected into a
+  /// [`Document`].
+  ///
+  /// [`Document`]: crate::dom::Document
+  pub fn recovering(mut self) -> Self {
+    self.machine.recover = true;
+    self
+  }
+  fn next_parsed(&mut self) -> Option<PResult<Parsed<'text>>> {
+    self.machine.next_parsed(&self.grammar)
+  }
+}
+
+impl Machine {
+  fn new() -> Self {
+    Self {
       cursor: Pos(0),
       state: ParserState::BeginDocument,
       begin_valid: false,
       nest: 0,
+      skip_node: false,
+      skip_depth: 0,
+      recover: false,
+      blocks: Vec::new(),
     }
   }
-  fn next_event(&mut self) -> PResult<InnerEvent<'text>> {
+  fn next_event<'text>(&mut self, grammar: &Grammar<'text>) -> PResult<InnerEvent<'text>> {
     let event = match &mut self.state {
       ParserState::BeginDocument => {
-        let (cursor, event) = self.grammar.begin_document(self.cursor)?;
+        let (cursor, event) = grammar.begin_document(self.cursor)?;
         self.cursor = cursor;
         event
       }
       ParserState::NextNode => {
-        let (cursor, event) = self.grammar.start_node(self.cursor, self.nest == 0)?;
+        let (cursor, event) = grammar.start_node(self.cursor, self.nest == 0)?;
         self.cursor = cursor;
         event
       }
       ParserState::NodeProps => {
-        let (cursor, event) = self.grammar.node_item(self.cursor, self.nest == 0, true)?;
+        let (cursor, event) = grammar.node_item(self.cursor, self.nest == 0, true)?;
         self.cursor = cursor;
         event
       }
       // same as NodeProps without propvalue
       ParserState::NodeChildren => {
-        let (cursor, event) = self.grammar.node_item(self.cursor, self.nest == 0, false)?;
+        let (cursor, event) = grammar.node_item(self.cursor, self.nest == 0, false)?;
         self.cursor = cursor;
         event
       }
-      ParserState::Done => InnerEvent::Done,
+      ParserState::Unclosed if self.nest > 0 => InnerEvent::End {
+        brace: Span::new(self.cursor, self.cursor),
+      },
+      ParserState::Unclosed | ParserState::Done => InnerEvent::Done,
     };
     self.state = match event {
       InnerEvent::Node { .. } | InnerEvent::PropValue { .. } => ParserState::NodeProps,
@@ -891,154 +1724,714 @@ impl<'text> Parser<'text> {
         self.nest += 1;
         ParserState::NextNode
       }
-      InnerEvent::End => {
+      InnerEvent::End { .. } => {
         self.nest -= 1;
-        ParserState::NodeChildren
+        match self.state {
+          ParserState::Unclosed => ParserState::Unclosed,
+          _ => ParserState::NodeChildren,
+        }
       }
       InnerEvent::Done => ParserState::Done,
     };
     Ok(event)
   }
-  fn next_real(&mut self) -> PResult<Option<Event<'text>>> {
-    // sd node -> pull until node/end/finish, consume end, then loop
-    // sd value -> consume & loop
-    let mut next_pop = None;
-    Ok(Some(loop {
-      // this position is only the real start if next_pop is none
-      // used for diagnostics
-      let start_cursor = self.cursor;
-      break match next_pop.take().ok_or(()).or_else(|()| self.next_event())? {
-        InnerEvent::Node { sd: true, .. } => {
-          // continue until next node/end/finish
-          // next_pop is always none here so we can just take events
-          let mut depth = 0_usize;
-          next_pop = loop {
-            match self.next_event()? {
-              node @ InnerEvent::Node { sd: false, .. } if depth == 0 => {
-                break Some(node);
-              }
-              InnerEvent::Begin { .. } => depth += 1,
-              InnerEvent::End => match depth.checked_sub(1) {
-                Some(next) => {
-                  depth = next;
-                  if depth == 0 {
-                    break None;
-                  }
-                }
-                None => break Some(InnerEvent::End),
-              },
-              InnerEvent::Done => break Some(InnerEvent::Done),
-              InnerEvent::Node { .. } | InnerEvent::PropValue { .. } => {}
-            }
-          };
-          continue;
-        }
-        InnerEvent::Begin { sd: true } => {
-          let mut depth = 0_usize;
-          loop {
-            match self.next_event()? {
-              InnerEvent::Begin { .. } => depth += 1,
-              InnerEvent::End => {
-                if let Some(next) = depth.checked_sub(1) {
-                  depth = next;
-                } else {
-                  break;
-                }
-              }
-              _ => {}
-            }
+  /// Parse the next event, including the ones that get filtered out
+  fn step<'text>(&mut self, grammar: &Grammar<'text>) -> PResult<Step<'text>> {
+    // sd node -> skip entries & children until the next node/end/finish
+    // sd children -> skip until the matching end
+    // sd value -> skip
+    let start_cursor = self.cursor;
+    let event = self.next_event(grammar)?;
+    let children = match &event {
+      InnerEvent::Begin { span, .. } => {
+        self.blocks.push(span.span().start);
+        None
+      }
+      InnerEvent::End { brace } => Some(Span {
+        start: self.blocks.pop().unwrap_or(brace.start),
+        end: brace.end,
+      }),
+      _ => None,
+    };
+    if self.skip_depth > 0 {
+      match event {
+        InnerEvent::Begin { .. } => self.skip_depth += 1,
+        InnerEvent::End { .. } => self.skip_depth -= 1,
+        // stopped after an error
+        InnerEvent::Done => return Ok(Step::Done),
+        _ => {}
+      }
+      return Ok(Step::Skipped);
+    }
+    if self.skip_node {
+      match event {
+        InnerEvent::Node { sd: false, .. } | InnerEvent::End { .. } | InnerEvent::Done => self.skip_node = false,
+        InnerEvent::Begin { sd, .. } => {
+          self.skip_depth = 1;
+          // a slashdashed node still only gets one children block
+          if !sd && !std::mem::replace(&mut self.begin_valid, false) {
+            return Err(Error::MultipleChildren(start_cursor.0));
           }
-          continue;
+          return Ok(Step::Skipped);
         }
-        InnerEvent::Node {
-          sd: false,
-          r#type,
-          name,
-        } => {
-          self.begin_valid = true;
-          Event::Node { r#type, name }
+        InnerEvent::Node { sd: true, .. } | InnerEvent::PropValue { .. } => return Ok(Step::Skipped),
+      }
+    }
+    let (event, span) = match event {
+      InnerEvent::Node { sd: true, .. } => {
+        self.skip_node = true;
+        self.begin_valid = true;
+        return Ok(Step::Skipped);
+      }
+      InnerEvent::Begin { sd: true, .. } => {
+        self.skip_depth = 1;
+        return Ok(Step::Skipped);
+      }
+      InnerEvent::Node {
+        sd: false,
+        r#type,
+        name,
+        span,
+      } => {
+        self.begin_valid = true;
+        (Event::Node { r#type, name }, span)
+      }
+      InnerEvent::PropValue { sd: true, .. } => return Ok(Step::Skipped),
+      InnerEvent::PropValue {
+        sd: false,
+        r#type,
+        key,
+        value,
+        span,
+      } => (Event::Entry { r#type, key, value }, span),
+      InnerEvent::Begin { sd: false, span } => {
+        if self.begin_valid {
+          (Event::Begin, span)
+        } else {
+          // if recovering, the extra block gets skipped like a slashdashed one
+          self.skip_depth = 1;
+          return Err(Error::MultipleChildren(start_cursor.0));
         }
-        InnerEvent::PropValue { sd: true, .. } => continue,
-        InnerEvent::PropValue {
-          sd: false,
-          r#type,
-          key,
-          value,
-        } => Event::Entry { r#type, key, value },
-        InnerEvent::Begin { sd: false } => {
-          if self.begin_valid {
-            Event::Begin
-          } else {
-            return Err(Error::MultipleChildren(start_cursor.0));
-          }
+      }
+      InnerEvent::End { brace } => {
+        self.begin_valid = false;
+        (
+          Event::End,
+          EventSpan::End {
+            brace,
+            children: children.unwrap(),
+          },
+        )
+      }
+      InnerEvent::Done => return Ok(Step::Done),
+    };
+    let literal = match (&event, span) {
+      (Event::Entry { value, .. }, EventSpan::Entry(EntrySpan { value: at, .. })) if grammar.syntax.literals => {
+        let text = grammar.slice(Pos(at.start), Pos(at.end));
+        Some(Literal {
+          text: Cow::Borrowed(text),
+          kind: LiteralKind::new(text, value),
+        })
+      }
+      _ => None,
+    };
+    Ok(Step::Event(event, span, literal))
+  }
+  /// Skip ahead after an error, see [`Parser::recovering`]
+  fn resync(&mut self, grammar: &Grammar, error: &Error) {
+    if let Error::MultipleChildren(_) = error {
+      // already skipping the block
+      return;
+    }
+    let error_at = error.offset().map_or(grammar.end().0, |at| at.max(self.cursor.0));
+    // start from the failed item, so a string the error is in gets skipped
+    // as a whole
+    let mut at = self.cursor;
+    // children blocks opened after the error
+    let mut depth = 0_usize;
+    self.begin_valid = false;
+    self.state = loop {
+      if let Some(end) = grammar.skip_string(at) {
+        at = end;
+        continue;
+      }
+      if grammar.tail(at).starts_with("/*") {
+        if let Ok(end) = grammar.multi_line_comment(at.offset_str("/*")) {
+          at = end;
+          continue;
         }
-        InnerEvent::End => {
-          self.begin_valid = false;
-          Event::End
+      }
+      if grammar.tail(at).starts_with("//") {
+        // up to the newline, which ends the node
+        while let Some(ch) = grammar.top_char(at).filter(|&ch| !grammar.newline(ch)) {
+          at = at.offset_char(ch);
         }
-        InnerEvent::Done => return Ok(None),
-      };
-    }))
+        continue;
+      }
+      match grammar.top_char(at) {
+        // the failed item up to the error was fine
+        Some(_) if at.0 < error_at => {}
+        Some('{') => depth += 1,
+        Some('}') if depth > 0 => depth -= 1,
+        // let node_item produce the end
+        Some('}') if self.nest > 0 => break ParserState::NodeChildren,
+        Some(ch) if depth == 0 && (ch == ';' || grammar.newline(ch)) => {
+          at = at.offset_char(ch);
+          break ParserState::NextNode;
+        }
+        Some(_) => {}
+        None => break ParserState::Unclosed,
+      }
+      at = at.offset_char(grammar.top_char(at).unwrap());
+    };
+    self.cursor = at;
+  }
+  /// [`Machine::step`], skipping ahead or stopping after errors
+  fn step_spanned<'text>(&mut self, grammar: &Grammar<'text>) -> PResult<Step<'text>> {
+    let step = self.step(grammar);
+    // this is a terrible place to put it but oh well
+    if let Err(error) = &step {
+      if self.recover {
+        self.resync(grammar, error);
+      } else {
+        self.state = ParserState::Done;
+      }
+    }
+    step
+  }
+  fn next_parsed<'text>(&mut self, grammar: &Grammar<'text>) -> Option<PResult<Parsed<'text>>> {
+    loop {
+      match self.step_spanned(grammar) {
+        Ok(Step::Event(event, span, literal)) => return Some(Ok((event, span, literal))),
+        Ok(Step::Skipped) => {}
+        Ok(Step::Done) => return None,
+        Err(error) => return Some(Err(error)),
+      }
+    }
   }
 }
 
 impl<'text> Iterator for Parser<'text> {
   type Item = PResult<Event<'text>>;
   fn next(&mut self) -> Option<Self::Item> {
-    let event = self.next_real();
-    // this is a terrible place to put it but oh well
-    if event.is_err() {
-      self.state = ParserState::Done;
-    }
-    event.transpose()
+    self.next_parsed().map(|event| event.map(|(event, ..)| event))
   }
 }
 
-/// Write an iterator of events out as text, without constructing a
-/// [`Document`] first
+/// A [`Parser`] that also yields the source positions of every event,
+/// created with [`Parser::spanned`]
+pub struct SpannedParser<'text>(Parser<'text>);
+
+impl<'text> SpannedParser<'text> {
+  /// Get the underlying parser
+  pub fn into_inner(self) -> Parser<'text> {
+    self.0
+  }
+}
+
+impl<'text> Iterator for SpannedParser<'text> {
+  type Item = PResult<(Event<'text>, EventSpan)>;
+  fn next(&mut self) -> Option<Self::Item> {
+    self
+      .0
+      .next_parsed()
+      .map(|event| event.map(|(event, span, _)| (event, span)))
+  }
+}
+
+/// A parser that also yields how the value of every entry was written,
+/// created with [`Parser::literals`], [`ChunkParser::literals`], or
+/// [`ReadParser::literals`]
 ///
-/// [`Document`]: crate::dom::Document
-pub fn write_stream<'text, I: IntoIterator<Item = Event<'text>>>(f: &mut impl fmt::Write, events: I) -> fmt::Result {
-  let mut depth = 0;
-  let mut non_start = false;
-  for event in events {
-    match event {
-      Event::Node { r#type, name } => {
-        if non_start {
-          f.write_str("\n")?;
-        }
-        non_start = true;
-        for _ in 0..depth {
-          f.write_str("    ")?;
-        }
-        if let Some(r#type) = &r#type {
-          write!(f, "({})", IdentDisplay(r#type))?;
+/// Entries come with a [`Literal`], other events with `None`.
+pub struct Literals<P>(P);
+
+impl<P> Literals<P> {
+  /// Get the underlying parser, like for [`ChunkParser::feed`]
+  pub fn get_mut(&mut self) -> &mut P {
+    &mut self.0
+  }
+  /// Get the underlying parser
+  pub fn into_inner(self) -> P {
+    self.0
+  }
+}
+
+impl<'text> Iterator for Literals<Parser<'text>> {
+  type Item = PResult<(Event<'text>, Option<Literal<'text>>)>;
+  fn next(&mut self) -> Option<Self::Item> {
+    self
+      .0
+      .next_parsed()
+      .map(|event| event.map(|(event, _, literal)| (event, literal)))
+  }
+}
+
+/// A streaming parser for text that arrives in pieces, see [`ChunkParser::feed`]
+///
+/// Events are owned, and only the text that hasn't been turned into events
+/// yet is kept around, so memory use is bounded by the largest token instead
+/// of the whole document. Positions in errors and spans count from the start
+/// of all the input fed so far.
+///
+/// This is an [`Iterator`] that yields every event that can be parsed from the
+/// input so far, then returns `None` until more input is fed, so it can be
+/// iterated again after every [`ChunkParser::feed`].
+/// After [`ChunkParser::finish`], it runs to the end like [`Parser`].
+/// See [`ReadParser`] for pulling from an [`io::Read`] instead.
+pub struct ChunkParser {
+  syntax: Syntax,
+  machine: Machine,
+  // text not parsed yet, starting at byte `base` of the input
+  buffer: String,
+  base: usize,
+  // bytes at the end of the last chunk that aren't a complete character yet
+  pending: Vec<u8>,
+  // the input ended at the end of `buffer`
+  finished: bool,
+  // position of invalid utf-8, reported once `buffer` is parsed
+  invalid: Option<usize>,
+}
+
+impl ChunkParser {
+  /// Create a new parser without any input yet
+  pub fn new() -> Self {
+    Self {
+      syntax: Syntax::default(),
+      machine: Machine::new(),
+      buffer: String::new(),
+      base: 0,
+      pending: Vec::new(),
+      finished: false,
+      invalid: None,
+    }
+  }
+  /// See [`Parser::with_version`]
+  pub fn with_version(mut self, version: Version) -> Self {
+    self.syntax.version = version;
+    self
+  }
+  /// See [`Parser::with_really_raw`]
+  pub fn with_really_raw(mut self, really_raw: bool) -> Self {
+    self.syntax.really_raw = really_raw;
+    self
+  }
+  /// See [`Parser::with_strict_integers`]
+  pub fn with_strict_integers(mut self, strict: bool) -> Self {
+    self.syntax.strict_integers = strict;
+    self
+  }
+  /// See [`Parser::literals`]
+  pub fn literals(mut self) -> Literals<Self> {
+    self.syntax.literals = true;
+    Literals(self)
+  }
+  /// See [`Parser::recovering`]
+  pub fn recovering(mut self) -> Self {
+    self.machine.recover = true;
+    self
+  }
+  /// Add the next piece of input
+  ///
+  /// Chunks can be split anywhere, even in the middle of a character.
+  /// Input after [`ChunkParser::finish`] or invalid utf-8 is ignored.
+  pub fn feed(&mut self, chunk: &[u8]) {
+    if self.finished || self.invalid.is_some() {
+      return;
+    }
+    // drop the text that's already been parsed
+    let parsed = self.machine.cursor.0 - self.base;
+    self.buffer.drain(..parsed);
+    self.base += parsed;
+    self.pending.extend_from_slice(chunk);
+    let valid = match std::str::from_utf8(&self.pending) {
+      Ok(text) => text.len(),
+      Err(error) => {
+        if error.error_len().is_some() {
+          self.invalid = Some(self.base + self.buffer.len() + error.valid_up_to());
         }
-        write!(f, "{}", IdentDisplay(&name))?;
+        error.valid_up_to()
       }
-      Event::Entry { key, r#type, value } => {
-        f.write_str(" ")?;
-        if let Some(key) = &key {
-          write!(f, "{}=", IdentDisplay(key))?;
-        }
-        if let Some(r#type) = &r#type {
-          write!(f, "({})", IdentDisplay(r#type))?;
+    };
+    // already validated
+    self
+      .buffer
+      .push_str(std::str::from_utf8(&self.pending[..valid]).unwrap());
+    self.pending.drain(..valid);
+  }
+  /// Mark the end of the input
+  pub fn finish(&mut self) {
+    if !self.pending.is_empty() && self.invalid.is_none() {
+      self.invalid = Some(self.base + self.buffer.len());
+    }
+    self.finished = true;
+  }
+  /// All events have been parsed, either because the input is finished or
+  /// parsing stopped at an error
+  pub fn is_done(&self) -> bool {
+    matches!(self.machine.state, ParserState::Done)
+  }
+  /// Current byte position in the input,
+  /// right after the last event that was parsed
+  pub fn position(&self) -> usize {
+    self.machine.cursor.0
+  }
+  fn next_parsed(&mut self) -> Option<PResult<Parsed<'static>>> {
+    if self.is_done() {
+      return None;
+    }
+    let complete = self.finished && self.invalid.is_none();
+    let grammar = Grammar {
+      text: &self.buffer,
+      base: self.base,
+      syntax: self.syntax,
+      partial: !complete,
+      starved: Cell::new(false),
+    };
+    let near_end = |at: usize| at + Grammar::LOOKAHEAD > grammar.end().0;
+    loop {
+      // parse on a copy, the event is thrown away if more text could change
+      // it, but skipped ones are kept so a large slashdashed block isn't
+      // parsed again after every chunk
+      let mut machine = self.machine.clone();
+      let step = machine.step_spanned(&grammar);
+      let unsure = !complete
+        && (grammar.starved.get()
+          || near_end(machine.cursor.0)
+          || match &step {
+            Ok(Step::Event(..) | Step::Skipped) => false,
+            Err(error) => error.offset().is_none_or(near_end),
+            Ok(Step::Done) => true,
+          });
+      if unsure {
+        break;
+      }
+      self.machine = machine;
+      match step {
+        Ok(Step::Event(event, span, literal)) => {
+          return Some(Ok((event.into_static(), span, literal.map(Literal::into_owned))))
         }
-        write!(f, "{value}")?;
+        Ok(Step::Skipped) => {}
+        Ok(Step::Done) => return None,
+        Err(error) => return Some(Err(error)),
+      }
+    }
+    if !self.finished && self.invalid.is_none() {
+      return None;
+    }
+    // nothing more is coming, the rest of the text is valid on its own
+    self.machine.state = ParserState::Done;
+    Some(Err(Error::InvalidEncoding(self.invalid.unwrap())))
+  }
+}
+
+impl Default for ChunkParser {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Iterator for ChunkParser {
+  type Item = PResult<Event<'static>>;
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_parsed().map(|event| event.map(|(event, ..)| event))
+  }
+}
+
+impl Iterator for Literals<ChunkParser> {
+  type Item = PResult<(Event<'static>, Option<Literal<'static>>)>;
+  fn next(&mut self) -> Option<Self::Item> {
+    self
+      .0
+      .next_parsed()
+      .map(|event| event.map(|(event, _, literal)| (event, literal)))
+  }
+}
+
+/// A streaming parser that reads its input from an [`io::Read`]
+///
+/// This is an [`Iterator`] of owned [`Event`], reading more input as needed
+/// in small chunks, see [`ChunkParser`].
+/// Reading errors are yielded as [`Error::Io`] and stop the parser.
+pub struct ReadParser<R> {
+  reader: R,
+  inner: ChunkParser,
+}
+
+/// Size of each read in [`ReadParser`]
+const READ_CHUNK: usize = 8 * 1024;
+
+impl<R: io::Read> ReadParser<R> {
+  /// Create a new parser reading from `reader`
+  ///
+  /// Reads are unbuffered chunks of several kilobytes,
+  /// so there's no need for an [`io::BufReader`].
+  pub fn new(reader: R) -> Self {
+    Self {
+      reader,
+      inner: ChunkParser::new(),
+    }
+  }
+  /// See [`Parser::with_version`]
+  pub fn with_version(self, version: Version) -> Self {
+    Self {
+      inner: self.inner.with_version(version),
+      ..self
+    }
+  }
+  /// See [`Parser::with_really_raw`]
+  pub fn with_really_raw(self, really_raw: bool) -> Self {
+    Self {
+      inner: self.inner.with_really_raw(really_raw),
+      ..self
+    }
+  }
+  /// See [`Parser::with_strict_integers`]
+  pub fn with_strict_integers(self, strict: bool) -> Self {
+    Self {
+      inner: self.inner.with_strict_integers(strict),
+      ..self
+    }
+  }
+  /// See [`Parser::literals`]
+  pub fn literals(mut self) -> Literals<Self> {
+    self.inner.syntax.literals = true;
+    Literals(self)
+  }
+  /// See [`Parser::recovering`]
+  pub fn recovering(self) -> Self {
+    Self {
+      inner: self.inner.recovering(),
+      ..self
+    }
+  }
+  /// Current byte position in the input,
+  /// right after the last event that was parsed
+  pub fn position(&self) -> usize {
+    self.inner.position()
+  }
+  /// Get the underlying reader
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+  fn read_chunk(&mut self) -> io::Result<()> {
+    let mut chunk = [0; READ_CHUNK];
+    loop {
+      match self.reader.read(&mut chunk) {
+        Ok(0) => self.inner.finish(),
+        Ok(len) => self.inner.feed(&chunk[..len]),
+        Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+        Err(error) => return Err(error),
       }
-      Event::Begin => {
-        f.write_str(" {")?;
-        depth += 1;
+      return Ok(());
+    }
+  }
+  fn next_parsed(&mut self) -> Option<PResult<Parsed<'static>>> {
+    loop {
+      if let Some(event) = self.inner.next_parsed() {
+        return Some(event);
       }
-      Event::End => {
-        f.write_str("\n")?;
-        depth -= 1;
-        for _ in 0..depth {
-          f.write_str("    ")?;
-        }
-        f.write_str("}")?;
+      if self.inner.is_done() {
+        return None;
+      }
+      if let Err(error) = self.read_chunk() {
+        self.inner.machine.state = ParserState::Done;
+        return Some(Err(Error::Io(error)));
       }
     }
   }
+}
+
+impl<R: io::Read> Iterator for ReadParser<R> {
+  type Item = PResult<Event<'static>>;
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_parsed().map(|event| event.map(|(event, ..)| event))
+  }
+}
+
+impl<R: io::Read> Iterator for Literals<ReadParser<R>> {
+  type Item = PResult<(Event<'static>, Option<Literal<'static>>)>;
+  fn next(&mut self) -> Option<Self::Item> {
+    self
+      .0
+      .next_parsed()
+      .map(|event| event.map(|(event, _, literal)| (event, literal)))
+  }
+}
+
+/// An error while writing text, see [`write_stream_as`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum WriteError {
+  /// The underlying writer failed
+  Fmt(fmt::Error),
+  /// A value can't be written in the chosen version, like `#inf` in v1
+  Unrepresentable(Value<'static>),
+}
+
+impl fmt::Display for WriteError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Fmt(error) => fmt::Display::fmt(error, f),
+      Self::Unrepresentable(value) => write!(f, "value {value} can't be written in this version of kdl"),
+    }
+  }
+}
+impl std::error::Error for WriteError {}
+impl From<fmt::Error> for WriteError {
+  fn from(value: fmt::Error) -> Self {
+    Self::Fmt(value)
+  }
+}
+
+/// Text output state, shared with [`Document`]
+///
+/// [`Document`]: crate::dom::Document
+pub(crate) struct Writer {
+  version: Version,
+  depth: usize,
+  non_start: bool,
+  // inside a children block without any nodes yet
+  empty_block: bool,
+  // write empty children blocks with a blank line, like `Display` always has
+  blank_empty_blocks: bool,
+}
+
+impl Writer {
+  pub(crate) fn new(version: Version) -> Self {
+    Self {
+      version,
+      depth: 0,
+      non_start: false,
+      empty_block: false,
+      blank_empty_blocks: false,
+    }
+  }
+  /// Writer for the [`Display`](fmt::Display) impls of
+  /// [`Document`](crate::dom::Document) and friends
+  pub(crate) fn display() -> Self {
+    Self {
+      blank_empty_blocks: true,
+      ..Self::new(Version::V2)
+    }
+  }
+  fn ident(&self, f: &mut impl fmt::Write, text: &str) -> fmt::Result {
+    match self.version {
+      Version::V1 => write!(f, "{}", IdentDisplayV1(text)),
+      Version::V2 | Version::V2_0_1 => write!(f, "{}", IdentDisplay(text)),
+    }
+  }
+  fn indent(&self, f: &mut impl fmt::Write) -> fmt::Result {
+    for _ in 0..self.depth {
+      f.write_str("    ")?;
+    }
+    Ok(())
+  }
+  pub(crate) fn node(&mut self, f: &mut impl fmt::Write, r#type: Option<&str>, name: &str) -> fmt::Result {
+    if self.non_start {
+      f.write_str("\n")?;
+    }
+    self.non_start = true;
+    self.empty_block = false;
+    self.indent(f)?;
+    if let Some(r#type) = r#type {
+      f.write_str("(")?;
+      self.ident(f, r#type)?;
+      f.write_str(")")?;
+    }
+    self.ident(f, name)
+  }
+  pub(crate) fn entry(
+    &mut self,
+    f: &mut impl fmt::Write,
+    key: Option<&str>,
+    r#type: Option<&str>,
+    value: &Value,
+    literal: Option<&Literal>,
+  ) -> Result<(), WriteError> {
+    f.write_str(" ")?;
+    if let Some(key) = key {
+      self.ident(f, key)?;
+      f.write_str("=")?;
+    }
+    if let Some(r#type) = r#type {
+      f.write_str("(")?;
+      self.ident(f, r#type)?;
+      f.write_str(")")?;
+    }
+    match (self.version, value) {
+      _ if literal.is_some_and(|literal| literal.represents(value, self.version)) => {
+        f.write_str(&literal.unwrap().text)?
+      }
+      (Version::V1, Value::String(text)) => self.ident(f, text)?,
+      (Version::V1, Value::Float(num)) if !num.is_finite() => {
+        return Err(WriteError::Unrepresentable(value.clone().into_owned()))
+      }
+      (Version::V1, Value::Bool(true)) => f.write_str("true")?,
+      (Version::V1, Value::Bool(false)) => f.write_str("false")?,
+      (Version::V1, Value::Null) => f.write_str("null")?,
+      _ => write!(f, "{value}")?,
+    }
+    Ok(())
+  }
+  pub(crate) fn begin(&mut self, f: &mut impl fmt::Write) -> fmt::Result {
+    self.depth += 1;
+    self.empty_block = true;
+    f.write_str(" {")
+  }
+  pub(crate) fn end(&mut self, f: &mut impl fmt::Write) -> fmt::Result {
+    if self.empty_block && self.blank_empty_blocks {
+      f.write_str("\n")?;
+    }
+    self.empty_block = false;
+    f.write_str("\n")?;
+    self.depth -= 1;
+    self.indent(f)?;
+    f.write_str("}")
+  }
+  fn event(&mut self, f: &mut impl fmt::Write, event: &Event, literal: Option<&Literal>) -> Result<(), WriteError> {
+    match event {
+      Event::Node { r#type, name } => self.node(f, r#type.as_deref(), name)?,
+      Event::Entry { key, r#type, value } => self.entry(f, key.as_deref(), r#type.as_deref(), value, literal)?,
+      Event::Begin => self.begin(f)?,
+      Event::End => self.end(f)?,
+    }
+    Ok(())
+  }
+}
+
+/// Write an iterator of events out as text, without constructing a
+/// [`Document`] first
+///
+/// The events can also come with how their values were written, from
+/// [`Parser::literals`].
+///
+/// [`Document`]: crate::dom::Document
+pub fn write_stream<'text, I>(f: &mut impl fmt::Write, events: I) -> fmt::Result
+where
+  I: IntoIterator<Item: Into<(Event<'text>, Option<Literal<'text>>)>>,
+{
+  let mut writer = Writer::new(Version::V2);
+  for event in events {
+    let (event, literal) = event.into();
+    // every value can be written in v2
+    writer.event(f, &event, literal.as_ref()).map_err(|_| fmt::Error)?;
+  }
+  Ok(())
+}
+
+/// Write an iterator of events out as text in a specific version of the spec
+///
+/// Fails if a value can't be represented in that version, the text written
+/// up to that point is left in `f`.
+pub fn write_stream_as<'text, I>(f: &mut impl fmt::Write, events: I, version: Version) -> Result<(), WriteError>
+where
+  I: IntoIterator<Item: Into<(Event<'text>, Option<Literal<'text>>)>>,
+{
+  let mut writer = Writer::new(version);
+  for event in events {
+    let (event, literal) = event.into();
+    writer.event(f, &event, literal.as_ref())?;
+  }
   Ok(())
 }
@@ -2148,3 +2148,1889 @@ test_case! { zero_space_before_slashdash_arg_fail,
   dom: Panic,
   stream: Panic,
 }
+
+// api tests
+#[test]
+fn error_display_and_kind() {
+  use crate::stream::{Error, ErrorKind};
+  let error = Document::parse("node \"string\"1\n").unwrap_err();
+  assert_eq!(error.kind(), ErrorKind::ExpectedSpace);
+  assert_eq!(error.offset(), Some(13));
+  assert_eq!(error.to_string(), "expected whitespace at byte 13");
+  let error = Document::parse("node \"unterminated").unwrap_err();
+  assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+  assert_eq!(error.offset(), None);
+  assert_eq!(error.to_string(), "unexpected end of file");
+  let boxed: Box<dyn std::error::Error> = Box::new(Error::BannedChar('\u{7F}', 4));
+  assert_eq!(boxed.to_string(), "disallowed character '\\u{7f}' at byte 4");
+}
+
+#[test]
+fn error_diagnostic() {
+  use crate::diagnostic::Location;
+  let text = "a 1\n\tnode \"string\"1\n";
+  let error = Document::parse(text).unwrap_err();
+  assert_eq!(
+    error.diagnostic(text).with_file("config.kdl").to_string(),
+    "error: expected whitespace\n --> config.kdl:2:15\n  |\n2 | \tnode \"string\"1\n  | \t             ^\n  = help: arguments, properties, and children blocks must be separated by whitespace"
+  );
+  let error = Document::parse("a\r\nb \"x").unwrap_err();
+  assert_eq!(error.diagnostic("a\r\nb \"x").location().to_string(), "2:5");
+  // an offset in a line break points past the end of the line
+  let error = crate::stream::Error::ExpectedSpace(4);
+  assert_eq!(
+    error.diagnostic("abc\r\nx").to_string(),
+    "error: expected whitespace\n --> <input>:1:5\n  |\n1 | abc\n  |    ^\n  = help: arguments, properties, and children blocks must be separated by whitespace"
+  );
+  let location = Location::of("é😀x\nz", 6);
+  assert_eq!(
+    (
+      location.line,
+      location.column,
+      location.column_utf8,
+      location.column_utf16
+    ),
+    (1, 3, 7, 4)
+  );
+  assert_eq!(Location::offset("é😀x\nz", 1, 3), Some(6));
+  assert_eq!(Location::offset("é😀x\nz", 2, 1), Some(8));
+  assert_eq!(Location::offset("é😀x\nz", 3, 1), None);
+}
+
+#[test]
+fn recovering_parse() {
+  use crate::stream::ErrorKind;
+  let text =
+    "a 1\nb \"x\"y 2\nc {\n  d (e\n  f 3; g #bad\n}\nh {\n  i\n} {\n  j\n}\n}\nk {\n  /-l {\n    m (\n  }\n  n";
+  let (document, errors) = Document::parse_recovering(text);
+  assert_eq!(
+    document.to_string(),
+    "a 1\nb x\nc {\n    d\n    f 3\n    g\n}\nh {\n    i\n}\nk {\n    n\n}"
+  );
+  let errors = errors
+    .iter()
+    .map(|error| (error.kind(), error.offset()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    errors,
+    [
+      (ErrorKind::ExpectedSpace, Some(9)),
+      (ErrorKind::ExpectedCloseParen, Some(23)),
+      (ErrorKind::BadKeyword, Some(33)),
+      (ErrorKind::MultipleChildren, Some(49)),
+      (ErrorKind::UnexpectedCloseBracket, Some(58)),
+      (ErrorKind::ExpectedCloseParen, Some(79)),
+      (ErrorKind::UnexpectedEof, None),
+    ]
+  );
+  // without recovery, the first error stops the parser
+  let mut parser = Parser::new(text);
+  assert_eq!(parser.by_ref().filter(Result::is_err).count(), 1);
+  assert!(parser.next().is_none());
+}
+
+#[test]
+fn slashdash_multiple_children() {
+  use crate::stream::{Error, ErrorKind};
+  assert!(matches!(
+    Document::parse("/-a {} {}\nb"),
+    Err(Error::MultipleChildren(6))
+  ));
+  assert!(matches!(
+    Document::parse("a; /-b {} {}"),
+    Err(Error::MultipleChildren(9))
+  ));
+  // slashdashed blocks don't count
+  assert!(Document::parse("/-a /-{} {} /-{}\nb").is_ok());
+  let (document, errors) = Document::parse_recovering("/-a {} {\n  c\n}\nb");
+  assert_eq!(document.to_string(), "b");
+  assert_eq!(
+    errors.iter().map(Error::kind).collect::<Vec<_>>(),
+    [ErrorKind::MultipleChildren]
+  );
+}
+
+#[test]
+fn recovering_skips_strings() {
+  use crate::stream::ErrorKind;
+  // braces, semicolons, and newlines in strings and comments don't end the
+  // node with the error
+  let text =
+    "a (x \"}\" #\"a;b\"# /* { */ 1\nb 2\nc {\n  d (x \"}\"; e \"\\q}\"; f\n  g \"\"\"\n  }\n  \"\"\" (\n}\nh // }\n";
+  let (document, errors) = Document::parse_recovering(text);
+  assert_eq!(
+    document.to_string(),
+    "a\nb 2\nc {\n    d\n    e\n    f\n    g \"}\"\n}\nh"
+  );
+  let errors = errors.iter().map(|error| error.kind()).collect::<Vec<_>>();
+  assert_eq!(
+    errors,
+    [
+      ErrorKind::ExpectedCloseParen,
+      ErrorKind::ExpectedCloseParen,
+      ErrorKind::BadEscape,
+      ErrorKind::ExpectedCloseParen,
+    ]
+  );
+  // v1 raw strings too
+  let text = "a (x r#\"}\"#; b\n";
+  let events = Parser::new(text).with_version(crate::stream::Version::V1).recovering();
+  let (document, errors): (Vec<_>, Vec<_>) = events.partition(Result::is_ok);
+  assert_eq!(errors.len(), 1);
+  let document: Document = document.into_iter().map(Result::unwrap).collect();
+  assert_eq!(document.to_string(), "a\nb");
+}
+
+#[test]
+fn spanned_events() {
+  use crate::stream::{EntrySpan, EventSpan, Span};
+  let span = |start, end| Span { start, end };
+  let text = "(t)\"a b\" 1 /-2 k=(u)#true {\n  c\n}";
+  let spans = Parser::new(text)
+    .spanned()
+    .map(|event| event.unwrap().1)
+    .collect::<Vec<_>>();
+  assert_eq!(
+    spans,
+    [
+      EventSpan::Node {
+        r#type: Some(span(0, 3)),
+        name: span(3, 8),
+      },
+      EventSpan::Entry(EntrySpan {
+        key: None,
+        r#type: None,
+        value: span(9, 10),
+      }),
+      EventSpan::Entry(EntrySpan {
+        key: Some(span(15, 16)),
+        r#type: Some(span(17, 20)),
+        value: span(20, 25),
+      }),
+      EventSpan::Begin(span(26, 27)),
+      EventSpan::Node {
+        r#type: None,
+        name: span(30, 31),
+      },
+      EventSpan::End {
+        brace: span(32, 33),
+        children: span(26, 33),
+      },
+    ]
+  );
+  assert_eq!(spans[2].span(), span(15, 25));
+  assert_eq!(&text[std::ops::Range::from(spans[2].span())], "k=(u)#true");
+}
+
+#[test]
+fn spanned_document() {
+  use crate::dom::IndexPath;
+  use crate::stream::Span;
+  let text = "server {\n  listen port=80\n  (t)host \"a\"\n}\nother";
+  let document = Document::parse_spanned(text).unwrap();
+  assert_eq!(document, Document::parse(text).unwrap());
+  let server = document.nodes[0].span().unwrap();
+  assert_eq!(server.span, Span { start: 0, end: 41 });
+  assert_eq!(server.children, Some(Span { start: 7, end: 41 }));
+  let listen = &document.nodes[0].children.as_ref().unwrap().nodes[0];
+  assert_eq!(
+    &text[listen.span().unwrap().span.start..listen.span().unwrap().span.end],
+    "listen port=80"
+  );
+  let port = listen.entry("port").unwrap().span().unwrap();
+  assert_eq!(
+    (port.key, port.value),
+    (Some(Span { start: 18, end: 22 }), Span { start: 23, end: 25 })
+  );
+  let path = document.node_at(24).unwrap();
+  assert_eq!(
+    path,
+    IndexPath {
+      nodes: vec![0, 0],
+      entry: Some(0)
+    }
+  );
+  assert_eq!(path.entry(&document).unwrap().value, 80.into());
+  let path = document.node_at(29).unwrap();
+  assert_eq!((path.nodes.as_slice(), path.entry), (&[0, 1][..], None));
+  assert_eq!(path.node(&document).unwrap().name(), "host");
+  assert_eq!(document.node_at(9).unwrap().nodes, [0]);
+  assert_eq!(document.node_at(43).unwrap().nodes, [1]);
+  assert_eq!(document.node_at(41), None);
+  assert_eq!(Document::parse(text).unwrap().node_at(24), None);
+}
+
+#[test]
+fn version_one() {
+  use crate::stream::Version;
+  let parse = |text, version| {
+    Parser::new(text)
+      .with_version(version)
+      .collect::<Result<Document, _>>()
+      .map(|doc| doc.to_string())
+  };
+  let text = "#node true false null r\"C:\\path\" r##\"a \"# b\"## \"esc\\/aped\"\nnode{ child; }\n\"<a>\" 1\n";
+  assert_eq!(
+    parse(text, Version::V1).unwrap(),
+    "\"#node\" #true #false #null \"C:\\\\path\" \"a \\\"# b\" \"esc/aped\"\nnode {\n    child\n}\n<a> 1"
+  );
+  assert!(parse("<a> 1", Version::V1).is_err());
+  assert!(parse(text, Version::V2).is_err());
+  assert!(parse("node \"multi\nline\"", Version::V1).is_ok());
+  assert!(parse("node #true \"\\s\"", Version::V1).is_err());
+  assert!(parse("true 1", Version::V1).is_err());
+  assert_eq!(parse("node inf", Version::V1).unwrap(), "node inf");
+  assert_eq!(Parser::new(text).detect_version().version(), Version::V1);
+  assert_eq!(Parser::new("node #true").detect_version().version(), Version::V2);
+  assert_eq!(Parser::new("node r\"x\"").detect_version().version(), Version::V1);
+  // the marker wins over what the text parses as
+  assert_eq!(
+    Parser::new("/- kdl-version 1\nnode").detect_version().version(),
+    Version::V1
+  );
+  assert_eq!(Parser::new("node true").detect_version().version(), Version::V1);
+  assert_eq!(
+    Parser::new("\u{FEFF}/- kdl-version 2\nnode true")
+      .detect_version()
+      .version(),
+    Version::V2
+  );
+  assert_eq!(parse("/- kdl-version 1\nnode true", Version::V1).unwrap(), "node #true");
+}
+
+#[test]
+fn write_version_one() {
+  use crate::stream::{write_stream_as, Version, WriteError};
+  let text = "node #true #false #null 1.5 \"C:\\\\dir\" \"say \\\"hi\\\"#\" \"a\\nb\" \"r#x\" \"true\" \"<a>\" (\"a b\")x k=1 {\n    child\n}";
+  let document = Document::parse(text).unwrap();
+  let mut v1 = String::new();
+  document.write_as(&mut v1, Version::V1).unwrap();
+  assert_eq!(
+    v1,
+    "node true false null 1.5 r\"C:\\dir\" r##\"say \"hi\"#\"## \"a\\nb\" \"r#x\" \"true\" \"<a>\" (\"a b\")x k=1 {\n    child\n}"
+  );
+  let reparsed = Parser::new(&v1)
+    .with_version(Version::V1)
+    .collect::<Result<Document, _>>();
+  assert_eq!(reparsed.unwrap(), document);
+  let mut v2 = String::new();
+  document.write_as(&mut v2, Version::V2).unwrap();
+  assert_eq!(v2, document.to_string());
+  let mut stream = String::new();
+  write_stream_as(&mut stream, Parser::new(text).map(Result::unwrap), Version::V1).unwrap();
+  assert_eq!(stream, v1);
+  let error = Document::parse("node #inf")
+    .unwrap()
+    .write_as(&mut String::new(), Version::V1);
+  assert!(matches!(error, Err(WriteError::Unrepresentable(value)) if value.to_string() == "#inf"));
+}
+
+#[test]
+fn draft_and_really_raw() {
+  use crate::stream::Version;
+  let parse = |parser: Parser| parser.collect::<Result<Document, _>>().map(|doc| doc.to_string());
+  let draft = |text| parse(Parser::new(text).with_version(Version::V2_0_1));
+  assert_eq!(draft("node\u{b}arg\n").unwrap(), "node arg");
+  assert_eq!(draft("foo123{bar}\n").unwrap(), "foo123 {\n    bar\n}");
+  assert_eq!(
+    draft("node \"string\"/-1\nnode \"string\" {}/-{}\nnode \"string\"/-foo=1\n").unwrap(),
+    "node string\nnode string {\n\n}\nnode string"
+  );
+  assert!(draft("node \"string\"1").is_err());
+  let text = "template #\"line 1\nline \u{1} 2\"#";
+  assert!(parse(Parser::new(text)).is_err());
+  let document = Parser::new(text)
+    .with_really_raw(true)
+    .collect::<Result<Document, _>>()
+    .unwrap();
+  assert_eq!(document.nodes[0][0].value, "line 1\nline \u{1} 2".into());
+  // escaped strings are still checked
+  assert!(parse(Parser::new("node \"a\nb\"").with_really_raw(true)).is_err());
+}
+
+#[test]
+fn chunked_input() {
+  use crate::stream::{ChunkParser, Error, ReadParser};
+  /// reads a single byte at a time
+  struct Trickle<'a>(&'a [u8]);
+  impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+      let len = self.0.len().min(buf.len()).min(1);
+      buf[..len].copy_from_slice(&self.0[..len]);
+      self.0 = &self.0[len..];
+      Ok(len)
+    }
+  }
+  let text = "(a)node 1 \"two\" key=#\"raw\"# {\n  /-child; multi \"\"\"\n    ünï\n    \"\"\" 0x10\n}\nlast";
+  let events = |parser: Parser| parser.map(|event| format!("{event:?}")).collect::<Vec<_>>();
+  let expected = events(Parser::new(text));
+  let read = ReadParser::new(Trickle(text.as_bytes()));
+  assert_eq!(read.map(|event| format!("{event:?}")).collect::<Vec<_>>(), expected);
+  // events near the end of the input so far wait for more
+  let mut chunked = ChunkParser::new();
+  chunked.feed(b"node 1 2");
+  assert_eq!(chunked.by_ref().count(), 1);
+  chunked.feed(b"3 {}");
+  chunked.finish();
+  assert_eq!(chunked.by_ref().count(), 4);
+  assert!(chunked.is_done());
+  // error positions are from the start of the input
+  let text = "first\n".repeat(1000) + "bad 1=2\nlast";
+  let mut chunked = ChunkParser::new().recovering();
+  for chunk in text.as_bytes().chunks(100) {
+    chunked.feed(chunk);
+  }
+  chunked.finish();
+  let errors = chunked
+    .filter_map(Result::err)
+    .map(|error| error.offset())
+    .collect::<Vec<_>>();
+  assert_eq!(errors, [Some(6005)]);
+  let mut chunked = ChunkParser::new();
+  chunked.feed(b"node \"\xff\"");
+  chunked.finish();
+  let result = chunked.collect::<Result<Vec<_>, _>>();
+  assert!(matches!(result, Err(Error::InvalidEncoding(6))));
+  // stopping at an error inside an ignored children block
+  assert_eq!(Parser::new("node {} {}").count(), 4);
+}
+
+#[test]
+fn chunked_slashdash() {
+  use crate::stream::ChunkParser;
+  // skipped events are kept as they arrive, so the parser doesn't hold on to
+  // the whole block and parse it again from the start after every chunk
+  let block = "{\n".to_owned() + &"  c 1 key=2 {\n    d\n  }\n".repeat(10_000) + "}\ne";
+  // and the same for an extra children block skipped after an error
+  for (text, len) in [(format!("a\n/-b {block}"), 2), (format!("a {{}} {block}"), 5)] {
+    let mut chunked = ChunkParser::new().recovering();
+    let mut events = Vec::new();
+    let mut fed = 0;
+    for chunk in text.as_bytes().chunks(16) {
+      chunked.feed(chunk);
+      fed += chunk.len();
+      events.extend(chunked.by_ref());
+      assert!(fed - chunked.position() < 32);
+    }
+    chunked.finish();
+    events.extend(chunked);
+    let expected = Parser::new(&text).recovering().collect::<Vec<_>>();
+    assert_eq!(format!("{events:?}"), format!("{expected:?}"));
+    assert_eq!(events.len(), len);
+  }
+}
+
+#[test]
+fn byte_input() {
+  use crate::stream::Error;
+  let utf16 = |big_endian: bool, text: &str| {
+    let units = "\u{FEFF}".encode_utf16().chain(text.encode_utf16());
+    units
+      .flat_map(|unit| {
+        if big_endian {
+          unit.to_be_bytes()
+        } else {
+          unit.to_le_bytes()
+        }
+      })
+      .collect::<Vec<_>>()
+  };
+  let expected = Document::parse("node \"ünï\" 1 {\n  child 😀\n}").unwrap();
+  assert_eq!(
+    Document::parse_bytes("node \"ünï\" 1 {\n  child 😀\n}".as_bytes()).unwrap(),
+    expected
+  );
+  assert_eq!(
+    Document::parse_bytes("\u{FEFF}node \"ünï\" 1 {\n  child 😀\n}".as_bytes()).unwrap(),
+    expected
+  );
+  for big_endian in [false, true] {
+    let bytes = utf16(big_endian, "node \"ünï\" 1 {\n  child 😀\n}");
+    assert_eq!(Document::parse_bytes(&bytes).unwrap(), expected);
+    // the parser can only borrow utf-8
+    let error = Parser::from_bytes(&bytes).err().unwrap();
+    assert!(matches!(error, Error::Utf16));
+    assert_eq!(error.to_string(), "utf-16 text has to be decoded first");
+    // lone surrogate
+    let mut bytes = utf16(big_endian, "node");
+    bytes.extend([0xD8, 0xD8]);
+    assert!(matches!(Document::parse_bytes(&bytes), Err(Error::InvalidEncoding(10))));
+    bytes.pop();
+    assert!(matches!(Document::parse_bytes(&bytes), Err(Error::InvalidEncoding(10))));
+  }
+  assert!(matches!(
+    Document::parse_bytes(b"node \"a\xC0b\""),
+    Err(Error::InvalidEncoding(7))
+  ));
+  assert!(matches!(
+    Parser::from_bytes(b"node \xE2\x82"),
+    Err(Error::InvalidEncoding(5))
+  ));
+  let parser = Parser::from_bytes("\u{FEFF}node".as_bytes()).unwrap();
+  assert_eq!(parser.collect::<Result<Document, _>>().unwrap().to_string(), "node");
+  // a byte order mark anywhere else is still banned
+  assert!(Document::parse_bytes("node \u{FEFF}".as_bytes()).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_from_document() {
+  use std::collections::BTreeMap;
+
+  use serde::Deserialize;
+
+  use crate::de;
+  use crate::dom::Value;
+  #[derive(Debug, PartialEq, Deserialize)]
+  #[serde(rename_all = "lowercase")]
+  enum Mode {
+    Fast,
+    Slow,
+  }
+  #[derive(Debug, PartialEq, Deserialize)]
+  #[serde(rename_all = "lowercase")]
+  enum Listen {
+    Tcp { port: u16 },
+    Udp { port: u16 },
+  }
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct Server {
+    #[serde(rename = "0")]
+    host: String,
+    #[serde(rename = "$type")]
+    kind: Option<String>,
+    listen: Vec<Listen>,
+    timeout: Option<f64>,
+  }
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct Config<'a> {
+    name: &'a str,
+    tags: Vec<String>,
+    mode: Mode,
+    point: (i32, i32),
+    limits: BTreeMap<String, u32>,
+    server: Vec<Server>,
+    missing: Option<bool>,
+  }
+  let text = "name demo\ntags a b c\nmode fast\npoint 1 -2\nlimits { files 100; procs 20 }\n\
+    (main)server alpha { (tcp)listen port=80; (udp)listen port=53 }\n\
+    server beta timeout=1.5 { (tcp)listen port=8080 }";
+  let document = Document::parse(text).unwrap();
+  let config: Config = de::from_document(&document).unwrap();
+  assert_eq!(
+    config,
+    Config {
+      name: "demo",
+      tags: vec!["a".into(), "b".into(), "c".into()],
+      mode: Mode::Fast,
+      point: (1, -2),
+      limits: [("files".into(), 100), ("procs".into(), 20)].into(),
+      server: vec![
+        Server {
+          host: "alpha".into(),
+          kind: Some("main".into()),
+          listen: vec![Listen::Tcp { port: 80 }, Listen::Udp { port: 53 }],
+          timeout: None,
+        },
+        Server {
+          host: "beta".into(),
+          kind: None,
+          listen: vec![Listen::Tcp { port: 8080 }],
+          timeout: Some(1.5),
+        },
+      ],
+      missing: None,
+    }
+  );
+  // errors have the path to the problem
+  #[derive(Debug, Deserialize)]
+  #[allow(dead_code)]
+  struct Servers {
+    server: Server,
+  }
+  let text = "server alpha {\n  (tcp)listen port=80\n  (udp)listen port=70000\n}";
+  let error = de::from_str::<Servers>(text).unwrap_err();
+  assert_eq!(error.path(), "server > listen[1].port");
+  assert_eq!(error.message(), "invalid value: integer `70000`, expected u16");
+  assert_eq!(error.span().map(|span| &text[span.start..span.end]), Some("port=70000"));
+  let error = de::from_str::<Servers>("server alpha beta").unwrap_err();
+  assert_eq!(error.to_string(), "server: missing field `listen`");
+  let error = de::from_str::<Servers>("server alpha\nserver beta").unwrap_err();
+  assert_eq!(error.to_string(), "server: expected one `server` node, found 2");
+  let error = de::from_str::<Servers>("server {").unwrap_err();
+  assert_eq!(error.to_string(), "unexpected end of file");
+  // values and single nodes
+  let node = &Document::parse("(udp)listen 53").unwrap().nodes[0];
+  #[derive(Debug, PartialEq, Deserialize)]
+  #[serde(rename_all = "lowercase")]
+  enum Port {
+    Tcp(u16),
+    Udp(u16),
+  }
+  assert_eq!(de::from_node::<Port>(node).unwrap(), Port::Udp(53));
+  assert_eq!(de::from_node::<Vec<u8>>(node).unwrap(), [53]);
+  assert_eq!(de::from_value::<Option<u8>>(&Value::Null).unwrap(), None);
+  assert!(de::from_value::<i8>(&Value::Integer(128)).is_err());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde_from_parser() {
+  use std::collections::BTreeMap;
+
+  use serde::Deserialize;
+
+  use crate::de;
+  #[derive(Debug, PartialEq, Deserialize)]
+  #[serde(rename_all = "lowercase")]
+  enum Listen {
+    Tcp { port: u16 },
+    Udp { port: u16 },
+  }
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct Server<'a> {
+    #[serde(rename = "0")]
+    host: &'a str,
+    #[serde(rename = "$type")]
+    kind: Option<String>,
+    listen: Vec<Listen>,
+    timeout: Option<f64>,
+  }
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct Config<'a> {
+    name: &'a str,
+    tags: Vec<String>,
+    point: (i32, i32),
+    limits: BTreeMap<String, u32>,
+    #[serde(borrow)]
+    server: Vec<Server<'a>>,
+    missing: Option<bool>,
+  }
+  let text = "name demo\ntags a b c\npoint 1 -2\nlimits { files 100; procs 20 }\n\
+    unknown { deeply { nested } }\nunknown again\n\
+    (main)server alpha { (tcp)listen port=80; (udp)listen port=53 }\n\
+    server beta timeout=1.5 { (tcp)listen port=8080 }";
+  let document = Document::parse(text).unwrap();
+  let config: Config = de::from_parser(Parser::new(text)).unwrap();
+  assert_eq!(config, de::from_document::<Config>(&document).unwrap());
+  assert_eq!(config.server[1].host, "beta");
+  // children come as a lazy sequence
+  #[derive(Debug, PartialEq, Deserialize)]
+  struct Tree {
+    #[serde(rename = "$name")]
+    name: String,
+    #[serde(rename = "$children")]
+    children: Vec<Tree>,
+  }
+  let tree: Vec<Tree> = de::from_parser(Parser::new("a { b { c }; d }\ne")).unwrap();
+  let leaf = |name: &str| Tree {
+    name: name.into(),
+    children: vec![],
+  };
+  assert_eq!(
+    tree,
+    [
+      Tree {
+        name: "a".into(),
+        children: vec![
+          Tree {
+            name: "b".into(),
+            children: vec![leaf("c")],
+          },
+          leaf("d"),
+        ],
+      },
+      leaf("e"),
+    ]
+  );
+  // errors have the path to the problem
+  #[derive(Debug, Deserialize)]
+  #[allow(dead_code)]
+  struct Servers<'a> {
+    #[serde(borrow)]
+    server: Server<'a>,
+  }
+  let text = "server alpha {\n  (tcp)listen port=80\n  (udp)listen port=70000\n}";
+  let error = de::from_parser::<Servers>(Parser::new(text)).unwrap_err();
+  assert_eq!(error.path(), "server > listen[1].port");
+  assert_eq!(error.message(), "invalid value: integer `70000`, expected u16");
+  assert_eq!(error.span().map(|span| &text[span.start..span.end]), Some("port=70000"));
+  let error = de::from_parser::<Servers>(Parser::new("server alpha { (tcp)listen port=1 }\nserver beta")).unwrap_err();
+  assert_eq!(error.to_string(), "server: expected one `server` node, found more");
+  let error = de::from_parser::<Servers>(Parser::new("server alpha { listen; }\n}")).unwrap_err();
+  assert_eq!(error.message(), "unexpected `}`");
+  let error = de::from_parser::<Vec<u8>>(Parser::new("- 1; - 2; - x")).unwrap_err();
+  assert_eq!(error.to_string(), "-[2]: invalid type: string \"x\", expected u8");
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde_to_string() {
+  use std::collections::BTreeMap;
+
+  use serde::{Deserialize, Serialize};
+
+  use crate::{de, ser};
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  #[serde(rename_all = "lowercase")]
+  enum Mode {
+    Fast,
+    Slow,
+  }
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  #[serde(rename_all = "lowercase")]
+  enum Listen {
+    Tcp { port: u16 },
+    Udp(u16),
+  }
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Server {
+    #[serde(rename = "0")]
+    host: String,
+    #[serde(rename = "$type")]
+    kind: Option<String>,
+    listen: Vec<Listen>,
+    #[serde(with = "ser::child", default)]
+    timeout: Option<f64>,
+  }
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Config {
+    name: String,
+    tags: Vec<String>,
+    mode: Mode,
+    point: (i32, i32),
+    limits: BTreeMap<String, u32>,
+    server: Vec<Server>,
+    missing: Option<bool>,
+  }
+  let config = Config {
+    name: "demo".into(),
+    tags: vec!["a".into(), "b c".into()],
+    mode: Mode::Slow,
+    point: (1, -2),
+    limits: [("files".into(), 100), ("procs".into(), 20)].into(),
+    server: vec![
+      Server {
+        host: "alpha".into(),
+        kind: Some("main".into()),
+        listen: vec![Listen::Tcp { port: 80 }, Listen::Udp(53)],
+        timeout: None,
+      },
+      Server {
+        host: "beta".into(),
+        kind: None,
+        listen: vec![Listen::Tcp { port: 8080 }],
+        timeout: Some(1.5),
+      },
+    ],
+    missing: None,
+  };
+  let text = ser::to_string(&config).unwrap();
+  assert_eq!(
+    text,
+    "name demo\ntags a \"b c\"\nmode slow\npoint 1 -2\nlimits files=100 procs=20\n\
+    (main)server alpha {\n    (tcp)listen port=80\n    (udp)listen 53\n}\n\
+    server beta {\n    (tcp)listen port=8080\n    timeout 1.5\n}"
+  );
+  assert_eq!(de::from_str::<Config>(&text).unwrap(), config);
+  assert_eq!(ser::to_document(&config).unwrap().to_string(), text);
+  // a lone element that wouldn't read back as a list gets wrapped
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Points {
+    point: Vec<(i32, i32)>,
+    #[serde(with = "ser::property")]
+    origin: Listen,
+  }
+  let points = Points {
+    point: vec![(1, 2)],
+    origin: Listen::Udp(5),
+  };
+  let text = ser::to_string(&[("points", &points)].into_iter().collect::<BTreeMap<_, _>>()).unwrap();
+  assert_eq!(text, "points origin=(udp)5 {\n    point {\n        - 1 2\n    }\n}");
+  let map = de::from_str::<BTreeMap<String, Points>>(&text).unwrap();
+  assert_eq!(map["points"], points);
+  // the rest of the struct field names
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Tree {
+    #[serde(rename = "$name")]
+    name: String,
+    #[serde(rename = "$args")]
+    values: Vec<i64>,
+    #[serde(rename = "$children")]
+    children: Vec<Tree>,
+  }
+  let leaf = |name: &str, values: Vec<i64>| Tree {
+    name: name.into(),
+    values,
+    children: vec![],
+  };
+  let tree = vec![Tree {
+    name: "a".into(),
+    values: vec![1],
+    children: vec![leaf("b", vec![2, 3]), leaf("c", vec![])],
+  }];
+  let text = ser::to_string(&tree).unwrap();
+  assert_eq!(text, "a 1 {\n    b 2 3\n    c\n}");
+  assert_eq!(de::from_str::<Vec<Tree>>(&text).unwrap(), tree);
+  // errors
+  assert_eq!(
+    ser::to_string(&5).unwrap_err().to_string(),
+    "expected a struct, map, sequence, or enum variant at the top level"
+  );
+  #[derive(Serialize)]
+  struct Bad {
+    #[serde(with = "ser::property")]
+    point: (i32, i32),
+  }
+  assert_eq!(
+    ser::to_string(&[("bad", Bad { point: (1, 2) })].into_iter().collect::<BTreeMap<_, _>>())
+      .unwrap_err()
+      .to_string(),
+    "`point` can't be a property, it isn't a single value"
+  );
+}
+
+#[test]
+#[cfg(feature = "derive")]
+fn derive_from_node() {
+  use crate::convert::{FromNode, ToNode};
+  #[derive(Debug, PartialEq, FromNode, ToNode)]
+  #[kdl(name = "server")]
+  struct Server<'a> {
+    #[kdl(arg)]
+    host: &'a str,
+    #[kdl(arguments)]
+    aliases: Vec<String>,
+    #[kdl(property, default)]
+    port: u16,
+    #[kdl(property, rename = "max-connections")]
+    max_connections: Option<u32>,
+    #[kdl(child)]
+    tls: Option<Tls>,
+    #[kdl(children, rename = "listen")]
+    listen: Vec<Listen>,
+  }
+  #[derive(Debug, PartialEq, FromNode, ToNode)]
+  struct Tls {
+    #[kdl(property)]
+    cert: String,
+  }
+  #[derive(Debug, PartialEq, FromNode, ToNode)]
+  struct Listen {
+    #[kdl(node_name)]
+    name: String,
+    #[kdl(type_name, default = "tcp")]
+    protocol: String,
+    #[kdl(arg)]
+    port: u16,
+  }
+  fn tcp() -> String {
+    "tcp".into()
+  }
+  let text = "server example.com www.example.com max-connections=10 {\n    tls cert=a.pem\n    \
+    listen 80\n    (udp)listen 53\n}";
+  let document = Document::parse_spanned(text).unwrap();
+  let server = Server::from_node(&document.nodes[0]).unwrap();
+  assert_eq!(
+    server,
+    Server {
+      host: "example.com",
+      aliases: vec!["www.example.com".into()],
+      port: 0,
+      max_connections: Some(10),
+      tls: Some(Tls { cert: "a.pem".into() }),
+      listen: vec![
+        Listen {
+          name: "listen".into(),
+          protocol: "tcp".into(),
+          port: 80,
+        },
+        Listen {
+          name: "listen".into(),
+          protocol: "udp".into(),
+          port: 53,
+        },
+      ],
+    }
+  );
+  assert_eq!(
+    server.to_node().to_string(),
+    "server example.com www.example.com port=0 max-connections=10 {\n    tls cert=a.pem\n    \
+    (tcp)listen 80\n    (udp)listen 53\n}"
+  );
+  // errors have the path to the problem, and spans when parsed with them
+  let text = "server a {\n    listen 80\n    (udp)listen 70000\n}";
+  let document = Document::parse_spanned(text).unwrap();
+  let error = Server::from_node(&document.nodes[0]).unwrap_err();
+  assert_eq!(error.path(), "listen[1].0");
+  assert_eq!(error.message(), "invalid value: integer `70000`, expected u16");
+  assert_eq!(error.span().map(|span| &text[span.start..span.end]), Some("70000"));
+  let document = Document::parse("server a { tls; }").unwrap();
+  let error = Server::from_node(&document.nodes[0]).unwrap_err();
+  assert_eq!(error.to_string(), "tls: missing property `cert`");
+  let document = Document::parse("server port=80").unwrap();
+  let error = Server::from_node(&document.nodes[0]).unwrap_err();
+  assert_eq!(error.to_string(), "missing argument 0");
+}
+
+#[test]
+#[cfg(feature = "derive")]
+fn derive_optional_round_trip() {
+  use crate::convert::{FromNode, ToNode};
+  #[derive(Debug, PartialEq, FromNode, ToNode)]
+  struct Range {
+    #[kdl(arg)]
+    start: Option<i32>,
+    #[kdl(arg)]
+    end: i32,
+    #[kdl(property)]
+    step: Option<i32>,
+  }
+  let range = Range {
+    start: None,
+    end: 5,
+    step: None,
+  };
+  let text = range.to_node().to_string();
+  assert_eq!(text, "- #null 5");
+  // `#null` and missing entries are both `None`
+  for text in [&text[..], "- #null 5 step=#null"] {
+    let document = Document::parse(text).unwrap();
+    assert_eq!(Range::from_node(&document.nodes[0]).unwrap(), range);
+  }
+  let document = Document::parse("- 1 5 step=2").unwrap();
+  let range = Range::from_node(&document.nodes[0]).unwrap();
+  assert_eq!((range.start, range.step), (Some(1), Some(2)));
+  assert_eq!(range.to_node().to_string(), "- 1 5 step=2");
+}
+
+#[test]
+#[cfg(feature = "derive")]
+fn derive_children_names() {
+  use crate::convert::{FromNode, ToNode};
+  #[derive(FromNode, ToNode)]
+  struct Item {
+    #[kdl(node_name)]
+    name: String,
+  }
+  #[derive(FromNode, ToNode)]
+  struct All {
+    #[kdl(children)]
+    items: Vec<Item>,
+  }
+  #[derive(FromNode, ToNode)]
+  struct Named {
+    #[kdl(children, rename = "b")]
+    items: Vec<Item>,
+  }
+  let document = Document::parse("- { a; b; c }").unwrap();
+  // every child without `rename`, keeping their names
+  let all = All::from_node(&document.nodes[0]).unwrap();
+  assert_eq!(
+    all.items.iter().map(|item| &item.name[..]).collect::<Vec<_>>(),
+    ["a", "b", "c"]
+  );
+  assert_eq!(all.to_node().to_string(), "- {\n    a\n    b\n    c\n}");
+  // only the renamed ones otherwise, written with that name
+  let named = Named::from_node(&document.nodes[0]).unwrap();
+  assert_eq!(named.items.len(), 1);
+  assert_eq!(named.to_node().to_string(), "- {\n    b\n}");
+}
+
+#[test]
+fn typed_accessors() {
+  use std::collections::{BTreeMap, HashMap};
+
+  use crate::convert::{self, FromNode, FromValue};
+  use crate::dom::{Node, Value};
+  let text = "server example.com {\n    listen 80 host=a\n    listen 70000 host=b\n    \
+    limits files=10 procs=2 files=20\n    tags a b #null\n    timeout #null\n}";
+  let document = Document::parse_spanned(text).unwrap();
+  let server = document.node::<&Node>("server").unwrap();
+  assert_eq!(server.arg::<String>(0).unwrap(), "example.com");
+  assert_eq!(server.arg::<Option<&str>>(1).unwrap(), None);
+  assert_eq!(
+    server.child::<Vec<Option<&str>>>("tags").unwrap(),
+    [Some("a"), Some("b"), None]
+  );
+  assert_eq!(server.child::<Option<f64>>("timeout").unwrap(), None);
+  assert_eq!(server.child::<Option<f64>>("missing").unwrap(), None);
+  let limits = server.child::<BTreeMap<String, u8>>("limits").unwrap();
+  assert_eq!(limits, [("files".into(), 20), ("procs".into(), 2)].into());
+  let limits = server.child::<HashMap<&str, i64>>("limits").unwrap();
+  assert_eq!(limits["files"], 20);
+  // range checks against the i128
+  assert_eq!(
+    server.children.as_ref().unwrap().nodes[2].get::<u32>("files").unwrap(),
+    20
+  );
+  assert_eq!(
+    u8::from_value(&Value::Integer(256)).unwrap_err().message(),
+    "invalid value: integer `256`, expected u8"
+  );
+  assert_eq!(i8::from_value(&Value::Integer(-128)).unwrap(), -128);
+  assert_eq!(
+    f64::from_value(&Value::BigInteger("1e".into())).unwrap_err().message(),
+    "invalid value: integer `1e`, expected f64"
+  );
+  assert_eq!(
+    u128::from_value(&Value::Integer(-1)).unwrap_err().message(),
+    "invalid value: integer `-1`, expected u128"
+  );
+  assert_eq!(
+    bool::from_value(&Value::Integer(1)).unwrap_err().message(),
+    "invalid type: integer `1`, expected a boolean"
+  );
+  // errors have the full path
+  #[allow(dead_code)]
+  struct Listen(u16);
+  impl<'a> FromNode<'a> for Listen {
+    fn from_node(node: &'a Node) -> Result<Self, convert::Error> {
+      node.arg(0).map(Listen)
+    }
+  }
+  #[allow(dead_code)]
+  struct Server(Vec<Listen>);
+  impl<'a> FromNode<'a> for Server {
+    fn from_node(node: &'a Node) -> Result<Self, convert::Error> {
+      convert::children(node, Some("listen")).map(Server)
+    }
+  }
+  let error = document.node::<Server>("server").err().unwrap();
+  assert_eq!(
+    error.to_string(),
+    "server > listen[1].0: invalid value: integer `70000`, expected u16"
+  );
+  assert_eq!(error.span().map(|span| &text[span.start..span.end]), Some("70000"));
+  let error = server.child::<u16>("listen").unwrap_err();
+  assert_eq!(error.to_string(), "listen: expected one `listen` node, found 2");
+  let error = server.get::<u16>("port").unwrap_err();
+  assert_eq!(error.to_string(), "missing property `port`");
+  assert_eq!(error.span().map(|span| span.start), Some(0));
+  let error = document.node::<&Node>("client").unwrap_err();
+  assert_eq!(error.to_string(), "missing node `client`");
+}
+
+#[test]
+fn value_helpers() {
+  use std::collections::BTreeSet;
+
+  use crate::dom::Value;
+  let text = Value::from("a");
+  assert_eq!(
+    (text.as_str(), text.as_i64(), text.type_name()),
+    (Some("a"), None, "string")
+  );
+  let big = Value::Integer(i64::MAX as i128 + 1);
+  assert_eq!(
+    (big.as_i64(), big.as_u64(), big.as_i128()),
+    (None, Some(1 << 63), Some(1 << 63))
+  );
+  assert_eq!(Value::Integer(-1).as_u64(), None);
+  assert_eq!(Value::Integer(3).as_f64(), Some(3.0));
+  assert_eq!(Value::Float(0.5).as_f64(), Some(0.5));
+  assert_eq!(Value::Bool(true).as_bool(), Some(true));
+  assert!(Value::Null.is_null() && !Value::Bool(false).is_null());
+  assert_eq!(Value::Float(1.0).type_name(), "float");
+  // conversions check for overflow
+  assert_eq!(u8::try_from(&Value::Integer(255)).unwrap(), 255);
+  assert_eq!(
+    i16::try_from(Value::Integer(40000)).unwrap_err().message(),
+    "invalid value: integer `40000`, expected i16"
+  );
+  assert_eq!(f64::try_from(Value::Integer(2)).unwrap(), 2.0);
+  assert!(f32::try_from(Value::Float(1e300)).is_err());
+  assert!(f32::try_from(Value::Float(f64::INFINITY)).unwrap().is_infinite());
+  assert!(usize::try_from(&Value::from("1")).is_err());
+  // total ordering that agrees with equality
+  let values = [
+    Value::from("b"),
+    Value::Float(f64::NAN),
+    Value::Float(1.0),
+    Value::Integer(1),
+    Value::Float(-0.0),
+    Value::Integer(i128::MIN),
+    Value::Float(f64::NEG_INFINITY),
+    Value::Float(0.5),
+    Value::Integer(0),
+    Value::Float(0.0),
+    Value::Bool(true),
+    Value::Null,
+    Value::from("a"),
+    Value::Float(f64::INFINITY),
+    Value::Integer(i128::MAX),
+  ];
+  let sorted: Vec<_> = values.iter().cloned().collect::<BTreeSet<_>>().into_iter().collect();
+  assert_eq!(
+    sorted,
+    [
+      Value::Null,
+      Value::Bool(true),
+      Value::Float(f64::NEG_INFINITY),
+      Value::Integer(i128::MIN),
+      Value::Integer(0),
+      Value::Float(0.0),
+      Value::Float(0.5),
+      Value::Integer(1),
+      Value::Float(1.0),
+      Value::Integer(i128::MAX),
+      Value::Float(f64::INFINITY),
+      Value::Float(f64::NAN),
+      Value::from("a"),
+      Value::from("b"),
+    ]
+  );
+  for l in &values {
+    for r in &values {
+      assert_eq!(l == r, l.cmp(r).is_eq(), "{l:?} {r:?}");
+      assert_eq!(l.cmp(r), r.cmp(l).reverse(), "{l:?} {r:?}");
+    }
+  }
+  assert!(Value::Float(-1.5) < Value::Integer(-1));
+  assert!(Value::Integer(-2) < Value::Float(-1.5));
+}
+
+#[test]
+fn big_integers() {
+  use std::borrow::Cow;
+
+  use crate::convert::ToValue;
+  use crate::dom::Value;
+  use crate::stream::ErrorKind;
+  let big = |text: &str| Value::BigInteger(text.to_owned().into());
+  let text = "- 340282366920938463463374607431768211455 -0x1_0000000000_0000000000_0000000000_0000000000 \
+    0b1000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001 \
+    +000_170141183460469231731687303715884105728 170141183460469231731687303715884105727 1e400";
+  let document = Document::parse(text).unwrap();
+  let values: Vec<_> = document.nodes[0].entries.iter().map(|entry| &entry.value).collect();
+  assert_eq!(
+    values,
+    [
+      &big("340282366920938463463374607431768211455"),
+      &big("-1461501637330902918203684832716283019655932542976"),
+      &big("3213876088517980551083924184682325205044405987565585670602753"),
+      &big("170141183460469231731687303715884105728"),
+      &Value::Integer(i128::MAX),
+      &Value::Float(f64::INFINITY),
+    ]
+  );
+  // written canonically, so it's borrowed
+  assert!(matches!(values[0], Value::BigInteger(Cow::Borrowed(_))));
+  assert_eq!(
+    document.to_string(),
+    "- 340282366920938463463374607431768211455 -1461501637330902918203684832716283019655932542976 \
+     3213876088517980551083924184682325205044405987565585670602753 170141183460469231731687303715884105728 \
+     170141183460469231731687303715884105727 #inf"
+  );
+  // strict mode errors instead
+  let errors: Vec<_> = Parser::new("- 1 170141183460469231731687303715884105728")
+    .with_strict_integers(true)
+    .filter_map(Result::err)
+    .collect();
+  assert_eq!(
+    errors
+      .iter()
+      .map(|error| (error.kind(), error.offset()))
+      .collect::<Vec<_>>(),
+    [(ErrorKind::IntegerOverflow, Some(4))]
+  );
+  // conversions
+  assert_eq!(values[0].as_u128(), Some(u128::MAX));
+  assert_eq!(u128::try_from(values[0]).unwrap(), u128::MAX);
+  assert_eq!(values[0].as_i128(), None);
+  assert_eq!(values[1].as_f64(), Some(-(2.0_f64.powi(160))));
+  assert_eq!(
+    i128::try_from(values[3]).unwrap_err().message(),
+    "invalid value: integer `170141183460469231731687303715884105728`, expected i128"
+  );
+  assert_eq!(ToValue::to_value(&u128::MAX), *values[0]);
+  assert_eq!(ToValue::to_value(&1_u128), Value::Integer(1));
+  // ordering by numeric value
+  let mut sorted = vec![
+    big("-1461501637330902918203684832716283019655932542976"),
+    Value::Float(1e39),
+    big("170141183460469231731687303715884105728"),
+    Value::Integer(i128::MIN),
+    Value::Float(-1e50),
+    Value::Float(2.0_f64.powi(127)),
+    big("340282366920938463463374607431768211455"),
+    Value::Integer(i128::MAX),
+    Value::Float(f64::NAN),
+  ];
+  sorted.sort();
+  assert_eq!(
+    sorted,
+    [
+      Value::Float(-1e50),
+      big("-1461501637330902918203684832716283019655932542976"),
+      Value::Integer(i128::MIN),
+      Value::Integer(i128::MAX),
+      big("170141183460469231731687303715884105728"),
+      Value::Float(2.0_f64.powi(127)),
+      big("340282366920938463463374607431768211455"),
+      Value::Float(1e39),
+      Value::Float(f64::NAN),
+    ]
+  );
+}
+
+#[test]
+fn literal_round_trip() {
+  use crate::dom::Value;
+  use crate::stream::{write_stream_as, ChunkParser, LiteralKind, Version};
+  let text = "node 0xFF -0o17 0b1010 1.50 1e3 10_000 plain \"quoted\" #\"raw \"\"# ##\"hash\"#\"## #true #-inf\n\
+    parent {\n    child key=\"\"\"\n        multi\n          line\n        \"\"\" #\"\"\"\n        raw\n        \"\"\"#\n}";
+  let document = Document::parse_literals(text).unwrap();
+  assert_eq!(document, Document::parse(text).unwrap());
+  assert_eq!(document.to_string(), text);
+  let kinds: Vec<_> = document.nodes[0]
+    .entries
+    .iter()
+    .map(|entry| entry.literal().unwrap().kind)
+    .collect();
+  assert_eq!(
+    kinds,
+    [
+      LiteralKind::Integer { radix: 16 },
+      LiteralKind::Integer { radix: 8 },
+      LiteralKind::Integer { radix: 2 },
+      LiteralKind::Float,
+      LiteralKind::Float,
+      LiteralKind::Integer { radix: 10 },
+      LiteralKind::Identifier,
+      LiteralKind::Quoted { multi_line: false },
+      LiteralKind::Raw {
+        hashes: 1,
+        multi_line: false
+      },
+      LiteralKind::Raw {
+        hashes: 2,
+        multi_line: false
+      },
+      LiteralKind::Keyword,
+      LiteralKind::Keyword,
+    ]
+  );
+  let child = &document.nodes[1].children.as_ref().unwrap().nodes[0];
+  assert_eq!(
+    child.entries[0].literal().unwrap().kind,
+    LiteralKind::Quoted { multi_line: true }
+  );
+  assert_eq!(
+    child.entries[1].literal().unwrap().kind,
+    LiteralKind::Raw {
+      hashes: 1,
+      multi_line: true
+    }
+  );
+  // streaming keeps them too
+  let mut out = String::new();
+  write_stream(&mut out, Parser::new(text).literals().map(Result::unwrap)).unwrap();
+  assert_eq!(out, text);
+  let mut chunked = ChunkParser::new().literals();
+  chunked.get_mut().feed(text.as_bytes());
+  chunked.get_mut().finish();
+  let mut out = String::new();
+  write_stream(&mut out, chunked.map(Result::unwrap)).unwrap();
+  assert_eq!(out, text);
+  // changed values are written normally
+  let mut document = document;
+  document.nodes[0].entries[0].value = Value::Integer(256);
+  document.nodes[0].entries[3].value = Value::Float(1.5);
+  assert!(document.nodes[0].to_string().starts_with("node 256 -0o17 0b1010 1.50 "));
+  // only literals that are also valid in the other version are kept
+  document.nodes.truncate(1);
+  document.write_as(&mut String::new(), Version::V1).unwrap_err();
+  document.nodes[0].entries.pop();
+  let mut out = String::new();
+  document.write_as(&mut out, Version::V1).unwrap();
+  assert_eq!(
+    out,
+    "node 256 -0o17 0b1010 1.50 1e3 10_000 plain \"quoted\" r#\"raw \"\"# r##\"hash\"#\"## true"
+  );
+  let v1 = "node r##\"a\"#\"## true 0x1F";
+  let mut out = String::new();
+  write_stream_as(
+    &mut out,
+    Parser::new(v1).with_version(Version::V1).literals().map(Result::unwrap),
+    Version::V1,
+  )
+  .unwrap();
+  assert_eq!(out, v1);
+  let mut out = String::new();
+  write_stream(
+    &mut out,
+    Parser::new(v1).with_version(Version::V1).literals().map(Result::unwrap),
+  )
+  .unwrap();
+  assert_eq!(out, "node \"a\\\"#\" #true 0x1F");
+}
+
+#[test]
+fn reserved_annotations() {
+  use crate::annotation::{self, Base64, Date, DateTime, Duration, Reserved, Time, Url, Uuid};
+  use crate::dom::Value;
+  let valid = |name: &str, value: Value| Reserved::from_name(name).unwrap().check(&value).is_ok();
+  let strings = |name: &str, texts: &[&str]| texts.iter().map(|&text| valid(name, text.into())).collect::<Vec<_>>();
+  assert!(valid("u8", Value::Integer(255)) && !valid("u8", Value::Integer(256)) && !valid("u8", "1".into()));
+  assert!(valid("i8", Value::Integer(-128)) && !valid("u64", Value::Integer(-1)));
+  assert!(valid("f32", Value::Integer(1)) && !valid("f32", Value::Float(1e300)));
+  assert!(valid("decimal64", Value::Float(0.1)) && !valid("decimal128", "0.1".into()));
+  assert_eq!(
+    strings("date", &["2024-02-29", "2023-02-29", "2024-13-01", "24-01-01"]),
+    [true, false, false, false]
+  );
+  assert_eq!(
+    strings("time", &["23:59:60.25", "24:00:00", "12:00"]),
+    [true, false, false]
+  );
+  assert_eq!(
+    strings(
+      "date-time",
+      &[
+        "2024-02-29T13:45:00Z",
+        "2024-02-29 13:45:00.5-05:30",
+        "2024-02-29T13:45:00+1"
+      ]
+    ),
+    [true, true, false]
+  );
+  assert_eq!(
+    strings(
+      "duration",
+      &[
+        "P1Y2M3W4DT5H6M7.5S",
+        "PT0S",
+        "P1M",
+        "PT1M",
+        "P",
+        "P1DT",
+        "PT1.5M",
+        "P1D2Y"
+      ]
+    ),
+    [true, true, true, true, false, false, false, false]
+  );
+  assert_eq!(
+    strings("decimal", &["-1.50e+3", "1.", ".5", "1e"]),
+    [true, false, false, false]
+  );
+  assert_eq!(strings("currency", &["EUR", "eur"]), [true, false]);
+  assert_eq!(strings("country-2", &["NZ", "NZL"]), [true, false]);
+  assert_eq!(strings("country-3", &["NZL", "NZ"]), [true, false]);
+  assert_eq!(strings("country-subdivision", &["NZ-AUK", "NZ-"]), [true, false]);
+  assert_eq!(
+    strings(
+      "email",
+      &[
+        "a.b+c@example.com",
+        "a@[127.0.0.1]",
+        "a..b@example.com",
+        "ä@example.com"
+      ]
+    ),
+    [true, true, false, false]
+  );
+  assert_eq!(strings("idn-email", &["ä@exämple.com"]), [true]);
+  assert_eq!(
+    strings("hostname", &["example.com.", "-a.com", "ex_ample.com"]),
+    [true, false, false]
+  );
+  assert_eq!(strings("idn-hostname", &["exämple.com"]), [true]);
+  assert_eq!(strings("ipv4", &["10.0.0.1", "10.0.0.256"]), [true, false]);
+  assert_eq!(strings("ipv6", &["::1", "1::2::3"]), [true, false]);
+  assert_eq!(
+    strings("url", &["https://example.com/a%20b?q#f", "/relative", "a b:c", "x:%2"]),
+    [true, false, false, false]
+  );
+  assert_eq!(
+    strings("url-reference", &["../a?b", "a:b/c", "a/b:c"]),
+    [true, true, true]
+  );
+  assert_eq!(strings("irl", &["https://exämple.com"]), [true]);
+  assert_eq!(
+    strings("url-template", &["/users{/id}{?q,page*}", "/{a", "/{a b}"]),
+    [true, false, false]
+  );
+  assert_eq!(
+    strings(
+      "uuid",
+      &[
+        "0f8fad5b-d9cb-469f-a165-70867728950E",
+        "0f8fad5bd9cb469fa16570867728950e"
+      ]
+    ),
+    [true, false]
+  );
+  assert_eq!(
+    strings(
+      "regex",
+      &[r"^a(b|c)*?[^]\d]+$", "a(b", "a)", "*a", "[a", r"a\", "(?:a|+)"]
+    ),
+    [true, false, false, false, false, false, false]
+  );
+  assert_eq!(
+    strings("base64", &["", "a2Rs", "a2Q=", "aw==", "a2R=", "a2Rs=", "a2Q"]),
+    [true, true, true, true, false, false, false]
+  );
+  // unknown annotations are fine
+  assert_eq!(Reserved::from_name("color"), None);
+  assert_eq!(Reserved::from_name("date-time").map(Reserved::name), Some("date-time"));
+
+  let text = "a (u8)1 x=(u8)-1 (color)red\na (date)\"2024-01-01\" (date)tomorrow { b { c (uuid)1 } }";
+  let errors = annotation::validate(&Document::parse_spanned(text).unwrap());
+  let errors: Vec<_> = errors
+    .iter()
+    .map(|error| (error.to_string(), error.span().unwrap().start))
+    .collect();
+  assert_eq!(
+    errors,
+    [
+      ("a[0].x: invalid value: integer `-1`, expected u8".into(), 8),
+      ("a[1].1: invalid value: string \"tomorrow\", expected a date".into(), 49),
+      ("a[1] > b > c.0: invalid type: integer `1`, expected a UUID".into(), 72),
+    ]
+  );
+  let events: Vec<_> = annotation::validate_events(Parser::new(text))
+    .filter_map(Result::err)
+    .map(|error| error.to_string())
+    .collect();
+  assert_eq!(
+    events,
+    [
+      "a.x: invalid value: integer `-1`, expected u8",
+      "a.1: invalid value: string \"tomorrow\", expected a date",
+      "a > b > c.0: invalid type: integer `1`, expected a UUID",
+    ]
+  );
+
+  // decoding
+  let date_time: DateTime = "2024-02-29t13:45:07.250-05:30".parse().unwrap();
+  assert_eq!(
+    date_time,
+    DateTime {
+      date: Date {
+        year: 2024,
+        month: 2,
+        day: 29
+      },
+      time: Time {
+        hour: 13,
+        minute: 45,
+        second: 7,
+        nanosecond: 250_000_000
+      },
+      offset: Some(-330),
+    }
+  );
+  assert_eq!(date_time.to_string(), "2024-02-29T13:45:07.25-05:30");
+  assert_eq!("2024-02-29T00:00:00".parse::<DateTime>().unwrap().offset, None);
+  assert_eq!(
+    "2024-02-30".parse::<Date>().unwrap_err().to_string(),
+    "invalid value: string \"2024-02-30\", expected a date"
+  );
+  let duration: Duration = "P1Y2M3W4DT5H6M7,5S".parse().unwrap();
+  assert_eq!(
+    duration,
+    Duration {
+      years: 1,
+      months: 2,
+      weeks: 3,
+      days: 4,
+      hours: 5,
+      minutes: 6,
+      seconds: 7,
+      nanoseconds: 500_000_000
+    }
+  );
+  assert_eq!(duration.to_string(), "P1Y2M3W4DT5H6M7.5S");
+  for text in ["PT0S", "P1D", "PT1M", "P1DT1H", "PT0.001S"] {
+    assert_eq!(text.parse::<Duration>().unwrap().to_string(), text);
+  }
+  let uuid: Uuid = "0F8FAD5B-D9CB-469F-A165-70867728950E".parse().unwrap();
+  assert_eq!(uuid, Uuid(0x0f8fad5b_d9cb_469f_a165_70867728950e));
+  assert_eq!(uuid.to_string(), "0f8fad5b-d9cb-469f-a165-70867728950e");
+  for bytes in [&b""[..], b"k", b"kd", b"kdl", b"kdl!", &[0xFF, 0xFE, 0x00, 0x01]] {
+    let text = Base64(bytes.to_vec()).to_string();
+    assert_eq!(text.parse::<Base64>().unwrap().0, bytes, "{text}");
+  }
+  assert_eq!(Base64(b"kdl!".to_vec()).to_string(), "a2RsIQ==");
+  let url = Url::parse("https://user@example.com:8080/a/b?q=1#top").unwrap();
+  assert_eq!(
+    url,
+    Url {
+      scheme: Some("https"),
+      authority: Some("user@example.com:8080"),
+      path: "/a/b",
+      query: Some("q=1"),
+      fragment: Some("top"),
+    }
+  );
+  assert_eq!(url.to_string(), "https://user@example.com:8080/a/b?q=1#top");
+  assert_eq!(Url::parse_reference("a/b").unwrap().path, "a/b");
+  assert!(Url::parse("a/b").is_err());
+  let document =
+    Document::parse("server (ipv6)\"::1\" (ipv4)\"127.0.0.1\" started=(date-time)\"2024-01-01T00:00:00Z\" id=1")
+      .unwrap();
+  let server = &document.nodes[0];
+  assert_eq!(
+    server.arg::<std::net::Ipv6Addr>(0).unwrap(),
+    std::net::Ipv6Addr::LOCALHOST
+  );
+  assert_eq!(
+    server.arg::<std::net::Ipv4Addr>(1).unwrap(),
+    std::net::Ipv4Addr::LOCALHOST
+  );
+  assert_eq!(server.get::<DateTime>("started").unwrap().offset, Some(0));
+  assert_eq!(
+    server.get::<Url>("id").unwrap_err().to_string(),
+    "id: invalid type: integer `1`, expected an IRL reference"
+  );
+}
+
+#[test]
+fn annotation_handlers() {
+  use crate::annotation::Handlers;
+  use crate::convert::{invalid_type, Error};
+  use crate::dom::Value;
+  let handlers = Handlers::new()
+    .with("secret", |value: &mut Value| match value {
+      Value::String(text) if text.starts_with("env:") => {
+        *value = Value::from(format!("<{}>", &text[4..]));
+        Ok(())
+      }
+      _ => Err(Error::new("secrets have to come from the environment")),
+    })
+    .with("duration", |value: &mut Value| {
+      let text = value.as_str().ok_or_else(|| invalid_type(value, "minutes"))?;
+      let minutes = text.strip_suffix('m').and_then(|number| number.parse::<i128>().ok());
+      *value = Value::Integer(minutes.ok_or_else(|| Error::new("expected minutes"))? * 60);
+      Ok(())
+    });
+  assert!(handlers.contains("secret") && !handlers.contains("u8"));
+  let mut document = handlers
+    .parse(r#"db password=(secret)"env:DB_PASSWORD" timeout=(duration)"5m" (u8)300 (ipv4)"x""#)
+    .unwrap();
+  let db = &document.nodes[0];
+  assert_eq!(db.get::<&str>("password").unwrap(), "<DB_PASSWORD>");
+  assert_eq!(db.get::<i64>("timeout").unwrap(), 300);
+  assert_eq!(db.entry("timeout").unwrap().type_hint(), Some("duration"));
+  assert_eq!(
+    db.to_string(),
+    "db password=(secret)<DB_PASSWORD> timeout=(duration)300 (u8)300 (ipv4)x"
+  );
+
+  // handled values aren't handled twice, and reserved annotations are opt-in
+  let handlers = handlers.with_reserved(true);
+  let errors = handlers.handle_document(&mut document);
+  let errors: Vec<_> = errors.iter().map(|error| error.to_string()).collect();
+  assert_eq!(
+    errors,
+    [
+      "db.password: secrets have to come from the environment",
+      "db.timeout: invalid type: integer `300`, expected minutes",
+      "db.0: invalid value: integer `300`, expected u8",
+      "db.1: invalid value: string \"x\", expected an IPv4 address",
+    ]
+  );
+
+  let text = "a { b x=(duration)\"1h\" }\nb (secret)\"env:A\"";
+  let error = handlers.parse(text).unwrap_err();
+  assert_eq!(error.to_string(), "a > b.x: expected minutes");
+  assert_eq!(
+    &text[error.span().unwrap().start..error.span().unwrap().end],
+    "x=(duration)\"1h\""
+  );
+  let events: Vec<_> = handlers.handle_events(Parser::new(text)).collect();
+  assert_eq!(events[3].as_ref().unwrap_err().to_string(), "a > b.x: expected minutes");
+  assert!(matches!(
+    &events[6],
+    Ok(crate::stream::Event::Entry { value: Value::String(text), .. }) if text == "<A>"
+  ));
+
+  #[cfg(feature = "serde")]
+  {
+    #[derive(serde::Deserialize)]
+    struct Config {
+      timeout: u64,
+    }
+    let config: Config = handlers.from_str("timeout (duration)\"2m\"").unwrap();
+    assert_eq!(config.timeout, 120);
+  }
+}
+
+#[test]
+fn queries() {
+  use crate::dom::IndexPath;
+  use crate::query::Query;
+  let document = Document::parse(
+    r#"
+    http {
+      (tls)listen 443 cert="a.pem"
+      listen 80
+      route "/api" { proxy "http://10.0.0.1" timeout=30 }
+      route "/static" { files "/srv" }
+    }
+    mail { listen 25; (tls)listen 465 }
+    "#,
+  )
+  .unwrap();
+  let names = |query: &str| -> Vec<String> {
+    let query = Query::parse(query).unwrap();
+    let found = query.select(&document);
+    assert_eq!(found.len(), query.select_paths(&document).len());
+    found.iter().map(|node| node.to_string()).collect()
+  };
+  assert_eq!(names("top()").len(), 2);
+  assert_eq!(names("top() > mail"), names("mail"));
+  assert_eq!(names("http > listen").len(), 2);
+  assert_eq!(names("listen").len(), 4);
+  assert_eq!(names("(tls)listen"), ["(tls)listen 443 cert=a.pem", "(tls)listen 465"]);
+  assert_eq!(names("()[val() > 100]"), names("(tls)"));
+  assert_eq!(names("listen[val(0) < 100]"), ["listen 80", "listen 25"]);
+  assert_eq!(names("[cert $= \".pem\"]"), ["(tls)listen 443 cert=a.pem"]);
+  assert_eq!(names("[prop(cert)]"), names("[cert]"));
+  assert_eq!(
+    names("http >> proxy[timeout=30]"),
+    ["proxy \"http://10.0.0.1\" timeout=30"]
+  );
+  assert_eq!(names("route[val() ^= \"/s\"] > []"), ["files \"/srv\""]);
+  assert_eq!(names("top() >> [val() *= \"10.0\"]").len(), 1);
+  assert_eq!(names("listen + route"), names("route[val() = \"/api\"]"));
+  assert_eq!(names("(tls)listen ~ route").len(), 2);
+  assert_eq!(names("listen ~ (tls)listen"), ["(tls)listen 465"]);
+  assert_eq!(names("[name() = mail] || [tag() = tls]").len(), 3);
+  assert_eq!(names("top() > listen"), Vec::<String>::new());
+  assert_eq!(names("listen[val() = \"80\"]"), Vec::<String>::new());
+  assert_eq!(names("listen[val() != 80]").len(), 3);
+  assert_eq!(names("listen[val() = (tls)]"), Vec::<String>::new());
+
+  let query: Query = "route > files".parse().unwrap();
+  let path = IndexPath {
+    nodes: vec![0, 3, 0],
+    entry: None,
+  };
+  assert!(query.matches(&document, &path));
+  assert_eq!(query.select_paths(&document), [path]);
+  assert!(!query.matches(&document, &IndexPath::default()));
+
+  let error = |query: &str| Query::parse(query).unwrap_err().to_string();
+  assert_eq!(error(""), "expected a filter at byte 0");
+  assert_eq!(error("a > top()"), "`top()` has to come first at byte 4");
+  assert_eq!(error("a[val(-1)]"), "expected an argument index at byte 6");
+  assert_eq!(error("a[b 1]"), "expected `]` or a comparison at byte 4");
+  assert_eq!(error("a b"), "expected `||` or a combinator at byte 2");
+  assert_eq!(error("a[b = ]").split(" at ").nth(1), Some("byte 6"));
+}
+
+#[test]
+fn query_deep_document() {
+  use crate::query::Query;
+  // every `>>` and `~` has many ways to match here, which would take ages
+  // to try one by one
+  let text = format!("{}b{}", "a {".repeat(40), "}".repeat(40));
+  let document = Document::parse(&text).unwrap();
+  let query = Query::parse("a >> a >> a >> a >> a >> a >> a >> a >> b").unwrap();
+  let paths = query.select_paths(&document);
+  assert_eq!(paths.len(), 1);
+  assert_eq!(paths[0].nodes.len(), 41);
+  assert!(query.matches(&document, &paths[0]));
+  let query = Query::parse("q >> a >> a >> a >> a >> a >> a >> a >> b").unwrap();
+  assert!(query.select(&document).is_empty());
+  assert!(!query.matches(&document, &paths[0]));
+
+  let text = format!("{}b {}", "a; ".repeat(40), "c; ".repeat(40));
+  let document = Document::parse(&text).unwrap();
+  assert_eq!(
+    Query::parse("a ~ a ~ a ~ a ~ a ~ a ~ a ~ a ~ b")
+      .unwrap()
+      .select(&document)
+      .len(),
+    1
+  );
+  assert!(Query::parse("q ~ a ~ a ~ a ~ a ~ a ~ a ~ a ~ c")
+    .unwrap()
+    .select(&document)
+    .is_empty());
+}
+
+#[test]
+fn query_extract() {
+  use crate::query::Query;
+  use crate::stream::Event;
+  let text = r#"
+    plugins {
+      plugin name="log" { level "debug" }
+      plugin name="auth" { key "k"; plugin name="nested" }
+      (disabled)plugin name="cache"
+    }
+    server { plugin name="auth" }
+    plugin name="auth"
+  "#;
+  let document = Document::parse(text).unwrap();
+  let extracted = |query: &str| -> Vec<String> {
+    let query = Query::parse(query).unwrap();
+    let nodes: Vec<_> = query.extract(Parser::new(text)).nodes().map(Result::unwrap).collect();
+    nodes.iter().map(|node| node.to_string()).collect()
+  };
+  let selected = |query: &str| -> Vec<String> {
+    let query = Query::parse(query).unwrap();
+    query.select(&document).iter().map(|node| node.to_string()).collect()
+  };
+  for query in [
+    "plugins > plugin[name=\"auth\"]",
+    "plugin[name=auth]",
+    "top() > plugin",
+    "top() >> [level]",
+    "plugin + ()plugin",
+    "[name=log] ~ plugin",
+    "plugins ~ [] > plugin || top() > plugins > (disabled)",
+    "top()",
+    "missing",
+  ] {
+    assert_eq!(extracted(query), selected(query), "{query}");
+  }
+  // a match inside a match is only part of the outer one
+  assert_eq!(selected("plugin[name]").len(), 6);
+  assert_eq!(extracted("plugin[name]").len(), 5);
+
+  let query = Query::parse("plugins > plugin[name=log]").unwrap();
+  let events: Vec<_> = query.extract(Parser::new(text)).map(Result::unwrap).collect();
+  assert_eq!(events.len(), 6);
+  assert!(matches!(&events[0], Event::Node { name, .. } if name == "plugin"));
+  assert!(matches!(events[2], Event::Begin) && matches!(events[5], Event::End));
+
+  let mut events = query.extract(Parser::new("plugins { plugin name=log }\n}"));
+  assert_eq!(events.by_ref().filter(Result::is_ok).count(), 2);
+  assert!(events.next().is_none());
+  assert!(query.extract(Parser::new("plugins {")).any(|event| event.is_err()));
+}
+
+#[test]
+fn value_paths() {
+  use crate::dom::Value;
+  use crate::path::{Key, Path};
+  let path = |text: &str| text.parse::<Path>().unwrap();
+  let mut document = Document::parse(
+    r#"
+    upstream "a"
+    upstream "b" weight=2
+    upstream "c" "d" { check interval=5 interval=10 }
+    "#,
+  )
+  .unwrap();
+  assert_eq!(document.get_path(&path("upstream[2].args[1]")), Some(&Value::from("d")));
+  assert_eq!(
+    document.get_path(&path("upstream[2].check.interval")),
+    Some(&Value::Integer(10))
+  );
+  assert_eq!(document.get_path(&path("upstream.weight")), None);
+  assert_eq!(document.get_path(&path("upstream[3].args[0]")), None);
+
+  assert_eq!(
+    document.set_path(&path("upstream[1].weight"), Value::Integer(3)),
+    Some(Value::Integer(2))
+  );
+  assert_eq!(document.set_path(&path("upstream[4].args[1]"), Value::Bool(true)), None);
+  assert_eq!(
+    document.set_path(&path("server.listen.\"tcp.port\""), Value::Integer(80)),
+    None
+  );
+  assert_eq!(
+    document.remove_path(&path("upstream[2].check.interval")),
+    Some(Value::Integer(10))
+  );
+  assert_eq!(
+    document.remove_path(&path("upstream[2].args[0]")),
+    Some(Value::from("c"))
+  );
+  assert_eq!(document.remove_path(&path("upstream[2].args[1]")), None);
+  assert_eq!(
+    document.to_string(),
+    "upstream a\nupstream b weight=3\nupstream d {\n    check\n}\nupstream\nupstream #null #true\nserver {\n    listen tcp.port=80\n}"
+  );
+
+  let flat = document.flatten();
+  let paths: Vec<_> = flat.iter().map(|(path, _)| path.to_string()).collect();
+  assert_eq!(
+    paths,
+    [
+      "upstream.args[0]",
+      "upstream[1].args[0]",
+      "upstream[1].weight",
+      "upstream[2].args[0]",
+      "upstream[4].args[0]",
+      "upstream[4].args[1]",
+      "server.listen.\"tcp.port\"",
+    ]
+  );
+  for (flat_path, _) in &flat {
+    assert_eq!(&path(&flat_path.to_string()), flat_path);
+  }
+  // `check` and the empty `upstream` have nothing in them, the empty one is
+  // still created to keep the indices
+  let rebuilt = Document::unflatten(flat);
+  assert_eq!(
+    rebuilt.to_string(),
+    "upstream a\nupstream b weight=3\nupstream d\nupstream\nupstream #null #true\nserver {\n    listen tcp.port=80\n}"
+  );
+
+  assert_eq!(path("a.\"args\""), Path::new(["a"], Key::Property("args".into())));
+  assert_eq!(Path::new(["a b"], Key::Argument(0)).to_string(), "\"a b\".args[0]");
+  let error = |text: &str| text.parse::<Path>().unwrap_err().to_string();
+  assert_eq!(error("port"), "expected a node before the entry at byte 4");
+  assert_eq!(error("a..b"), "expected a name at byte 2");
+  assert_eq!(error("a[x].b"), "expected an index at byte 2");
+  assert_eq!(error("a.b[1]"), "only `args` can have an index at the end at byte 6");
+  assert_eq!(error("a.\"b"), "unexpected end of file at byte 4");
+}
+
+#[test]
+fn visitors() {
+  use crate::dom::{Entry, Node, Value};
+  use crate::visit::{Context, Edit, Flow, Visit, VisitMut};
+  struct Trace(Vec<String>);
+  impl<'a, 'text> Visit<'a, 'text> for Trace {
+    fn enter_node(&mut self, node: &'a Node<'text>, cx: Context) -> Flow {
+      self.0.push(format!("{:?} {}", cx.path().nodes, node.name()));
+      match node.name() {
+        "skip" => Flow::Skip,
+        _ => Flow::Continue,
+      }
+    }
+    fn leave_node(&mut self, node: &'a Node<'text>, cx: Context) -> Flow {
+      self.0.push(format!("/{} at {}", node.name(), cx.depth()));
+      match node.name() {
+        "stop" => Flow::Stop,
+        _ => Flow::Continue,
+      }
+    }
+    fn entry(&mut self, entry: &'a Entry<'text>, cx: Context) -> Flow {
+      self
+        .0
+        .push(format!("{}.{}={}", cx.index(), cx.path().entry.unwrap(), entry.value));
+      match entry.value.as_str() {
+        Some("rest") => Flow::Skip,
+        _ => Flow::Continue,
+      }
+    }
+  }
+  let document = Document::parse("a 1 rest 2 { skip 3 { b } ; c }; stop { d }; e").unwrap();
+  let mut trace = Trace(Vec::new());
+  document.visit(&mut trace);
+  assert_eq!(
+    trace.0,
+    [
+      "[0] a",
+      "0.0=1",
+      "0.1=rest",
+      "[0, 0] skip",
+      "/skip at 1",
+      "[0, 1] c",
+      "/c at 1",
+      "/a at 0",
+      "[1] stop",
+      "[1, 0] d",
+      "/d at 1",
+      "/stop at 0",
+    ]
+  );
+
+  struct Edits;
+  impl<'text> VisitMut<'text> for Edits {
+    fn enter_node(&mut self, node: &mut Node<'text>, _: Context) -> Edit<'text> {
+      match node.name() {
+        "old" => Edit::Replace(vec![Node::new("new"), Node::new("newer")]),
+        "gone" => Edit::Remove,
+        "mid" => Edit::InsertBefore(vec![Node::new("before")]),
+        "end" => Edit::InsertAfter(vec![Node::new("after")]),
+        "skip" => Edit::Skip,
+        _ => Edit::Continue,
+      }
+    }
+    fn leave_node(&mut self, node: &mut Node<'text>, _: Context) -> Edit<'text> {
+      if node.name() == "skip" {
+        node.set_name("left");
+      }
+      match node.children.as_ref() {
+        Some(children) if children.nodes.is_empty() => Edit::Remove,
+        _ => Edit::Continue,
+      }
+    }
+    fn entry(&mut self, entry: &mut Entry<'text>, _: Context) -> Flow {
+      if let Value::Integer(number) = &mut entry.value {
+        *number += 1;
+      }
+      Flow::Continue
+    }
+  }
+  let mut document =
+    Document::parse("x { old 1; gone { y } }; mid 1 { end 2 }; empty { gone }; skip 1 { gone }").unwrap();
+  document.visit_mut(&mut Edits);
+  // skipped nodes are still left
+  assert_eq!(
+    document.to_string(),
+    "x {\n    new\n    newer\n}\nbefore\nmid 2 {\n    end 3\n    after\n}\nleft 1 {\n    gone\n}"
+  );
+
+  let mut document = Document::parse("a 1 { secret 2 { b 3 } }; secret; c 4 \"x\"").unwrap();
+  document.retain_recursive(|node| node.name() != "secret");
+  document.map_values(|value| match value {
+    Value::Integer(number) => Value::Integer(number * 10),
+    value => value,
+  });
+  assert_eq!(document.to_string(), "a 10 {\n\n}\nc 40 x");
+}
+
+#[test]
+fn cursors() {
+  use crate::dom::{IndexPath, Node};
+  use crate::query::Query;
+  let mut document = Document::parse("a { b; c { d }; e }; f").unwrap();
+  let path = Query::parse("d").unwrap().select_paths(&document).remove(0);
+  let mut cursor = document.cursor_at(&path).unwrap();
+  assert_eq!((cursor.node().name(), cursor.depth()), ("d", 2));
+  assert_eq!(
+    cursor.ancestors().map(|node| node.name()).collect::<Vec<_>>(),
+    ["c", "a"]
+  );
+  assert!(!cursor.next_sibling() && !cursor.prev_sibling() && !cursor.first_child());
+  assert!(cursor.parent() && cursor.prev_sibling());
+  assert_eq!(cursor.node().name(), "b");
+  assert!(!cursor.prev_sibling() && cursor.next_sibling() && cursor.next_sibling());
+  assert_eq!(cursor.path().nodes, [0, 2]);
+  assert!(cursor.parent() && !cursor.parent() && cursor.next_sibling() && !cursor.next_sibling());
+  assert_eq!(cursor.node().name(), "f");
+  assert!(document.cursor_at(&IndexPath::default()).is_none());
+  assert!(document
+    .cursor_at(&IndexPath {
+      nodes: vec![0, 5],
+      entry: None
+    })
+    .is_none());
+
+  let mut cursor = document.cursor_mut().unwrap();
+  assert!(cursor.first_child() && cursor.next_sibling());
+  cursor.insert_before(Node::new("before"));
+  cursor.insert_after(Node::new("after"));
+  assert_eq!(cursor.node().name(), "c");
+  assert_eq!(cursor.as_cursor().path().nodes, [0, 2]);
+  cursor.push_child(Node::new("d2"));
+  cursor.node_mut().set_name("c2");
+  assert!(cursor.first_child());
+  assert_eq!(cursor.replace(Node::new("d1")).name(), "d");
+  assert_eq!(cursor.remove().unwrap().name(), "d1");
+  assert_eq!(cursor.node().name(), "d2");
+  assert_eq!(cursor.remove().unwrap().name(), "d2");
+  assert_eq!((cursor.node().name(), cursor.depth()), ("c2", 1));
+  assert!(cursor.next_sibling() && cursor.next_sibling());
+  assert_eq!(cursor.remove().unwrap().name(), "e");
+  assert_eq!(cursor.node().name(), "after");
+  assert!(cursor.parent() && cursor.next_sibling());
+  assert_eq!(cursor.remove().unwrap().name(), "f");
+  assert!(cursor.remove().is_none());
+  assert_eq!(
+    document.to_string(),
+    "a {\n    b\n    before\n    c2 {\n\n    }\n    after\n}"
+  );
+}
@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//! Walking every node and entry of a document, see [`Visit`] and [`VisitMut`]
+//!
+//! Nodes are visited in document order: [`enter_node`](Visit::enter_node),
+//! then each entry, then the children, then
+//! [`leave_node`](Visit::leave_node). Every hook has a default that does
+//! nothing, so visitors only implement the ones they need:
+//!
+//! ```
+//! use kdlite::dom::{Document, Node};
+//! use kdlite::visit::{Context, Flow, Visit};
+//!
+//! /// Names of nodes up to a depth
+//! struct Outline(Vec<String>);
+//! impl<'a, 'text> Visit<'a, 'text> for Outline {
+//!   fn enter_node(&mut self, node: &'a Node<'text>, cx: Context) -> Flow {
+//!     self.0.push(format!("{}{}", "  ".repeat(cx.depth()), node.name()));
+//!     if cx.depth() == 1 { Flow::Skip } else { Flow::Continue }
+//!   }
+//! }
+//!
+//! let document = Document::parse("a { b { c }; d }; e").unwrap();
+//! let mut outline = Outline(Vec::new());
+//! document.visit(&mut outline);
+//! assert_eq!(outline.0, ["a", "  b", "  d", "e"]);
+//! ```
+//!
+//! A [`VisitMut`] can also change the tree as it goes, with an [`Edit`].
+//! [`Document::retain_recursive`] and [`Document::map_values`] cover the
+//! common cases.
+//!
+//! [`Document::retain_recursive`]: crate::dom::Document::retain_recursive
+//! [`Document::map_values`]: crate::dom::Document::map_values
+
+use crate::dom::{Document, Entry, IndexPath, Node};
+
+/// Where a hook is in the document
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'p> {
+  path: &'p IndexPath,
+}
+
+impl Context<'_> {
+  /// Position of the current node, and the entry in entry hooks
+  pub fn path(&self) -> &IndexPath {
+    self.path
+  }
+  /// Number of nodes around the current one, 0 at the top level
+  pub fn depth(&self) -> usize {
+    self.path.nodes.len() - 1
+  }
+  /// Index of the current node among its siblings
+  pub fn index(&self) -> usize {
+    self.path.nodes[self.depth()]
+  }
+}
+
+/// What to do after a hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Flow {
+  /// Keep going
+  #[default]
+  Continue,
+  /// Skip the rest of the node: its entries and children after
+  /// `enter_node`, still calling `leave_node`, or its other entries after
+  /// an entry. Same as [`Continue`](Self::Continue) after `leave_node`.
+  Skip,
+  /// Stop the whole walk
+  Stop,
+}
+
+/// A visitor of a document, see the [module docs](self)
+pub trait Visit<'a, 'text> {
+  /// Called for a node before its entries and children
+  fn enter_node(&mut self, node: &'a Node<'text>, cx: Context) -> Flow {
+    let _ = (node, cx);
+    Flow::Continue
+  }
+  /// Called for a node after its entries and children
+  fn leave_node(&mut self, node: &'a Node<'text>, cx: Context) -> Flow {
+    let _ = (node, cx);
+    Flow::Continue
+  }
+  /// Called for each entry of a node
+  fn entry(&mut self, entry: &'a Entry<'text>, cx: Context) -> Flow {
+    let _ = (entry, cx);
+    Flow::Continue
+  }
+}
+
+/// What to do after a node hook of a [`VisitMut`]
+///
+/// Nodes that are inserted or put in place of another aren't visited.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Edit<'text> {
+  /// Keep going
+  #[default]
+  Continue,
+  /// Skip the entries and children of the node, still calling
+  /// `leave_node`, see [`Flow::Skip`]
+  Skip,
+  /// Stop the whole walk
+  Stop,
+  /// Remove the node, along with its children
+  Remove,
+  /// Replace the node with any number of nodes
+  Replace(Vec<Node<'text>>),
+  /// Insert nodes before this one, and keep going
+  InsertBefore(Vec<Node<'text>>),
+  /// Insert nodes after this one, and keep going
+  InsertAfter(Vec<Node<'text>>),
+}
+
+/// A visitor that can change a document, see the [module docs](self)
+pub trait VisitMut<'text> {
+  /// Called for a node before its entries and children
+  fn enter_node(&mut self, node: &mut Node<'text>, cx: Context) -> Edit<'text> {
+    let _ = (node, cx);
+    Edit::Continue
+  }
+  /// Called for a node after its entries and children
+  fn leave_node(&mut self, node: &mut Node<'text>, cx: Context) -> Edit<'text> {
+    let _ = (node, cx);
+    Edit::Continue
+  }
+  /// Called for each entry of a node
+  fn entry(&mut self, entry: &mut Entry<'text>, cx: Context) -> Flow {
+    let _ = (entry, cx);
+    Flow::Continue
+  }
+}
+
+/// Visit the nodes of a document, returning whether the walk was stopped
+pub(crate) fn walk<'a, 'text>(
+  document: &'a Document<'text>,
+  visitor: &mut impl Visit<'a, 'text>,
+  path: &mut IndexPath,
+) -> bool {
+  for (index, node) in document.nodes.iter().enumerate() {
+    path.nodes.push(index);
+    path.entry = None;
+    let stopped = walk_node(node, visitor, path);
+    path.nodes.pop();
+    if stopped {
+      return true;
+    }
+  }
+  false
+}
+
+fn walk_node<'a, 'text>(node: &'a Node<'text>, visitor: &mut impl Visit<'a, 'text>, path: &mut IndexPath) -> bool {
+  let skip = match visitor.enter_node(node, Context { path }) {
+    Flow::Continue => false,
+    Flow::Skip => true,
+    Flow::Stop => return true,
+  };
+  if !skip {
+    for (index, entry) in node.entries.iter().enumerate() {
+      path.entry = Some(index);
+      match visitor.entry(entry, Context { path }) {
+        Flow::Continue => {}
+        Flow::Skip => break,
+        Flow::Stop => return true,
+      }
+    }
+    path.entry = None;
+    if let Some(children) = &node.children {
+      if walk(children, visitor, path) {
+        return true;
+      }
+    }
+  }
+  visitor.leave_node(node, Context { path }) == Flow::Stop
+}
+
+/// Visit and change the nodes of a document, returning whether the walk was
+/// stopped
+pub(crate) fn walk_mut<'text>(
+  document: &mut Document<'text>,
+  visitor: &mut impl VisitMut<'text>,
+  path: &mut IndexPath,
+) -> bool {
+  let mut index = 0;
+  while index < document.nodes.len() {
+    path.nodes.push(index);
+    path.entry = None;
+    let (next, stopped) = walk_node_mut(document, index, visitor, path);
+    path.nodes.pop();
+    if stopped {
+      return true;
+    }
+    index = next;
+  }
+  false
+}
+
+/// Visit the node at an index, returning the index after it and whether the
+/// walk was stopped
+fn walk_node_mut<'text>(
+  document: &mut Document<'text>,
+  mut index: usize,
+  visitor: &mut impl VisitMut<'text>,
+  path: &mut IndexPath,
+) -> (usize, bool) {
+  // nodes to insert after this one once it's done
+  let mut after = Vec::new();
+  let mut skip = false;
+  match visitor.enter_node(&mut document.nodes[index], Context { path }) {
+    Edit::Continue => {}
+    Edit::Skip => skip = true,
+    Edit::Stop => return (index + 1, true),
+    Edit::Remove => return replace(document, index, Vec::new(), after, false),
+    Edit::Replace(nodes) => return replace(document, index, nodes, after, false),
+    Edit::InsertBefore(nodes) => {
+      index = insert_before(document, index, nodes);
+      *path.nodes.last_mut().unwrap() = index;
+    }
+    Edit::InsertAfter(nodes) => after = nodes,
+  }
+  let mut stopped = false;
+  if !skip {
+    let node = &mut document.nodes[index];
+    for entry in 0..node.entries.len() {
+      path.entry = Some(entry);
+      match visitor.entry(&mut node.entries[entry], Context { path }) {
+        Flow::Continue => {}
+        Flow::Skip => break,
+        Flow::Stop => {
+          stopped = true;
+          break;
+        }
+      }
+    }
+    path.entry = None;
+    if let (false, Some(children)) = (stopped, &mut node.children) {
+      stopped = walk_mut(children, visitor, path);
+    }
+  }
+  if !stopped {
+    match visitor.leave_node(&mut document.nodes[index], Context { path }) {
+      Edit::Continue | Edit::Skip => {}
+      Edit::Stop => stopped = true,
+      Edit::Remove => return replace(document, index, Vec::new(), after, false),
+      Edit::Replace(nodes) => return replace(document, index, nodes, after, false),
+      Edit::InsertBefore(nodes) => index = insert_before(document, index, nodes),
+      Edit::InsertAfter(nodes) => after.extend(nodes),
+    }
+  }
+  let next = index + 1 + after.len();
+  document.nodes.splice(index + 1..index + 1, after);
+  (next, stopped)
+}
+
+/// Put nodes in place of the one at an index, followed by `after`
+fn replace<'text>(
+  document: &mut Document<'text>,
+  index: usize,
+  nodes: Vec<Node<'text>>,
+  after: Vec<Node<'text>>,
+  stopped: bool,
+) -> (usize, bool) {
+  let next = index + nodes.len() + after.len();
+  document.nodes.splice(index..=index, nodes.into_iter().chain(after));
+  (next, stopped)
+}
+
+/// Insert nodes before an index, returning where the node there is now
+fn insert_before<'text>(document: &mut Document<'text>, index: usize, nodes: Vec<Node<'text>>) -> usize {
+  let next = index + nodes.len();
+  document.nodes.splice(index..index, nodes);
+  next
+}